@@ -7,6 +7,8 @@ const VERSION: &str = "0.1.0";
 pub struct Args {
     pub network: String,
     pub resolver: bool,
+    pub indexer: String,
+    pub skip_preflight: bool,
     pub get_current_network: bool,
     pub get_sync_status: bool,
     pub get_info: bool,
@@ -22,6 +24,28 @@ pub struct Args {
     pub get_mempool_entry: String,
     pub get_mempool_entries: bool,
     pub transfer_krc20_tokens: String,
+    pub batch_transfer_krc20_tokens: String,
+    pub mint_krc20_tokens: String,
+    pub mint_farm: String,
+    pub sweep_krc20_tokens: String,
+    pub deploy_krc20_token: String,
+    pub deploy_issue_krc20_token: String,
+    pub krc20_owner_op: String,
+    pub deploy_krc721_collection: String,
+    pub mint_krc721_tokens: String,
+    pub transfer_krc721_token: String,
+    pub inscribe_data: String,
+    pub recover: String,
+    pub list_krc20_tokens: String,
+    pub cancel_krc20_listing: String,
+    pub buy_krc20_listing: String,
+    pub decode_transactions: String,
+    pub local_index: String,
+    pub get_krc20_balances: String,
+    pub get_krc20_token_info: String,
+    pub holder_snapshot: String,
+    pub op_history: String,
+    pub op_history_output: String,
     pub build_wallet: bool,
     pub create_addrs: String,
     pub single_tx: String,
@@ -49,6 +73,20 @@ impl Args {
                     .help("Use resolver instead of local node".to_string())
                     .action(ArgAction::SetTrue),
             )
+            .arg(
+                Arg::new("indexer")
+                    .long("indexer")
+                    .required(false)
+                    .help("KRC-20 indexer base URL. Defaults to the public Kasplex indexer for the network".to_string())
+                    .value_name("URL")
+            )
+            .arg(
+                Arg::new("skip-preflight")
+                    .long("skip-preflight")
+                    .required(false)
+                    .help("Skip checking KRC-20 operations against the indexer before broadcasting".to_string())
+                    .action(ArgAction::SetTrue),
+            )
             .arg(
                 Arg::new("local-grpc")
                     .long("l")
@@ -161,6 +199,160 @@ impl Args {
                     .help("Transfer KRC-20 tokens. Must provide a path to a file that has the transaction info".to_string())
                     .value_name("FILE")
             )
+            .arg(
+                Arg::new("batch-transfer-krc20-tokens")
+                    .long("batchtxkrc20")
+                    .required(false)
+                    .help("Transfer KRC-20 tokens to many recipients from a single commit transaction. Must provide a path to a file that has the batch info".to_string())
+                    .value_name("FILE")
+            )
+            .arg(
+                Arg::new("mint-krc20-tokens")
+                    .long("mintkrc20")
+                    .required(false)
+                    .help("Mint KRC-20 tokens N times. Must provide a path to a file that has the mint info".to_string())
+                    .value_name("FILE")
+            )
+            .arg(
+                Arg::new("mint-farm")
+                    .long("mintfarm")
+                    .required(false)
+                    .help("Mint KRC-20 tokens from many child wallets concurrently and consolidate them to one address. Must provide a path to a file that has the mint farm info".to_string())
+                    .value_name("FILE")
+            )
+            .arg(
+                Arg::new("sweep-krc20-tokens")
+                    .long("sweepkrc20")
+                    .required(false)
+                    .help("Transfer the KRC-20 balances of many child wallets to one address. Must provide a path to a file that has the sweep info".to_string())
+                    .value_name("FILE")
+            )
+            .arg(
+                Arg::new("deploy-krc20-token")
+                    .long("deploykrc20")
+                    .required(false)
+                    .help("Deploy a KRC-20 token. Must provide a path to a file that has the deploy info".to_string())
+                    .value_name("FILE")
+            )
+            .arg(
+                Arg::new("deploy-issue-krc20-token")
+                    .long("deployissuekrc20")
+                    .required(false)
+                    .help("Deploy an issue-mode KRC-20 token. Must provide a path to a file that has the deploy info".to_string())
+                    .value_name("FILE")
+            )
+            .arg(
+                Arg::new("krc20-owner-op")
+                    .long("ownerkrc20")
+                    .required(false)
+                    .help("Issue, burn, chown or blacklist for an issue-mode KRC-20 token. Must provide a path to a file that has the op info".to_string())
+                    .value_name("FILE")
+            )
+            .arg(
+                Arg::new("deploy-krc721-collection")
+                    .long("deploykrc721")
+                    .required(false)
+                    .help("Deploy a KRC-721 collection. Must provide a path to a file that has the deploy info".to_string())
+                    .value_name("FILE")
+            )
+            .arg(
+                Arg::new("mint-krc721-tokens")
+                    .long("mintkrc721")
+                    .required(false)
+                    .help("Mint KRC-721 tokens. Must provide a path to a file that has the mint info".to_string())
+                    .value_name("FILE")
+            )
+            .arg(
+                Arg::new("transfer-krc721-token")
+                    .long("txkrc721")
+                    .required(false)
+                    .help("Transfer a KRC-721 token by token id. Must provide a path to a file that has the transaction info".to_string())
+                    .value_name("FILE")
+            )
+            .arg(
+                Arg::new("inscribe-data")
+                    .long("inscribe")
+                    .required(false)
+                    .help("Inscribe arbitrary content with a protocol tag and content type. Must provide a path to a file that has the inscription info".to_string())
+                    .value_name("FILE")
+            )
+            .arg(
+                Arg::new("recover")
+                    .long("recover")
+                    .required(false)
+                    .help("Reveal or refund commits left pending in the reveal journal. Must provide a path to a file that has the private key".to_string())
+                    .value_name("FILE")
+            )
+            .arg(
+                Arg::new("list-krc20-tokens")
+                    .long("listkrc20")
+                    .required(false)
+                    .help("List KRC-20 tokens for sale and export the listing PSKT. Must provide a path to a file that has the listing info".to_string())
+                    .value_name("FILE")
+            )
+            .arg(
+                Arg::new("cancel-krc20-listing")
+                    .long("cancelkrc20")
+                    .required(false)
+                    .help("Cancel a KRC-20 listing and reclaim its UTXO. Must provide a path to a file that has the listing info".to_string())
+                    .value_name("FILE")
+            )
+            .arg(
+                Arg::new("buy-krc20-listing")
+                    .long("buykrc20")
+                    .required(false)
+                    .help("Buy a KRC-20 listing from a seller's PSKT. Must provide a path to a file that has the purchase info".to_string())
+                    .value_name("FILE")
+            )
+            .arg(
+                Arg::new("decode-transactions")
+                    .long("decode")
+                    .required(false)
+                    .help("Decode the KRC-20 inscriptions of reveal transactions. Must provide a path to a file that has transaction ids or JSON file paths one per line".to_string())
+                    .value_name("FILE")
+            )
+            .arg(
+                Arg::new("local-index")
+                    .long("localindex")
+                    .required(false)
+                    .help("Run the local KRC-20 indexer following the virtual chain. Only tokens deployed after its start block are tracked. Must provide a path to a file that has the optional start block and store path".to_string())
+                    .value_name("FILE")
+            )
+            .arg(
+                Arg::new("get-krc20-balances")
+                    .long("krc20b")
+                    .required(false)
+                    .help("Get KRC-20 token balances from the indexer. Must provide a path to a file that has the addresses one per line".to_string())
+                    .value_name("FILE")
+            )
+            .arg(
+                Arg::new("get-krc20-token-info")
+                    .long("krc20info")
+                    .required(false)
+                    .help("Get KRC-20 token info from the indexer. Must provide a path to a file that has the tickers one per line".to_string())
+                    .value_name("FILE")
+            )
+            .arg(
+                Arg::new("op-history")
+                    .long("history")
+                    .required(false)
+                    .help("Export the KRC-20 operations of addresses to CSV. Must provide a path to a file that has the addresses one per line".to_string())
+                    .value_name("FILE")
+            )
+            .arg(
+                Arg::new("op-history-output")
+                    .long("historyout")
+                    .required(false)
+                    .help("Path of the CSV written by --history. Defaults to krc20-history.csv".to_string())
+                    .value_name("FILE")
+            )
+            .arg(
+                Arg::new("holder-snapshot")
+                    .long("snapshot")
+                    .required(false)
+                    .help("Export the holders of a KRC-20 token to CSV or JSON. Must provide a path to a file that has the snapshot info".to_string())
+                    .value_name("FILE")
+            )
             .arg(
                 Arg::new("build-wallet")
                     .long("bw")
@@ -190,6 +382,11 @@ impl Args {
                 .unwrap_or(&"testnet-10".to_string())
                 .clone(),
             resolver: *m.get_one::<bool>("resolver").unwrap_or(&false),
+            indexer: m
+                .get_one::<String>("indexer")
+                .unwrap_or(&"".to_string())
+                .clone(),
+            skip_preflight: *m.get_one::<bool>("skip-preflight").unwrap_or(&false),
             get_current_network: *m.get_one::<bool>("get-current-network").unwrap_or(&false),
             get_sync_status: *m.get_one::<bool>("get-sync-status").unwrap_or(&false),
             get_info: *m.get_one::<bool>("get-info").unwrap_or(&false),
@@ -219,6 +416,94 @@ impl Args {
                 .get_one::<String>("transfer-krc20-tokens")
                 .unwrap_or(&"".to_string())
                 .clone(),
+            batch_transfer_krc20_tokens: m
+                .get_one::<String>("batch-transfer-krc20-tokens")
+                .unwrap_or(&"".to_string())
+                .clone(),
+            mint_krc20_tokens: m
+                .get_one::<String>("mint-krc20-tokens")
+                .unwrap_or(&"".to_string())
+                .clone(),
+            mint_farm: m
+                .get_one::<String>("mint-farm")
+                .unwrap_or(&"".to_string())
+                .clone(),
+            sweep_krc20_tokens: m
+                .get_one::<String>("sweep-krc20-tokens")
+                .unwrap_or(&"".to_string())
+                .clone(),
+            deploy_krc20_token: m
+                .get_one::<String>("deploy-krc20-token")
+                .unwrap_or(&"".to_string())
+                .clone(),
+            deploy_issue_krc20_token: m
+                .get_one::<String>("deploy-issue-krc20-token")
+                .unwrap_or(&"".to_string())
+                .clone(),
+            krc20_owner_op: m
+                .get_one::<String>("krc20-owner-op")
+                .unwrap_or(&"".to_string())
+                .clone(),
+            deploy_krc721_collection: m
+                .get_one::<String>("deploy-krc721-collection")
+                .unwrap_or(&"".to_string())
+                .clone(),
+            mint_krc721_tokens: m
+                .get_one::<String>("mint-krc721-tokens")
+                .unwrap_or(&"".to_string())
+                .clone(),
+            transfer_krc721_token: m
+                .get_one::<String>("transfer-krc721-token")
+                .unwrap_or(&"".to_string())
+                .clone(),
+            inscribe_data: m
+                .get_one::<String>("inscribe-data")
+                .unwrap_or(&"".to_string())
+                .clone(),
+            recover: m
+                .get_one::<String>("recover")
+                .unwrap_or(&"".to_string())
+                .clone(),
+            list_krc20_tokens: m
+                .get_one::<String>("list-krc20-tokens")
+                .unwrap_or(&"".to_string())
+                .clone(),
+            cancel_krc20_listing: m
+                .get_one::<String>("cancel-krc20-listing")
+                .unwrap_or(&"".to_string())
+                .clone(),
+            buy_krc20_listing: m
+                .get_one::<String>("buy-krc20-listing")
+                .unwrap_or(&"".to_string())
+                .clone(),
+            decode_transactions: m
+                .get_one::<String>("decode-transactions")
+                .unwrap_or(&"".to_string())
+                .clone(),
+            local_index: m
+                .get_one::<String>("local-index")
+                .unwrap_or(&"".to_string())
+                .clone(),
+            get_krc20_balances: m
+                .get_one::<String>("get-krc20-balances")
+                .unwrap_or(&"".to_string())
+                .clone(),
+            get_krc20_token_info: m
+                .get_one::<String>("get-krc20-token-info")
+                .unwrap_or(&"".to_string())
+                .clone(),
+            op_history: m
+                .get_one::<String>("op-history")
+                .unwrap_or(&"".to_string())
+                .clone(),
+            op_history_output: m
+                .get_one::<String>("op-history-output")
+                .unwrap_or(&"".to_string())
+                .clone(),
+            holder_snapshot: m
+                .get_one::<String>("holder-snapshot")
+                .unwrap_or(&"".to_string())
+                .clone(),
             build_wallet: *m.get_one::<bool>("build-wallet").unwrap_or(&false),
             single_tx: m
                 .get_one::<String>("single-tx")
@@ -0,0 +1,536 @@
+use kaspa_addresses::{Address, Prefix, Version};
+use kaspa_consensus_core::tx::{ScriptPublicKey, Transaction, TransactionId, TransactionOutput};
+use kaspa_rpc_core::{api::rpc::RpcApi, RpcTransaction};
+use kaspa_txscript::{pay_to_address_script, pay_to_script_hash_script};
+use kaspa_wallet_core::utils::sompi_to_kaspa_string;
+use kaspa_wrpc_client::{error::Error, prelude::NetworkType, result::Result, KaspaRpcClient};
+use num_bigint::BigInt;
+use secp256k1::Keypair;
+
+use crate::client::{
+    build_batch_commit_transaction, build_redeem_script, build_reveal_transaction, fetch_utxos,
+    keypair_from_hex, output_utxo, p2sh_address, parse_kaspa, preflight,
+    submit_chained_transaction, token_decimals,
+};
+use crate::fees::{
+    batch_commit_mass, fee_and_change, fee_for_mass, min_output_value, reveal_mass, FeeSchedule,
+    MIN_LOCK_CHANGE,
+};
+use crate::indexer::IndexerClient;
+use crate::journal::{EntryStatus, Journal, JournalEntry, JOURNAL_FILE};
+use crate::krc20::{Krc20Op, Transfer};
+
+use std::time::Duration;
+
+/// Times each reveal is attempted. Retries spend the same commit output, so a
+/// failed reveal never needs a new commit.
+const REVEAL_ATTEMPTS: u32 = 3;
+const RETRY_DELAY: Duration = Duration::from_millis(2_000);
+
+/// One transfer of a batch and its P2SH lock in the shared commit.
+#[derive(Debug, Clone)]
+struct Lock {
+    recipient: String,
+    amount: String,
+    data: String,
+    redeem_script: Vec<u8>,
+    address: Address,
+}
+
+impl Lock {
+    fn new(
+        recipient: String,
+        amount: String,
+        transfer: Transfer,
+        x_only_public_key: &[u8],
+        prefix: Prefix,
+    ) -> Result<Lock> {
+        let data = Krc20Op::Transfer(transfer).to_json()?;
+        let redeem_script = build_redeem_script(x_only_public_key, data.as_bytes())?;
+        Ok(Lock {
+            recipient,
+            amount,
+            data,
+            address: p2sh_address(&redeem_script, prefix)?,
+            redeem_script,
+        })
+    }
+}
+
+/// Amounts in sompi shared by the transfers of a batch.
+#[derive(Debug, Clone, Copy)]
+struct BatchCosts {
+    /// Value of each P2SH output.
+    lock_amount: u64,
+    /// Network fee of each reveal.
+    network_fee: u64,
+    commit_fee: u64,
+}
+
+impl BatchCosts {
+    /// Sizes the locks and fees of a commit spending wallet inputs worth
+    /// `input_values` into `locks`, with change to `change_script`.
+    ///
+    /// All transfers inscribe the same op shape, so the largest reveal sets
+    /// the fee and lock amount for every output. The commit pays for its
+    /// storage mass too, which the many small locks dominate.
+    fn new(
+        locks: &[Lock],
+        input_values: &[u64],
+        change_script: &ScriptPublicKey,
+        protocol_fee: u64,
+        priority_fee: u64,
+    ) -> BatchCosts {
+        let max_reveal_mass = locks
+            .iter()
+            .map(|lock| reveal_mass(&lock.redeem_script, 0, &[], change_script))
+            .max()
+            .unwrap_or_default();
+        let lock_amount =
+            (fee_for_mass(max_reveal_mass) + priority_fee + protocol_fee + MIN_LOCK_CHANGE)
+                .max(min_output_value(locks.len() + 1));
+        let (network_fee, _) = fee_and_change(
+            &[lock_amount],
+            &[],
+            protocol_fee,
+            max_reveal_mass,
+            priority_fee,
+        );
+        let redeem_scripts: Vec<&[u8]> = locks
+            .iter()
+            .map(|lock| lock.redeem_script.as_slice())
+            .collect();
+        let (commit_fee, _) = fee_and_change(
+            input_values,
+            &vec![lock_amount; locks.len()],
+            0,
+            batch_commit_mass(input_values.len(), &redeem_scripts, change_script),
+            priority_fee,
+        );
+        BatchCosts {
+            lock_amount,
+            network_fee,
+            commit_fee,
+        }
+    }
+}
+
+/// Returns the commit outputs locking `lock_amount` in each of `locks`, in
+/// order, so output `i` is revealed by lock `i`.
+fn lock_outputs(locks: &[Lock], lock_amount: u64) -> Vec<TransactionOutput> {
+    locks
+        .iter()
+        .map(|lock| {
+            TransactionOutput::new(lock_amount, pay_to_script_hash_script(&lock.redeem_script))
+        })
+        .collect()
+}
+
+/// Outcome of one transfer of a batch.
+#[derive(Debug, Clone)]
+pub struct BatchTransfer {
+    pub recipient: String,
+    /// Amount in token units, as given in the batch file.
+    pub amount: String,
+    /// Index of the transfer's P2SH output in the commit.
+    pub output_index: u32,
+    /// The reveal id, or why the last reveal attempt failed.
+    pub result: std::result::Result<TransactionId, String>,
+}
+
+/// Result of a batch of transfers sharing one commit.
+#[derive(Debug, Clone)]
+pub struct BatchResult {
+    pub commit_id: TransactionId,
+    pub transfers: Vec<BatchTransfer>,
+}
+
+/// Parses a recipient line of the form `<address> <amount>`, also accepting a
+/// comma as separator.
+fn parse_recipient(line: &str) -> Result<(String, String)> {
+    let parts: Vec<&str> = line
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|part| !part.is_empty())
+        .collect();
+    match parts.as_slice() {
+        [recipient, amount] => Ok((recipient.to_string(), amount.to_string())),
+        _ => Err(Error::Custom(format!(
+            "Invalid recipient line '{}': expected an address and an amount",
+            line
+        ))),
+    }
+}
+
+/// Reveals the transfer locked in output `output_index` of `commit_tx`,
+/// sending everything but the fees back to `change_address`. The reveal is
+/// submitted without waiting for the commit to be accepted.
+#[allow(clippy::too_many_arguments)]
+async fn reveal_lock(
+    client: &KaspaRpcClient,
+    keypair: &Keypair,
+    journal: &Journal,
+    lock: &Lock,
+    commit_tx: &Transaction,
+    output_index: u32,
+    change_address: &Address,
+    network_fee: u64,
+    protocol_fee: u64,
+) -> Result<TransactionId> {
+    let utxo = output_utxo(commit_tx, output_index).ok_or_else(|| {
+        Error::Custom(format!("Commit transaction has no output {}", output_index))
+    })?;
+    let reveal_tx = build_reveal_transaction(
+        keypair,
+        utxo,
+        vec![],
+        &lock.redeem_script,
+        vec![],
+        change_address,
+        network_fee,
+        protocol_fee,
+    )?;
+    let reveal_id = submit_chained_transaction(client, &reveal_tx).await?;
+    // The reveal is out: failing here would get it submitted again
+    if let Err(e) = journal.update(
+        &commit_tx.id().to_string(),
+        output_index,
+        EntryStatus::Revealed,
+        &reveal_id.to_string(),
+    ) {
+        eprintln!("Failed to journal reveal {reveal_id}: {e}");
+    }
+    Ok(reveal_id)
+}
+
+/// Transfers a KRC-20 token to many recipients with a single commit
+/// transaction holding one P2SH output per transfer. The reveals are then
+/// submitted in parallel, each spending its own output, and failed reveals
+/// are retried against the same commit. Every output is recorded in the
+/// reveal journal, so reveals that still fail can be finished with
+/// `recover`.
+///
+/// Each P2SH output must keep the commit's storage mass standard, so with
+/// many recipients more KAS is locked than the reveal needs. The excess
+/// returns to the wallet with each reveal's change.
+///
+/// Batch transfer file layout, one value per line:
+/// private key, ticker, priority fee (KAS), token decimals (may be empty to
+/// read them from the indexer, required without one), then one
+/// `<address> <amount>` line per recipient.
+pub async fn batch_transfer_krc20_tokens(
+    client: &KaspaRpcClient,
+    indexer: Option<&IndexerClient>,
+    network: NetworkType,
+    batch_args: Vec<String>,
+) -> Result<BatchResult> {
+    if batch_args.len() < 5 {
+        return Err(Error::Custom(format!(
+            "Expected at least 5 lines of batch transfer arguments, found {}",
+            batch_args.len()
+        )));
+    }
+
+    let keypair = keypair_from_hex(&batch_args[0])?;
+    let ticker = batch_args[1].trim().to_string();
+    let fee = parse_kaspa(&batch_args[2], "priority fee")?;
+    let dec = token_decimals(indexer, &ticker, &batch_args[3]).await?;
+
+    let prefix = Prefix::from(network);
+    let (x_only_public_key, _) = keypair.x_only_public_key();
+    let address = Address::new(prefix, Version::PubKey, &x_only_public_key.serialize());
+    println!("Determining address: {address}");
+
+    let mut transfers = Vec::new();
+    for line in batch_args[4..]
+        .iter()
+        .filter(|line| !line.trim().is_empty())
+    {
+        let (recipient, amount) = parse_recipient(line)?;
+        let transfer = Transfer::new(&ticker, &amount, dec, &recipient)?;
+        transfers.push((recipient, amount, transfer));
+    }
+    if transfers.is_empty() {
+        return Err(Error::Custom(
+            "Batch transfer file has no recipients".to_string(),
+        ));
+    }
+
+    // Check the balance against the batch as a whole
+    if let Some(indexer) = indexer {
+        let mut total = transfers[0].2.clone();
+        total.amt = transfers
+            .iter()
+            .map(|(_, _, transfer)| transfer.amt.clone())
+            .sum::<BigInt>();
+        preflight(indexer, &Krc20Op::Transfer(total), &address).await?;
+    }
+
+    let locks = transfers
+        .into_iter()
+        .map(|(recipient, amount, transfer)| {
+            Lock::new(
+                recipient,
+                amount,
+                transfer,
+                &x_only_public_key.serialize(),
+                prefix,
+            )
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let utxos = fetch_utxos(client, &address).await?;
+    if utxos.is_empty() {
+        return Err(Error::Custom(format!(
+            "No UTXOs available for address: {address}"
+        )));
+    }
+    // Change too small to keep the commit standard is left to the fee
+    let input_values: Vec<u64> = utxos.iter().map(|(_, entry)| entry.amount).collect();
+    let protocol_fee = FeeSchedule::load(network)?.transfer;
+    let BatchCosts {
+        lock_amount,
+        network_fee,
+        commit_fee,
+    } = BatchCosts::new(
+        &locks,
+        &input_values,
+        &pay_to_address_script(&address),
+        protocol_fee,
+        fee,
+    );
+    let count = locks.len() as u64;
+    println!("Recipients:    {count}");
+    println!("Commit fee:    {} KAS", sompi_to_kaspa_string(commit_fee));
+    println!(
+        "Reveal fees:   {} KAS",
+        sompi_to_kaspa_string(network_fee * count)
+    );
+    println!(
+        "Protocol fees: {} KAS",
+        sompi_to_kaspa_string(protocol_fee * count)
+    );
+    println!(
+        "Locked:        {} KAS",
+        sompi_to_kaspa_string(lock_amount * count)
+    );
+
+    let commit_tx = build_batch_commit_transaction(
+        &keypair,
+        utxos,
+        lock_outputs(&locks, lock_amount),
+        &address,
+        commit_fee,
+    )?;
+
+    // Record every lock before broadcasting so each output can be recovered
+    // if we die before its reveal.
+    let journal = Journal::new(JOURNAL_FILE);
+    for (output_index, lock) in locks.iter().enumerate() {
+        journal.append(&JournalEntry {
+            op: lock.data.clone(),
+            redeem_script: hex::encode(&lock.redeem_script),
+            p2sh_address: lock.address.to_string(),
+            commit_id: commit_tx.id().to_string(),
+            output_index: output_index as u32,
+            key: address.to_string(),
+            status: EntryStatus::Pending,
+            reveal_id: None,
+            outputs: vec![],
+        })?;
+    }
+
+    let commit_id = client
+        .submit_transaction(RpcTransaction::from(&commit_tx), false)
+        .await?;
+    println!("Submitted commit transaction: {commit_id}");
+
+    let mut results: Vec<std::result::Result<TransactionId, String>> =
+        vec![Err("Not revealed".to_string()); locks.len()];
+    for attempt in 1..=REVEAL_ATTEMPTS {
+        let pending: Vec<usize> = (0..locks.len())
+            .filter(|index| results[*index].is_err())
+            .collect();
+        if pending.is_empty() {
+            break;
+        }
+        if attempt > 1 {
+            println!(
+                "Retrying {} failed reveals (attempt {attempt}/{REVEAL_ATTEMPTS})...",
+                pending.len()
+            );
+            tokio::time::sleep(RETRY_DELAY).await;
+        }
+
+        let reveals = pending.iter().map(|&index| {
+            reveal_lock(
+                client,
+                &keypair,
+                &journal,
+                &locks[index],
+                &commit_tx,
+                index as u32,
+                &address,
+                network_fee,
+                protocol_fee,
+            )
+        });
+        let outcomes = futures::future::join_all(reveals).await;
+        for (index, outcome) in pending.into_iter().zip(outcomes) {
+            let lock = &locks[index];
+            match outcome {
+                Ok(reveal_id) => {
+                    println!("Submitted reveal for {}: {reveal_id}", lock.recipient);
+                    results[index] = Ok(reveal_id);
+                }
+                Err(e) => {
+                    println!("Reveal for {} failed: {e}", lock.recipient);
+                    results[index] = Err(e.to_string());
+                }
+            }
+        }
+    }
+
+    Ok(BatchResult {
+        commit_id,
+        transfers: locks
+            .into_iter()
+            .zip(results)
+            .enumerate()
+            .map(|(index, (lock, result))| BatchTransfer {
+                recipient: lock.recipient,
+                amount: lock.amount,
+                output_index: index as u32,
+                result,
+            })
+            .collect(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fees::TRANSFER_FEE;
+    use kaspa_consensus_core::tx::{TransactionOutpoint, UtxoEntry};
+    use secp256k1::Secp256k1;
+
+    const KAS: u64 = 100_000_000;
+
+    fn keypair() -> Keypair {
+        Keypair::from_seckey_slice(&Secp256k1::new(), &[1; 32]).unwrap()
+    }
+
+    fn address(keypair: &Keypair) -> Address {
+        let (x_only_public_key, _) = keypair.x_only_public_key();
+        Address::new(
+            Prefix::Mainnet,
+            Version::PubKey,
+            &x_only_public_key.serialize(),
+        )
+    }
+
+    fn locks(keypair: &Keypair, count: u8) -> Vec<Lock> {
+        let (x_only_public_key, _) = keypair.x_only_public_key();
+        (0..count)
+            .map(|n| {
+                let recipient =
+                    Address::new(Prefix::Mainnet, Version::PubKey, &[n + 2; 32]).to_string();
+                let transfer = Transfer::new("test", "1", 0, &recipient).unwrap();
+                Lock::new(
+                    recipient,
+                    "1".to_string(),
+                    transfer,
+                    &x_only_public_key.serialize(),
+                    Prefix::Mainnet,
+                )
+                .unwrap()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn sizes_locks_for_the_reveal_and_the_commit_storage_mass() {
+        let keypair = keypair();
+        let change_script = pay_to_address_script(&address(&keypair));
+        let inputs = [1_000 * KAS];
+
+        // A few locks only need to cover their reveal and a standard change
+        let few = BatchCosts::new(
+            &locks(&keypair, 2),
+            &inputs,
+            &change_script,
+            TRANSFER_FEE,
+            0,
+        );
+        assert!(few.network_fee > 0);
+        assert!(few.lock_amount > TRANSFER_FEE + MIN_LOCK_CHANGE);
+        assert!(few.lock_amount < TRANSFER_FEE + MIN_LOCK_CHANGE + KAS / 1_000);
+        assert!(few.lock_amount - few.network_fee - TRANSFER_FEE >= min_output_value(1));
+
+        // Many locks are raised so the commit's storage mass stays standard
+        let many = BatchCosts::new(
+            &locks(&keypair, 50),
+            &inputs,
+            &change_script,
+            TRANSFER_FEE,
+            0,
+        );
+        assert_eq!(many.lock_amount, min_output_value(51));
+        assert!(many.commit_fee > few.commit_fee);
+    }
+
+    #[test]
+    fn commit_locks_each_transfer_at_its_index() {
+        let keypair = keypair();
+        let address = address(&keypair);
+        let change_script = pay_to_address_script(&address);
+        let locks = locks(&keypair, 3);
+        let inputs = [1_000 * KAS];
+        let costs = BatchCosts::new(&locks, &inputs, &change_script, TRANSFER_FEE, 0);
+
+        let utxo = (
+            TransactionOutpoint::new(TransactionId::from_u64_word(1), 0),
+            UtxoEntry::new(inputs[0], change_script.clone(), 0, false),
+        );
+        let commit = build_batch_commit_transaction(
+            &keypair,
+            vec![utxo],
+            lock_outputs(&locks, costs.lock_amount),
+            &address,
+            costs.commit_fee,
+        )
+        .unwrap();
+        assert_eq!(commit.outputs.len(), locks.len() + 1);
+        let change = commit.outputs.last().unwrap();
+        assert_eq!(change.script_public_key, change_script);
+        assert_eq!(
+            change.value,
+            inputs[0] - 3 * costs.lock_amount - costs.commit_fee
+        );
+
+        for (index, lock) in locks.iter().enumerate() {
+            assert_eq!(
+                commit.outputs[index],
+                TransactionOutput::new(
+                    costs.lock_amount,
+                    pay_to_script_hash_script(&lock.redeem_script)
+                )
+            );
+            // Each lock pays its reveal's network and protocol fees
+            let reveal = build_reveal_transaction(
+                &keypair,
+                output_utxo(&commit, index as u32).unwrap(),
+                vec![],
+                &lock.redeem_script,
+                vec![],
+                &address,
+                costs.network_fee,
+                TRANSFER_FEE,
+            )
+            .unwrap();
+            assert_eq!(
+                reveal.outputs[0].value,
+                costs.lock_amount - costs.network_fee - TRANSFER_FEE
+            );
+        }
+    }
+}
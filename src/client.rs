@@ -1,101 +1,1416 @@
 #![allow(unused)]
-use kaspa_notify::subscription::context::SubscriptionContext;
-use kaspa_rpc_core::{api::rpc::RpcApi, GetServerInfoResponse};
-use kaspa_txscript::{opcodes::codes::*, script_builder::ScriptBuilder};
+use kaspa_addresses::{Address, Prefix, Version};
+use kaspa_consensus_core::hashing::sighash::{calc_schnorr_signature_hash, SigHashReusedValues};
+use kaspa_consensus_core::hashing::sighash_type::{SigHashType, SIG_HASH_ALL};
+use kaspa_consensus_core::sign::sign;
+use kaspa_consensus_core::subnets::SUBNETWORK_ID_NATIVE;
+use kaspa_consensus_core::tx::{
+    SignableTransaction, Transaction, TransactionId, TransactionInput, TransactionOutpoint,
+    TransactionOutput, UtxoEntry,
+};
+use kaspa_rpc_core::{api::rpc::RpcApi, RpcTransaction};
+use kaspa_txscript::{
+    extract_script_pub_key_address, opcodes::codes::*, pay_to_address_script,
+    pay_to_script_hash_script, pay_to_script_hash_signature_script,
+};
+use kaspa_wallet_core::utils::try_kaspa_str_to_sompi;
 use kaspa_wallet_keys::privatekey::PrivateKey;
-use kaspa_wrpc_client::{
-    client::{ConnectOptions, ConnectStrategy},
-    prelude::NetworkId,
-    prelude::NetworkType,
-    result::Result,
-    KaspaRpcClient, Resolver, WrpcEncoding,
+use kaspa_wrpc_client::{error::Error, prelude::NetworkType, result::Result, KaspaRpcClient};
+use num_bigint::BigInt;
+use secp256k1::{Keypair, Message, Secp256k1};
+
+use crate::decoder::{parse_envelope, ENVELOPE_MARKER};
+use crate::envelope::Content;
+use crate::fees::{
+    check_reveal_fee, fee_and_change, keeps_change, min_output_value, payment_mass, reveal_mass,
+    CostBreakdown, FeeSchedule,
 };
+use crate::indexer::{parse_number, IndexerClient, TokenInfo};
+use crate::journal::{EntryStatus, Journal, JournalEntry, JournalOutput, JOURNAL_FILE};
+use crate::krc20::{
+    parse_decimals, Blacklist, BlacklistMode, Burn, Chown, Deploy, Issue, Krc20Op, List, Mint,
+    Transfer,
+};
+use crate::krc721::{self, Krc721Op};
 
 use std::fs;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
-async fn transfer_krc20_tokens(network: NetworkType) -> Result<()> {
-    let encoding = WrpcEncoding::Borsh;
+const DEPLOY_POLL_INTERVAL: Duration = Duration::from_millis(2_000);
+const DEPLOY_WAIT_TIMEOUT: u64 = 300; // 5 minutes
 
-    let contents = fs::read_to_string("transfer_args.txt");
-    let transfer_args: Vec<String> = contents
-        .expect("Failed to read file")
-        .lines()
-        .map(|line| line.to_string())
-        .collect();
+/// A wallet UTXO that can be spent as a transaction input.
+pub type Utxo = (TransactionOutpoint, UtxoEntry);
+
+/// Builds the `kasplex` envelope redeem script for an inscription.
+/// The script is spendable only by the owner of `x_only_public_key`.
+pub fn build_redeem_script(x_only_public_key: &[u8], data: &[u8]) -> Result<Vec<u8>> {
+    Content::new(ENVELOPE_MARKER, None, data).redeem_script(x_only_public_key)
+}
+
+/// Returns the P2SH commit address for a redeem script.
+pub fn p2sh_address(redeem_script: &[u8], prefix: Prefix) -> Result<Address> {
+    extract_script_pub_key_address(&pay_to_script_hash_script(redeem_script), prefix)
+        .map_err(|e| Error::Custom(format!("Failed to derive P2SH address: {}", e)))
+}
+
+pub async fn fetch_utxos(client: &KaspaRpcClient, address: &Address) -> Result<Vec<Utxo>> {
+    let entries = client.get_utxos_by_addresses(vec![address.clone()]).await?;
+    Ok(entries
+        .into_iter()
+        .map(|entry| {
+            (
+                TransactionOutpoint::from(entry.outpoint),
+                UtxoEntry::from(entry.utxo_entry),
+            )
+        })
+        .collect())
+}
+
+/// Submits `tx`, which may spend outputs of a transaction submitted just
+/// before it. Orphans are allowed so the node holds `tx` until the parent has
+/// reached its mempool.
+pub async fn submit_chained_transaction(
+    client: &KaspaRpcClient,
+    tx: &Transaction,
+) -> Result<TransactionId> {
+    Ok(client
+        .submit_transaction(RpcTransaction::from(tx), true)
+        .await?)
+}
+
+/// Builds and signs the commit transaction that locks `commit_amount` into the
+/// P2SH address of `redeem_script`. Output 0 is the P2SH lock, output 1 (if any)
+/// is the change back to `change_address`.
+pub fn build_commit_transaction(
+    keypair: &Keypair,
+    utxos: Vec<Utxo>,
+    redeem_script: &[u8],
+    change_address: &Address,
+    commit_amount: u64,
+    fee: u64,
+) -> Result<Transaction> {
+    build_batch_commit_transaction(
+        keypair,
+        utxos,
+        vec![TransactionOutput::new(
+            commit_amount,
+            pay_to_script_hash_script(redeem_script),
+        )],
+        change_address,
+        fee,
+    )
+}
+
+/// Builds and signs a commit transaction whose first outputs are the P2SH
+/// `locks`, followed by the change back to `change_address` (if any).
+pub fn build_batch_commit_transaction(
+    keypair: &Keypair,
+    utxos: Vec<Utxo>,
+    locks: Vec<TransactionOutput>,
+    change_address: &Address,
+    fee: u64,
+) -> Result<Transaction> {
+    build_payment_transaction(keypair, utxos, locks, change_address, fee)
+}
+
+/// Builds and signs a transaction spending the wallet `utxos` of `keypair`
+/// into `outputs`, followed by the change back to `change_address`. Change
+/// too small to be standard is left to the fee.
+pub fn build_payment_transaction(
+    keypair: &Keypair,
+    utxos: Vec<Utxo>,
+    outputs: Vec<TransactionOutput>,
+    change_address: &Address,
+    fee: u64,
+) -> Result<Transaction> {
+    let total: u64 = utxos.iter().map(|(_, entry)| entry.amount).sum();
+    let required = outputs.iter().map(|output| output.value).sum::<u64>() + fee;
+    if total < required {
+        return Err(Error::Custom(format!(
+            "Insufficient funds: have {} sompi, need {} sompi",
+            total, required
+        )));
+    }
+    let change = total - required;
+
+    let input_values: Vec<u64> = utxos.iter().map(|(_, entry)| entry.amount).collect();
+    let output_values: Vec<u64> = outputs.iter().map(|output| output.value).collect();
+    let mut outputs = outputs;
+    if keeps_change(&input_values, &output_values, change) {
+        outputs.push(TransactionOutput::new(
+            change,
+            pay_to_address_script(change_address),
+        ));
+    }
+
+    let (inputs, entries): (Vec<TransactionInput>, Vec<UtxoEntry>) = utxos
+        .into_iter()
+        .enumerate()
+        .map(|(sequence, (outpoint, entry))| {
+            (
+                TransactionInput::new(outpoint, vec![], sequence as u64, 1),
+                entry,
+            )
+        })
+        .unzip();
+
+    let tx = Transaction::new(0, inputs, outputs, 0, SUBNETWORK_ID_NATIVE, 0, vec![]);
+    let signed = sign(SignableTransaction::with_entries(tx, entries), *keypair);
+    Ok(signed.tx)
+}
+
+/// Payments per transaction when paying many addresses at once. Each output
+/// adds storage mass, so smaller transactions allow smaller outputs.
+pub const PAYMENT_CHUNK_SIZE: usize = 16;
+
+/// Transactions paying many addresses from one wallet.
+#[derive(Debug, Clone)]
+pub struct Payments {
+    pub ids: Vec<TransactionId>,
+    /// The UTXO paid to each address, in the order of the payments.
+    pub utxos: Vec<Utxo>,
+    /// Network fees paid by all transactions.
+    pub fee: u64,
+}
+
+/// Pays each `(address, amount)` from the wallet `utxos` of `keypair` in
+/// transactions of at most `PAYMENT_CHUNK_SIZE` outputs, each spending the
+/// change of the one before. Every amount is raised to the smallest output
+/// its own transaction allows. All transactions are built before the first
+/// is submitted, so a wallet that cannot cover them spends nothing.
+pub async fn pay_in_chunks(
+    client: &KaspaRpcClient,
+    keypair: &Keypair,
+    utxos: Vec<Utxo>,
+    payments: &[(Address, u64)],
+    change_address: &Address,
+    priority_fee: u64,
+) -> Result<Payments> {
+    let change_script = pay_to_address_script(change_address);
+    let mut utxos = utxos;
+    let mut txs = Vec::new();
+    let mut paid = Payments {
+        ids: Vec::new(),
+        utxos: Vec::new(),
+        fee: 0,
+    };
+    for chunk in payments.chunks(PAYMENT_CHUNK_SIZE) {
+        let min_value = min_output_value(chunk.len() + 1);
+        let outputs: Vec<TransactionOutput> = chunk
+            .iter()
+            .map(|(address, amount)| {
+                TransactionOutput::new((*amount).max(min_value), pay_to_address_script(address))
+            })
+            .collect();
+        let input_values: Vec<u64> = utxos.iter().map(|(_, entry)| entry.amount).collect();
+        let output_values: Vec<u64> = outputs.iter().map(|output| output.value).collect();
+        let mass = payment_mass(utxos.len(), &outputs, &change_script);
+        let (fee, _) = fee_and_change(&input_values, &output_values, 0, mass, priority_fee);
+        let tx = build_payment_transaction(keypair, utxos, outputs, change_address, fee)?;
+        paid.utxos
+            .extend((0..chunk.len() as u32).filter_map(|index| output_utxo(&tx, index)));
+        paid.fee += fee;
+        utxos = output_utxo(&tx, chunk.len() as u32).into_iter().collect();
+        txs.push(tx);
+    }
+    for tx in &txs {
+        let id = submit_chained_transaction(client, tx).await?;
+        println!("Submitted payment transaction: {id}");
+        paid.ids.push(id);
+    }
+    Ok(paid)
+}
+
+/// Signs input `index` of `signable` with `keypair` using `sighash_type` and
+/// returns the `OP_DATA_65 <SIGNATURE+SIGHASH_TYPE>` push.
+pub fn schnorr_signature(
+    signable: &SignableTransaction,
+    index: usize,
+    keypair: &Keypair,
+    sighash_type: SigHashType,
+    reused_values: &mut SigHashReusedValues,
+) -> Result<Vec<u8>> {
+    let sig_hash = calc_schnorr_signature_hash(
+        &signable.as_verifiable(),
+        index,
+        sighash_type,
+        reused_values,
+    );
+    let msg = Message::from_digest_slice(sig_hash.as_bytes().as_slice())
+        .map_err(|e| Error::Custom(format!("Failed to create sighash message: {}", e)))?;
+    let sig: [u8; 64] = *keypair.sign_schnorr(msg).as_ref();
+    Ok(std::iter::once(65u8)
+        .chain(sig)
+        .chain([sighash_type.to_u8()])
+        .collect())
+}
+
+/// Builds and signs the reveal transaction spending the P2SH `commit_utxo`
+/// together with any `wallet_utxos` into `outputs`, sending everything else
+/// minus `network_fee` and `protocol_fee` back to `change_address` in a final
+/// change output. The P2SH signature script pushes the signature followed by
+/// the redeem script, which is what exposes the inscription on chain.
+///
+/// The reveal is only signed if its change leaves exactly the given fees and
+/// the protocol fee comes on top of the minimum network fee, since the
+/// indexer silently drops underpaying ops.
+#[allow(clippy::too_many_arguments)]
+pub fn build_reveal_transaction(
+    keypair: &Keypair,
+    commit_utxo: Utxo,
+    wallet_utxos: Vec<Utxo>,
+    redeem_script: &[u8],
+    outputs: Vec<TransactionOutput>,
+    change_address: &Address,
+    network_fee: u64,
+    protocol_fee: u64,
+) -> Result<Transaction> {
+    let utxos: Vec<Utxo> = std::iter::once(commit_utxo).chain(wallet_utxos).collect();
+    let total: u64 = utxos.iter().map(|(_, entry)| entry.amount).sum();
+    let required =
+        network_fee + protocol_fee + outputs.iter().map(|output| output.value).sum::<u64>();
+    if total <= required {
+        return Err(Error::Custom(format!(
+            "Reveal inputs of {} sompi cannot cover outputs and fee of {} sompi",
+            total, required
+        )));
+    }
+
+    let (inputs, entries): (Vec<TransactionInput>, Vec<UtxoEntry>) = utxos
+        .into_iter()
+        .enumerate()
+        .map(|(sequence, (outpoint, entry))| {
+            (
+                TransactionInput::new(outpoint, vec![], sequence as u64, 1),
+                entry,
+            )
+        })
+        .unzip();
+    let mut outputs = outputs;
+    outputs.push(TransactionOutput::new(
+        total - required,
+        pay_to_address_script(change_address),
+    ));
+    let tx = Transaction::new(0, inputs, outputs, 0, SUBNETWORK_ID_NATIVE, 0, vec![]);
+    check_reveal_fee(&tx, &entries, redeem_script, network_fee, protocol_fee)?;
+    let mut signable = SignableTransaction::with_entries(tx, entries);
+
+    let mut reused_values = SigHashReusedValues::new();
+    for index in 0..signable.tx.inputs.len() {
+        let signature =
+            schnorr_signature(&signable, index, keypair, SIG_HASH_ALL, &mut reused_values)?;
+        signable.tx.inputs[index].signature_script = if index == 0 {
+            pay_to_script_hash_signature_script(redeem_script.to_vec(), signature)
+                .map_err(|e| Error::Custom(format!("Failed to build signature script: {}", e)))?
+        } else {
+            signature
+        };
+    }
+    Ok(signable.tx)
+}
+
+/// Returns output `index` of a transaction we built as a spendable UTXO.
+pub fn output_utxo(tx: &Transaction, index: u32) -> Option<Utxo> {
+    tx.outputs.get(index as usize).map(|output| {
+        (
+            TransactionOutpoint::new(tx.id(), index),
+            UtxoEntry::new(output.value, output.script_public_key.clone(), 0, false),
+        )
+    })
+}
+
+/// Fees in sompi a commit/reveal round pays on top of the network fees
+/// derived from transaction mass.
+#[derive(Debug, Clone, Copy)]
+pub struct Fees {
+    /// Added to both the commit and the reveal fee.
+    pub priority_fee: u64,
+    /// Paid by the reveal for the inscribed op.
+    pub protocol_fee: u64,
+}
+
+/// Result of a single commit/reveal round.
+#[derive(Debug, Clone)]
+pub struct Inscribed {
+    pub commit_id: TransactionId,
+    pub reveal_id: TransactionId,
+    /// The reveal's last output, usable to fund the next round.
+    pub change: Utxo,
+}
+
+/// Inscribes `content` with a commit transaction funded by `utxos` and a
+/// reveal transaction spending the P2SH output and the commit change into
+/// `outputs` and a change output back to the wallet of `keypair`.
+#[allow(clippy::too_many_arguments)]
+pub async fn inscribe(
+    client: &KaspaRpcClient,
+    keypair: &Keypair,
+    prefix: Prefix,
+    utxos: Vec<Utxo>,
+    content: &Content,
+    outputs: Vec<TransactionOutput>,
+    fees: Fees,
+) -> Result<Inscribed> {
+    let (x_only_public_key, _) = keypair.x_only_public_key();
+    let address = Address::new(prefix, Version::PubKey, &x_only_public_key.serialize());
+
+    let redeem_script = content.redeem_script(&x_only_public_key.serialize())?;
+    let commit_address = p2sh_address(&redeem_script, prefix)?;
+    println!("Commit address: {commit_address}");
+
+    let input_values: Vec<u64> = utxos.iter().map(|(_, entry)| entry.amount).collect();
+    let cost = CostBreakdown::estimate(
+        &input_values,
+        &redeem_script,
+        &outputs,
+        &pay_to_address_script(&address),
+        fees.priority_fee,
+        fees.protocol_fee,
+    );
+    cost.print();
+    cost.check_balance(input_values.iter().sum())?;
+
+    let commit_tx = build_commit_transaction(
+        keypair,
+        utxos,
+        &redeem_script,
+        &address,
+        cost.lock_amount,
+        cost.commit_fee,
+    )?;
+
+    // The reveal spends the commit outputs straight away, without waiting for
+    // the commit to be accepted. It is built before anything is broadcast so
+    // a reveal that cannot be signed costs nothing.
+    let commit_utxo = output_utxo(&commit_tx, 0)
+        .ok_or_else(|| Error::Custom("Commit transaction has no P2SH output".to_string()))?;
+    let commit_change: Vec<Utxo> = output_utxo(&commit_tx, 1).into_iter().collect();
+    let journal_outputs = outputs.iter().map(JournalOutput::from).collect();
+    let reveal_tx = build_reveal_transaction(
+        keypair,
+        commit_utxo,
+        commit_change,
+        &redeem_script,
+        outputs,
+        &address,
+        cost.reveal_fee,
+        cost.protocol_fee,
+    )?;
+
+    // Record the redeem script before broadcasting so the commit output can
+    // be recovered if we die before the reveal.
+    let journal = Journal::new(JOURNAL_FILE);
+    journal.append(&JournalEntry {
+        op: String::from_utf8_lossy(&content.body).to_string(),
+        redeem_script: hex::encode(&redeem_script),
+        p2sh_address: commit_address.to_string(),
+        commit_id: commit_tx.id().to_string(),
+        output_index: 0,
+        key: address.to_string(),
+        status: EntryStatus::Pending,
+        reveal_id: None,
+        outputs: journal_outputs,
+    })?;
+
+    let commit_id = client
+        .submit_transaction(RpcTransaction::from(&commit_tx), false)
+        .await?;
+    println!("Submitted commit transaction: {commit_id}");
+
+    let reveal_id = submit_chained_transaction(client, &reveal_tx).await?;
+    println!("Submitted reveal transaction: {reveal_id}");
+    journal.update(
+        &commit_id.to_string(),
+        0,
+        EntryStatus::Revealed,
+        &reveal_id.to_string(),
+    )?;
+
+    let change = output_utxo(&reveal_tx, reveal_tx.outputs.len() as u32 - 1)
+        .ok_or_else(|| Error::Custom("Reveal transaction has no change output".to_string()))?;
+    Ok(Inscribed {
+        commit_id,
+        reveal_id,
+        change,
+    })
+}
+
+/// Inscribes `content` in a single commit/reveal round funded from all UTXOs
+/// of the wallet of `keypair`.
+pub async fn commit_reveal(
+    client: &KaspaRpcClient,
+    keypair: &Keypair,
+    prefix: Prefix,
+    content: &Content,
+    fees: Fees,
+) -> Result<Inscribed> {
+    let (x_only_public_key, _) = keypair.x_only_public_key();
+    let address = Address::new(prefix, Version::PubKey, &x_only_public_key.serialize());
+
+    let utxos = fetch_utxos(client, &address).await?;
+    if utxos.is_empty() {
+        return Err(Error::Custom(format!(
+            "No UTXOs available for address: {address}"
+        )));
+    }
+
+    inscribe(client, keypair, prefix, utxos, content, vec![], fees).await
+}
+
+/// Returns the decimals of token `tick`, a ticker or contract address. With
+/// an indexer they come from its token info and `dec`, if given, must match.
+/// Without one `dec` is required, since guessing would scale the amount by
+/// the wrong power of ten.
+pub async fn token_decimals(indexer: Option<&IndexerClient>, tick: &str, dec: &str) -> Result<u8> {
+    let tick = tick.trim();
+    let dec = dec.trim();
+    let Some(indexer) = indexer else {
+        if dec.is_empty() {
+            return Err(Error::Custom(format!(
+                "Token decimals of {tick} are required without a KRC-20 indexer"
+            )));
+        }
+        return parse_decimals(dec);
+    };
+    let info = indexer
+        .get_token_info(tick)
+        .await?
+        .ok_or_else(|| Error::Custom(format!("Token {tick} not found")))?;
+    let indexed = parse_decimals(&info.dec)?;
+    if !dec.is_empty() && parse_decimals(dec)? != indexed {
+        return Err(Error::Custom(format!(
+            "Token decimals {dec} do not match the {indexed} decimals of {tick}"
+        )));
+    }
+    Ok(indexed)
+}
+
+/// Checks `op` against the indexer before any fees are spent: transfers and
+/// listings need an existing token and enough balance at `sender`, mints need
+/// a token that is not fully minted, and mint-mode deploys need an unused
+/// ticker. Issue-mode ops need `sender` to be the current owner of the token.
+pub async fn preflight(indexer: &IndexerClient, op: &Krc20Op, sender: &Address) -> Result<()> {
+    let Some(tick) = op.token() else {
+        return Ok(());
+    };
+    let info = indexer
+        .get_token_info(tick)
+        .await?
+        .filter(|info| !info.state.is_empty() && info.state != "unused");
+
+    match op {
+        Krc20Op::Deploy(deploy) if deploy.mode.is_none() => {
+            if let Some(info) = info {
+                return Err(Error::Custom(format!(
+                    "Ticker {} is already deployed (state: {})",
+                    tick, info.state
+                )));
+            }
+        }
+        Krc20Op::Mint(_) => {
+            let info =
+                info.ok_or_else(|| Error::Custom(format!("Ticker {} is not deployed", tick)))?;
+            if let Some(reason) = mint_out_reason(&info)? {
+                return Err(Error::Custom(reason));
+            }
+        }
+        Krc20Op::Transfer(Transfer { amt, .. }) | Krc20Op::List(List { amt, .. }) => {
+            info.ok_or_else(|| Error::Custom(format!("Ticker {} is not deployed", tick)))?;
+            let balance = match indexer.get_balance(&sender.to_string(), tick).await? {
+                Some(balance) => parse_number(&balance.balance, "balance")?,
+                None => BigInt::from(0),
+            };
+            if balance < *amt {
+                return Err(Error::Custom(format!(
+                    "Insufficient {} balance at {}: have {}, need {}",
+                    tick, sender, balance, amt
+                )));
+            }
+        }
+        Krc20Op::Issue(_) | Krc20Op::Burn(_) | Krc20Op::Chown(_) | Krc20Op::Blacklist(_) => {
+            let info =
+                info.ok_or_else(|| Error::Custom(format!("Contract {} is not deployed", tick)))?;
+            if info.mode != "issue" {
+                return Err(Error::Custom(format!(
+                    "Token {} is not an issue-mode token",
+                    tick
+                )));
+            }
+            if info.owner != sender.to_string() {
+                return Err(Error::Custom(format!(
+                    "{} is not the owner of {} (owner: {})",
+                    sender, tick, info.owner
+                )));
+            }
+            match op {
+                Krc20Op::Issue(issue) => {
+                    let max = parse_number(&info.max, "max")?;
+                    let minted = parse_number(&info.minted, "minted")?;
+                    if minted + &issue.amt > max {
+                        return Err(Error::Custom(format!(
+                            "Issuing {} would exceed the max supply of {}",
+                            issue.amt, tick
+                        )));
+                    }
+                }
+                Krc20Op::Burn(burn) => {
+                    let balance = match indexer.get_balance(&sender.to_string(), tick).await? {
+                        Some(balance) => parse_number(&balance.balance, "balance")?,
+                        None => BigInt::from(0),
+                    };
+                    if balance < burn.amt {
+                        return Err(Error::Custom(format!(
+                            "Insufficient {} balance at {}: have {}, need {}",
+                            tick, sender, balance, burn.amt
+                        )));
+                    }
+                }
+                _ => {}
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Returns why no further mint of the token described by `info` can succeed:
+/// it is finished, or less than one `lim` of its supply is left. Returns
+/// `None` if another mint fits.
+fn mint_out_reason(info: &TokenInfo) -> Result<Option<String>> {
+    let max = parse_number(&info.max, "max")?;
+    let minted = parse_number(&info.minted, "minted")?;
+    let lim = parse_number(&info.lim, "lim")?;
+    if info.state == "finished" || minted >= max {
+        return Ok(Some(format!("Ticker {} is fully minted", info.tick)));
+    }
+    let remaining = max - minted;
+    if remaining < lim {
+        return Ok(Some(format!(
+            "Ticker {} has {} left to mint, less than one mint of {}",
+            info.tick, remaining, lim
+        )));
+    }
+    Ok(None)
+}
+
+/// Checks the minted supply of `tick` before another mint round. Returns why
+/// minting should stop, or `None` to go on. The indexer lags behind the DAG,
+/// so mints still in flight are not counted; a failed lookup is reported and
+/// does not stop minting.
+pub async fn check_mint_out(indexer: &IndexerClient, tick: &str) -> Option<String> {
+    match indexer.get_token_info(tick).await {
+        Ok(Some(info)) => mint_out_reason(&info).unwrap_or_else(|e| {
+            println!("Could not check the supply of {tick}: {e}");
+            None
+        }),
+        Ok(None) => None,
+        Err(e) => {
+            println!("Could not check the supply of {tick}: {e}");
+            None
+        }
+    }
+}
+
+/// Polls the indexer every `interval` until the deploy of `tick` is
+/// confirmed, i.e. the token is known and not `unused`. Fails once `timeout`
+/// elapses without a confirmed deploy.
+pub async fn wait_for_deploy(
+    indexer: &IndexerClient,
+    tick: &str,
+    timeout: Duration,
+    interval: Duration,
+) -> Result<()> {
+    println!("Waiting for the deploy of {tick} to be confirmed...");
+    let started = Instant::now();
+    while started.elapsed() < timeout {
+        match indexer.get_token_info(tick).await {
+            Ok(Some(info)) if !info.state.is_empty() && info.state != "unused" => {
+                println!("{tick} is deployed (state: {})", info.state);
+                return Ok(());
+            }
+            Ok(_) => {}
+            Err(e) => println!("Could not check the deploy of {tick}: {e}"),
+        }
+        tokio::time::sleep(interval).await;
+    }
+    Err(Error::Custom(format!(
+        "Deploy of {tick} not confirmed after {} seconds",
+        timeout.as_secs()
+    )))
+}
+
+pub fn keypair_from_hex(private_key_hex: &str) -> Result<Keypair> {
+    let private_key = PrivateKey::try_new(private_key_hex)
+        .map_err(|e| Error::Custom(format!("Failed to create private key: {}", e)))?;
+    let secp = Secp256k1::new();
+    Keypair::from_seckey_slice(&secp, &private_key.secret_bytes())
+        .map_err(|e| Error::Custom(format!("Failed to create keypair: {}", e)))
+}
+
+/// Parses a KAS amount (e.g. "0.3"), treating an empty string as zero.
+pub fn parse_kaspa(value: &str, name: &str) -> Result<u64> {
+    match try_kaspa_str_to_sompi(value) {
+        Ok(sompi) => Ok(sompi.unwrap_or_default()),
+        Err(e) => Err(Error::Custom(format!("Invalid {}: {}", name, e))),
+    }
+}
+
+/// Transfers KRC-20 tokens. The amount is given in token units and scaled by
+/// the token decimals into the atomic amount the protocol expects.
+///
+/// Transfer file layout, one value per line:
+/// private key, network, ticker, priority fee (KAS), timeout (ms), log level,
+/// destination address, amount, optional token decimals (read from the
+/// indexer, required without one). The network, timeout and log level lines
+/// are kept for older files and ignored; the connection uses the command
+/// line settings.
+pub async fn transfer_krc20_tokens(
+    client: &KaspaRpcClient,
+    indexer: Option<&IndexerClient>,
+    network: NetworkType,
+    transfer_args: Vec<String>,
+) -> Result<(TransactionId, TransactionId)> {
+    if transfer_args.len() < 8 {
+        return Err(Error::Custom(format!(
+            "Expected at least 8 lines of transfer arguments, found {}",
+            transfer_args.len()
+        )));
+    }
 
     // Parse transfer arguements
     let private_key_arg = transfer_args[0].clone();
     let ticker = transfer_args[2].clone();
     let priority_fee_value = transfer_args[3].clone();
-    let timeout = transfer_args[4].clone().parse().unwrap_or(120_000);
-    let log_level = transfer_args[5].clone();
     let dest = transfer_args[6].clone();
     let amount = transfer_args[7].clone();
+    let dec = token_decimals(
+        indexer,
+        &ticker,
+        transfer_args.get(8).map(String::as_str).unwrap_or(""),
+    )
+    .await?;
+
+    let keypair = keypair_from_hex(&private_key_arg)?;
+    let prefix = Prefix::from(network);
+    let (x_only_public_key, _) = keypair.x_only_public_key();
+    let address = Address::new(prefix, Version::PubKey, &x_only_public_key.serialize());
+    println!("Determining address: {address}");
+
+    let fee = parse_kaspa(&priority_fee_value, "priority fee")?;
+    let op = Krc20Op::Transfer(Transfer::new(&ticker, &amount, dec, &dest)?);
+    if let Some(indexer) = indexer {
+        preflight(indexer, &op, &address).await?;
+    }
+    let content = Content::krc20(&op)?;
+    println!("Inscribing: {content}");
+
+    let fees = Fees {
+        priority_fee: fee,
+        protocol_fee: FeeSchedule::load(network)?.fee(&op),
+    };
+    let inscribed = commit_reveal(client, &keypair, prefix, &content, fees).await?;
+    Ok((inscribed.commit_id, inscribed.reveal_id))
+}
 
-    let url: Option<&str> = None;
-    let resolver = Some(Resolver::default());
-    let selected_network = match network {
-        NetworkType::Mainnet => Some(NetworkId::new(network)),
-        NetworkType::Testnet => Some(NetworkId::with_suffix(network, 10)),
-        NetworkType::Devnet => Some(NetworkId::new(network)),
-        NetworkType::Simnet => Some(NetworkId::new(network)),
+/// Mints a KRC-20 token `repeat` times, stopping at the first failed round.
+/// With an indexer, the token's supply is checked before every round and
+/// minting stops once less than one `lim` is left, and `wait` holds the first
+/// round until the token's deploy is confirmed. `preflight_indexer` checks
+/// the mint before any fees are spent.
+///
+/// Mint file layout, one value per line:
+/// private key, ticker, repeat count, priority fee (KAS), optional `to` address,
+/// optional `wait`, optional deploy wait timeout (seconds, defaults to 300).
+pub async fn mint_krc20_tokens(
+    client: &KaspaRpcClient,
+    preflight_indexer: Option<&IndexerClient>,
+    indexer: Option<&IndexerClient>,
+    network: NetworkType,
+    mint_args: Vec<String>,
+) -> Result<Vec<TransactionId>> {
+    if mint_args.len() < 4 {
+        return Err(Error::Custom(format!(
+            "Expected at least 4 lines of mint arguments, found {}",
+            mint_args.len()
+        )));
+    }
+
+    let private_key_arg = mint_args[0].clone();
+    let ticker = mint_args[1].clone();
+    let repeat: u32 = mint_args[2]
+        .trim()
+        .parse()
+        .map_err(|e| Error::Custom(format!("Invalid repeat count: {}", e)))?;
+    let priority_fee_value = mint_args[3].clone();
+    let to = mint_args
+        .get(4)
+        .map(|to| to.trim().to_string())
+        .filter(|to| !to.is_empty());
+    let wait = match mint_args.get(5).map(|wait| wait.trim()) {
+        None | Some("") => false,
+        Some(wait) if wait.eq_ignore_ascii_case("wait") => true,
+        Some(wait) => {
+            return Err(Error::Custom(format!(
+                "Invalid mint option '{}': expected 'wait' or nothing",
+                wait
+            )))
+        }
+    };
+    let wait_timeout = match mint_args.get(6).map(|timeout| timeout.trim()) {
+        None | Some("") => DEPLOY_WAIT_TIMEOUT,
+        Some(timeout) => timeout
+            .parse()
+            .map_err(|e| Error::Custom(format!("Invalid deploy wait timeout: {}", e)))?,
+    };
+
+    let keypair = keypair_from_hex(&private_key_arg)?;
+    let prefix = Prefix::from(network);
+    let (x_only_public_key, _) = keypair.x_only_public_key();
+    let address = Address::new(prefix, Version::PubKey, &x_only_public_key.serialize());
+    println!("Determining address: {address}");
+
+    let fee = parse_kaspa(&priority_fee_value, "priority fee")?;
+    let op = Krc20Op::Mint(Mint::new(&ticker, to.as_deref())?);
+    let tick = op.token().unwrap_or_default().to_string();
+    let fees = Fees {
+        priority_fee: fee,
+        protocol_fee: FeeSchedule::load(network)?.fee(&op),
     };
-    let subscription_context: Option<SubscriptionContext> = None;
-
-    println!("wRPC - connecting to {}...", network);
-    let client = KaspaRpcClient::new(
-        encoding,
-        url,
-        resolver,
-        selected_network,
-        subscription_context,
+    if wait {
+        let indexer = indexer.ok_or_else(|| {
+            Error::Custom("Waiting for a deploy needs a KRC-20 indexer".to_string())
+        })?;
+        wait_for_deploy(
+            indexer,
+            &tick,
+            Duration::from_secs(wait_timeout),
+            DEPLOY_POLL_INTERVAL,
+        )
+        .await?;
+    }
+    if let Some(indexer) = preflight_indexer {
+        preflight(indexer, &op, &address).await?;
+    }
+    let content = Content::krc20(&op)?;
+    println!("Inscribing {repeat} times: {content}");
+
+    let watch = indexer.map(|indexer| (indexer, tick.as_str()));
+    mint_rounds(
+        client,
+        &keypair,
+        prefix,
+        &content,
+        vec![],
+        fees,
+        repeat,
+        watch,
+    )
+    .await
+}
+
+/// Inscribes `content` `repeat` times, each reveal also paying `outputs`. Each
+/// round's reveal change funds the next commit, so wallet UTXOs are only
+/// queried once, and the balance is checked against all rounds up front.
+/// Stops at the first failed round, or before a round once
+/// `watch`, an indexer and a KRC-20 ticker, reports the token minted out.
+#[allow(clippy::too_many_arguments)]
+async fn mint_rounds(
+    client: &KaspaRpcClient,
+    keypair: &Keypair,
+    prefix: Prefix,
+    content: &Content,
+    outputs: Vec<TransactionOutput>,
+    fees: Fees,
+    repeat: u32,
+    watch: Option<(&IndexerClient, &str)>,
+) -> Result<Vec<TransactionId>> {
+    let (x_only_public_key, _) = keypair.x_only_public_key();
+    let address = Address::new(prefix, Version::PubKey, &x_only_public_key.serialize());
+
+    let mut utxos = fetch_utxos(client, &address).await?;
+    if utxos.is_empty() {
+        return Err(Error::Custom(format!(
+            "No UTXOs available for address: {address}"
+        )));
+    }
+
+    // Fail before the first commit rather than part way through the rounds.
+    let input_values: Vec<u64> = utxos.iter().map(|(_, entry)| entry.amount).collect();
+    let cost = CostBreakdown::estimate(
+        &input_values,
+        &content.redeem_script(&x_only_public_key.serialize())?,
+        &outputs,
+        &pay_to_address_script(&address),
+        fees.priority_fee,
+        fees.protocol_fee,
+    );
+    cost.check_balance_for(input_values.iter().sum(), repeat)?;
+
+    let mut reveal_ids = Vec::new();
+    for round in 1..=repeat {
+        if let Some((indexer, tick)) = watch {
+            if let Some(reason) = check_mint_out(indexer, tick).await {
+                println!("Stopping before mint {round}/{repeat}: {reason}");
+                break;
+            }
+        }
+        match inscribe(
+            client,
+            keypair,
+            prefix,
+            utxos,
+            content,
+            outputs.clone(),
+            fees,
+        )
+        .await
+        {
+            Ok(inscribed) => {
+                println!("Mint {round}/{repeat} reveal: {}", inscribed.reveal_id);
+                reveal_ids.push(inscribed.reveal_id);
+                utxos = vec![inscribed.change];
+            }
+            Err(e) => {
+                return Err(Error::Custom(format!(
+                    "Mint {round}/{repeat} failed after {} successful mints ({}): {}",
+                    reveal_ids.len(),
+                    reveal_ids
+                        .iter()
+                        .map(|id| id.to_string())
+                        .collect::<Vec<_>>()
+                        .join(", "),
+                    e
+                )))
+            }
+        }
+    }
+
+    Ok(reveal_ids)
+}
+
+/// Deploys a KRC-20 token. All parameters are validated before any KAS is
+/// spent, and the reveal pays the deploy fee of the network's fee schedule.
+///
+/// Deploy file layout, one value per line:
+/// private key, ticker, max, lim, dec, priority fee (KAS), optional pre,
+/// optional `to` address.
+pub async fn deploy_krc20_token(
+    client: &KaspaRpcClient,
+    indexer: Option<&IndexerClient>,
+    network: NetworkType,
+    deploy_args: Vec<String>,
+) -> Result<(TransactionId, TransactionId)> {
+    if deploy_args.len() < 6 {
+        return Err(Error::Custom(format!(
+            "Expected at least 6 lines of deploy arguments, found {}",
+            deploy_args.len()
+        )));
+    }
+
+    let deploy = Deploy::new(
+        &deploy_args[1],
+        &deploy_args[2],
+        &deploy_args[3],
+        &deploy_args[4],
+        deploy_args.get(6).map(String::as_str),
+        deploy_args.get(7).map(String::as_str),
     )?;
+    let keypair = keypair_from_hex(&deploy_args[0])?;
+    let prefix = Prefix::from(network);
+    let (x_only_public_key, _) = keypair.x_only_public_key();
+    let address = Address::new(prefix, Version::PubKey, &x_only_public_key.serialize());
+    println!("Determining address: {address}");
+
+    let fee = parse_kaspa(&deploy_args[5], "priority fee")?;
+    let op = Krc20Op::Deploy(deploy);
+    let inscribed = inscribe_op(client, indexer, &keypair, network, &op, fee).await?;
+    Ok((inscribed.commit_id, inscribed.reveal_id))
+}
+
+/// Checks `op` against the indexer, if any, and inscribes it in a single
+/// commit/reveal round from the wallet of `keypair`.
+async fn inscribe_op(
+    client: &KaspaRpcClient,
+    indexer: Option<&IndexerClient>,
+    keypair: &Keypair,
+    network: NetworkType,
+    op: &Krc20Op,
+    priority_fee: u64,
+) -> Result<Inscribed> {
+    let prefix = Prefix::from(network);
+    let (x_only_public_key, _) = keypair.x_only_public_key();
+    let address = Address::new(prefix, Version::PubKey, &x_only_public_key.serialize());
+    if let Some(indexer) = indexer {
+        preflight(indexer, op, &address).await?;
+    }
+    let content = Content::krc20(op)?;
+    println!("Inscribing: {content}");
 
-    let options = ConnectOptions {
-        block_async_connect: true,
-        connect_timeout: Some(Duration::from_millis(timeout)),
-        strategy: ConnectStrategy::Fallback,
-        ..Default::default()
+    let fees = Fees {
+        priority_fee,
+        protocol_fee: FeeSchedule::load(network)?.fee(op),
     };
+    commit_reveal(client, keypair, prefix, &content, fees).await
+}
 
-    client.connect(Some(options)).await?;
+/// Deploys an issue-mode KRC-20 token, whose owner mints on demand with
+/// `issue`. The deploy reveal id becomes the token's contract address (`ca`).
+///
+/// Deploy file layout, one value per line:
+/// private key, name, max, dec, priority fee (KAS), optional pre, optional
+/// owner address (defaults to the deployer).
+pub async fn deploy_issue_krc20_token(
+    client: &KaspaRpcClient,
+    indexer: Option<&IndexerClient>,
+    network: NetworkType,
+    deploy_args: Vec<String>,
+) -> Result<(TransactionId, TransactionId)> {
+    if deploy_args.len() < 5 {
+        return Err(Error::Custom(format!(
+            "Expected at least 5 lines of deploy arguments, found {}",
+            deploy_args.len()
+        )));
+    }
 
-    let GetServerInfoResponse {
-        is_synced,
-        server_version,
-        network_id,
-        has_utxo_index,
-        ..
-    } = client.get_server_info().await?;
+    let deploy = Deploy::new_issue(
+        &deploy_args[1],
+        &deploy_args[2],
+        &deploy_args[3],
+        deploy_args.get(5).map(String::as_str),
+        deploy_args.get(6).map(String::as_str),
+    )?;
+    let keypair = keypair_from_hex(&deploy_args[0])?;
+    let prefix = Prefix::from(network);
+    let (x_only_public_key, _) = keypair.x_only_public_key();
+    let address = Address::new(prefix, Version::PubKey, &x_only_public_key.serialize());
+    println!("Determining address: {address}");
 
-    println!("Node version: {server_version}");
-    println!("Network: {network_id}");
+    let fee = parse_kaspa(&deploy_args[4], "priority fee")?;
+    let op = Krc20Op::Deploy(deploy);
+    let inscribed = inscribe_op(client, indexer, &keypair, network, &op, fee).await?;
+    Ok((inscribed.commit_id, inscribed.reveal_id))
+}
 
-    println!("Node is synced: {is_synced}");
-    println!("Node is indexing: {has_utxo_index}");
+/// Runs an owner-only op on an issue-mode KRC-20 token. With an indexer the
+/// signing key is checked to be the current owner before anything is spent.
+///
+/// Owner file layout, one value per line:
+/// private key, op (`issue`, `burn`, `chown`, `blacklist-add` or
+/// `blacklist-remove`), contract address, priority fee (KAS), then
+/// - issue: amount, optional recipient (defaults to the owner), optional
+///   token decimals (read from the indexer, required without one)
+/// - burn: amount, optional token decimals (read from the indexer, required
+///   without one)
+/// - chown: new owner address
+/// - blacklist-add / blacklist-remove: address
+pub async fn krc20_owner_op(
+    client: &KaspaRpcClient,
+    indexer: Option<&IndexerClient>,
+    network: NetworkType,
+    owner_args: Vec<String>,
+) -> Result<(TransactionId, TransactionId)> {
+    if owner_args.len() < 5 {
+        return Err(Error::Custom(format!(
+            "Expected at least 5 lines of owner op arguments, found {}",
+            owner_args.len()
+        )));
+    }
 
-    let private_key = PrivateKey::try_new(&private_key_arg);
-    println!("Suibmitting private key: {:#?}", private_key);
+    let keypair = keypair_from_hex(&owner_args[0])?;
+    let ca = owner_args[2].as_str();
+    let fee = parse_kaspa(&owner_args[3], "priority fee")?;
+    let arg = |index: usize| owner_args.get(index).map(String::as_str);
+    let op = match owner_args[1].trim() {
+        "issue" => Krc20Op::Issue(Issue::new(
+            ca,
+            &owner_args[4],
+            token_decimals(indexer, ca, arg(6).unwrap_or("")).await?,
+            arg(5),
+        )?),
+        "burn" => Krc20Op::Burn(Burn::new(
+            ca,
+            &owner_args[4],
+            token_decimals(indexer, ca, arg(5).unwrap_or("")).await?,
+        )?),
+        "chown" => Krc20Op::Chown(Chown::new(ca, &owner_args[4])?),
+        "blacklist-add" => {
+            Krc20Op::Blacklist(Blacklist::new(ca, BlacklistMode::Add, &owner_args[4])?)
+        }
+        "blacklist-remove" => {
+            Krc20Op::Blacklist(Blacklist::new(ca, BlacklistMode::Remove, &owner_args[4])?)
+        }
+        op => {
+            return Err(Error::Custom(format!(
+            "Invalid owner op '{}': expected issue, burn, chown, blacklist-add or blacklist-remove",
+            op
+        )))
+        }
+    };
 
-    println!("Determining public key");
-    let public_key = private_key
-        .expect("Failed to create private key")
-        .to_public_key();
-    let address = public_key.ok().unwrap().to_address(network);
-    println!("Determining address: {:#?}", address);
+    let prefix = Prefix::from(network);
+    let (x_only_public_key, _) = keypair.x_only_public_key();
+    let address = Address::new(prefix, Version::PubKey, &x_only_public_key.serialize());
+    println!("Determining address: {address}");
 
-    let gas_fee = 0.3;
+    let inscribed = inscribe_op(client, indexer, &keypair, network, &op, fee).await?;
+    Ok((inscribed.commit_id, inscribed.reveal_id))
+}
 
-    /*
-    let mut script = ScriptBuilder::new()
-        .add_data(public_key.to_x_only_public_key())?
-        .add_op(OpCheckSig)?
-        .add_op(OpFalse)?
-        .add_op(OpIf)?;
-    */
+/// Deploys a KRC-721 collection. The deploy JSON is validated locally before
+/// any KAS is spent, and the reveal pays the KRC-721 deploy fee.
+///
+/// Deploy file layout, one value per line:
+/// private key, collection ticker, max supply, buri or path to a `.json`
+/// metadata file, priority fee (KAS), optional royalty fee (KAS), optional
+/// royalty address, optional premint, optional `to` address.
+pub async fn deploy_krc721_collection(
+    client: &KaspaRpcClient,
+    network: NetworkType,
+    deploy_args: Vec<String>,
+) -> Result<(TransactionId, TransactionId)> {
+    if deploy_args.len() < 5 {
+        return Err(Error::Custom(format!(
+            "Expected at least 5 lines of KRC-721 deploy arguments, found {}",
+            deploy_args.len()
+        )));
+    }
 
-    client.disconnect().await?;
+    let keypair = keypair_from_hex(&deploy_args[0])?;
+    let fee = parse_kaspa(&deploy_args[4], "priority fee")?;
+    let royalty_fee = parse_kaspa(
+        deploy_args.get(5).map(String::as_str).unwrap_or(""),
+        "royalty fee",
+    )?;
+    let deploy = krc721::Deploy::new(
+        &deploy_args[1],
+        &deploy_args[2],
+        &deploy_args[3],
+        Some(royalty_fee),
+        deploy_args.get(6).map(String::as_str),
+        deploy_args.get(7).map(String::as_str),
+        deploy_args.get(8).map(String::as_str),
+    )?;
+    let op = Krc721Op::Deploy(deploy);
+    // The inscribed JSON must pass the same checks an indexer applies
+    let content = Content::krc721(&op)?;
+    Krc721Op::from_json(&String::from_utf8_lossy(&content.body))?;
 
-    Ok(())
+    let prefix = Prefix::from(network);
+    let (x_only_public_key, _) = keypair.x_only_public_key();
+    let address = Address::new(prefix, Version::PubKey, &x_only_public_key.serialize());
+    println!("Determining address: {address}");
+    println!("Inscribing: {content}");
+
+    let fees = Fees {
+        priority_fee: fee,
+        protocol_fee: FeeSchedule::load(network)?.krc721_fee(&op),
+    };
+    let inscribed = commit_reveal(client, &keypair, prefix, &content, fees).await?;
+    Ok((inscribed.commit_id, inscribed.reveal_id))
+}
+
+/// Mints tokens of a KRC-721 collection `repeat` times, stopping at the first
+/// failed round. If the collection charges a royalty, every mint reveal pays
+/// it to the royalty address set by the deploy.
+///
+/// Mint file layout, one value per line:
+/// private key, collection ticker, repeat count, priority fee (KAS), optional
+/// `to` address, optional royalty fee (KAS), optional royalty address.
+pub async fn mint_krc721_tokens(
+    client: &KaspaRpcClient,
+    network: NetworkType,
+    mint_args: Vec<String>,
+) -> Result<Vec<TransactionId>> {
+    if mint_args.len() < 4 {
+        return Err(Error::Custom(format!(
+            "Expected at least 4 lines of KRC-721 mint arguments, found {}",
+            mint_args.len()
+        )));
+    }
+
+    let keypair = keypair_from_hex(&mint_args[0])?;
+    let repeat: u32 = mint_args[2]
+        .trim()
+        .parse()
+        .map_err(|e| Error::Custom(format!("Invalid repeat count: {}", e)))?;
+    let fee = parse_kaspa(&mint_args[3], "priority fee")?;
+    let op = Krc721Op::Mint(krc721::Mint::new(
+        &mint_args[1],
+        mint_args.get(4).map(String::as_str),
+    )?);
+    let royalty_fee = parse_kaspa(
+        mint_args.get(5).map(String::as_str).unwrap_or(""),
+        "royalty fee",
+    )?;
+    let royalty_to = mint_args
+        .get(6)
+        .map(|to| to.trim())
+        .filter(|to| !to.is_empty());
+    let outputs = match (royalty_fee, royalty_to) {
+        (0, None) => vec![],
+        (fee, Some(to)) if fee > 0 => {
+            let to = Address::try_from(to)
+                .map_err(|e| Error::Custom(format!("Invalid royalty address: {}", e)))?;
+            vec![TransactionOutput::new(fee, pay_to_address_script(&to))]
+        }
+        _ => {
+            return Err(Error::Custom(
+                "Expected both a royalty fee and a royalty address, or neither".to_string(),
+            ))
+        }
+    };
+
+    let prefix = Prefix::from(network);
+    let (x_only_public_key, _) = keypair.x_only_public_key();
+    let address = Address::new(prefix, Version::PubKey, &x_only_public_key.serialize());
+    println!("Determining address: {address}");
+
+    let fees = Fees {
+        priority_fee: fee,
+        protocol_fee: FeeSchedule::load(network)?.krc721_fee(&op),
+    };
+    let content = Content::krc721(&op)?;
+    println!("Inscribing {repeat} times: {content}");
+
+    mint_rounds(
+        client, &keypair, prefix, &content, outputs, fees, repeat, None,
+    )
+    .await
+}
+
+/// Transfers a single KRC-721 token by its token id.
+///
+/// Transfer file layout, one value per line:
+/// private key, collection ticker, token id, destination address, priority
+/// fee (KAS).
+pub async fn transfer_krc721_token(
+    client: &KaspaRpcClient,
+    network: NetworkType,
+    transfer_args: Vec<String>,
+) -> Result<(TransactionId, TransactionId)> {
+    if transfer_args.len() < 5 {
+        return Err(Error::Custom(format!(
+            "Expected at least 5 lines of KRC-721 transfer arguments, found {}",
+            transfer_args.len()
+        )));
+    }
+
+    let keypair = keypair_from_hex(&transfer_args[0])?;
+    let op = Krc721Op::Transfer(krc721::Transfer::new(
+        &transfer_args[1],
+        &transfer_args[2],
+        &transfer_args[3],
+    )?);
+    let fee = parse_kaspa(&transfer_args[4], "priority fee")?;
+
+    let prefix = Prefix::from(network);
+    let (x_only_public_key, _) = keypair.x_only_public_key();
+    let address = Address::new(prefix, Version::PubKey, &x_only_public_key.serialize());
+    println!("Determining address: {address}");
+
+    let content = Content::krc721(&op)?;
+    println!("Inscribing: {content}");
+
+    let fees = Fees {
+        priority_fee: fee,
+        protocol_fee: FeeSchedule::load(network)?.krc721_fee(&op),
+    };
+    let inscribed = commit_reveal(client, &keypair, prefix, &content, fees).await?;
+    Ok((inscribed.commit_id, inscribed.reveal_id))
+}
+
+/// Inscribes arbitrary content, such as plain text, JSON or a small image,
+/// under any protocol tag. Bodies longer than a script push are split across
+/// several pushes.
+///
+/// Inscribe file layout, one value per line:
+/// private key, protocol tag (e.g. `kasplex`), content type (may be empty),
+/// path to the content file, priority fee (KAS), optional protocol fee (KAS).
+pub async fn inscribe_data(
+    client: &KaspaRpcClient,
+    network: NetworkType,
+    inscribe_args: Vec<String>,
+) -> Result<(TransactionId, TransactionId)> {
+    if inscribe_args.len() < 5 {
+        return Err(Error::Custom(format!(
+            "Expected at least 5 lines of inscribe arguments, found {}",
+            inscribe_args.len()
+        )));
+    }
+
+    let keypair = keypair_from_hex(&inscribe_args[0])?;
+    let protocol = inscribe_args[1].trim();
+    if protocol.is_empty() {
+        return Err(Error::Custom("Protocol tag must not be empty".to_string()));
+    }
+    let path = inscribe_args[3].trim();
+    let body =
+        fs::read(path).map_err(|e| Error::Custom(format!("Failed to read {}: {}", path, e)))?;
+    let content = Content::new(protocol.as_bytes(), Some(&inscribe_args[2]), &body);
+    let fees = Fees {
+        priority_fee: parse_kaspa(&inscribe_args[4], "priority fee")?,
+        protocol_fee: parse_kaspa(
+            inscribe_args.get(5).map(String::as_str).unwrap_or(""),
+            "protocol fee",
+        )?,
+    };
+
+    let prefix = Prefix::from(network);
+    let (x_only_public_key, _) = keypair.x_only_public_key();
+    let address = Address::new(prefix, Version::PubKey, &x_only_public_key.serialize());
+    println!("Determining address: {address}");
+    println!("Inscribing: {content}");
+
+    let inscribed = commit_reveal(client, &keypair, prefix, &content, fees).await?;
+    Ok((inscribed.commit_id, inscribed.reveal_id))
+}
+
+/// Spends the P2SH outputs of commits that were never revealed, as recorded in
+/// the reveal journal. In `reveal` mode the reveal pays the op's protocol fee
+/// so the op completes. In `refund` mode only the priority fee is paid and the
+/// rest returns to the wallet; the envelope is still exposed, but ops that
+/// require a protocol fee are rejected by the indexer. A reveal also pays the
+/// journaled outputs of the original reveal, such as a listing's P2SH output
+/// or a KRC-721 mint royalty, topped up from the wallet. Protocol fees of
+/// generic inscriptions are not journaled, so such commits should be refunded.
+///
+/// Recover file layout, one value per line:
+/// private key, optional mode (`reveal` or `refund`, defaults to `reveal`),
+/// optional priority fee (KAS).
+pub async fn recover(
+    client: &KaspaRpcClient,
+    network: NetworkType,
+    recover_args: Vec<String>,
+) -> Result<Vec<TransactionId>> {
+    if recover_args.is_empty() {
+        return Err(Error::Custom(
+            "Expected a private key on the first line of the recover file".to_string(),
+        ));
+    }
+
+    let keypair = keypair_from_hex(&recover_args[0])?;
+    let refund = match recover_args.get(1).map(|mode| mode.trim()) {
+        None | Some("") | Some("reveal") => false,
+        Some("refund") => true,
+        Some(mode) => {
+            return Err(Error::Custom(format!(
+                "Invalid recover mode '{}': expected reveal or refund",
+                mode
+            )))
+        }
+    };
+    let fee = parse_kaspa(
+        recover_args.get(2).map(String::as_str).unwrap_or(""),
+        "priority fee",
+    )?;
+
+    let prefix = Prefix::from(network);
+    let (x_only_public_key, _) = keypair.x_only_public_key();
+    let address = Address::new(prefix, Version::PubKey, &x_only_public_key.serialize());
+    let schedule = FeeSchedule::load(network)?;
+
+    let journal = Journal::new(JOURNAL_FILE);
+    let pending: Vec<JournalEntry> = journal
+        .pending()?
+        .into_iter()
+        .filter(|entry| entry.key == address.to_string())
+        .collect();
+    println!("Found {} pending commits for {address}", pending.len());
+
+    let mut spent = Vec::new();
+    for entry in pending {
+        let commit_address = Address::try_from(entry.p2sh_address.as_str())
+            .map_err(|e| Error::Custom(format!("Invalid journal P2SH address: {}", e)))?;
+        let commit_id: TransactionId = entry
+            .commit_id
+            .parse()
+            .map_err(|e| Error::Custom(format!("Invalid journal commit id: {}", e)))?;
+        let redeem_script = hex::decode(&entry.redeem_script)
+            .map_err(|e| Error::Custom(format!("Invalid journal redeem script: {}", e)))?;
+
+        let commit_outpoint = TransactionOutpoint::new(commit_id, entry.output_index);
+        let Some(commit_utxo) = fetch_utxos(client, &commit_address)
+            .await?
+            .into_iter()
+            .find(|(outpoint, _)| *outpoint == commit_outpoint)
+        else {
+            println!(
+                "No UTXO for commit {commit_outpoint} at {commit_address} (not accepted yet or already spent)"
+            );
+            continue;
+        };
+
+        let outputs: Vec<TransactionOutput> = if refund {
+            vec![]
+        } else {
+            entry
+                .outputs
+                .iter()
+                .map(JournalOutput::to_output)
+                .collect::<Result<_>>()?
+        };
+        // The P2SH output was only sized for the fees, so outputs are paid
+        // from the wallet as the commit change did originally.
+        let wallet_utxos = if outputs.is_empty() {
+            vec![]
+        } else {
+            fetch_utxos(client, &address).await?
+        };
+        let (protocol_fee, status) = if refund {
+            (0, EntryStatus::Refunded)
+        } else {
+            let protocol = parse_envelope(&redeem_script)
+                .map(|envelope| envelope.content.protocol)
+                .unwrap_or_default();
+            let protocol_fee = if protocol == ENVELOPE_MARKER {
+                schedule.fee(&Krc20Op::from_json(&entry.op)?)
+            } else if protocol == krc721::ENVELOPE_MARKER {
+                schedule.krc721_fee(&Krc721Op::from_json(&entry.op)?)
+            } else {
+                0
+            };
+            (protocol_fee, EntryStatus::Revealed)
+        };
+        let input_values: Vec<u64> = std::iter::once(&commit_utxo)
+            .chain(&wallet_utxos)
+            .map(|(_, entry)| entry.amount)
+            .collect();
+        let output_values: Vec<u64> = outputs.iter().map(|output| output.value).collect();
+        let (network_fee, _) = fee_and_change(
+            &input_values,
+            &output_values,
+            protocol_fee,
+            reveal_mass(
+                &redeem_script,
+                wallet_utxos.len(),
+                &outputs,
+                &pay_to_address_script(&address),
+            ),
+            fee,
+        );
+        let reveal_tx = build_reveal_transaction(
+            &keypair,
+            commit_utxo,
+            wallet_utxos,
+            &redeem_script,
+            outputs,
+            &address,
+            network_fee,
+            protocol_fee,
+        )?;
+        let reveal_id = client
+            .submit_transaction(RpcTransaction::from(&reveal_tx), false)
+            .await?;
+        println!("Recovered commit {commit_id} with transaction {reveal_id}");
+        journal.update(
+            &entry.commit_id,
+            entry.output_index,
+            status,
+            &reveal_id.to_string(),
+        )?;
+        spent.push(reveal_id);
+    }
+
+    Ok(spent)
 }
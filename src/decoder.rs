@@ -0,0 +1,352 @@
+use kaspa_addresses::{Address, Prefix, Version};
+use kaspa_consensus_core::tx::{PopulatedTransaction, Transaction, TransactionId};
+use kaspa_rpc_core::{api::rpc::RpcApi, RpcHash, RpcTransaction};
+use kaspa_txscript::opcodes::{codes::*, deserialize_next_opcode};
+use kaspa_wrpc_client::{error::Error, result::Result, KaspaRpcClient};
+
+use crate::envelope::Content;
+use crate::krc20::Krc20Op;
+use crate::krc721::{self, Krc721Op};
+
+use std::fs;
+
+/// Marker pushed at the start of every Kasplex envelope.
+pub const ENVELOPE_MARKER: &[u8] = b"kasplex";
+
+/// The contents of an envelope redeem script.
+#[derive(Debug, Clone)]
+pub struct Envelope {
+    /// Public key the script is locked to: 32 bytes x-only (Schnorr) or 33
+    /// bytes compressed (ECDSA).
+    pub public_key: Vec<u8>,
+    /// Protocol tag, content type and body, reassembled from its pushes.
+    pub content: Content,
+}
+
+/// A KRC-20 op found in a reveal transaction.
+#[derive(Debug, Clone)]
+pub struct Inscription {
+    /// Index of the P2SH input whose redeem script carries the envelope.
+    pub input_index: usize,
+    pub public_key: Vec<u8>,
+    pub op: Krc20Op,
+}
+
+impl Inscription {
+    /// Returns the address of the key that signed the reveal.
+    pub fn signer(&self, prefix: Prefix) -> Address {
+        signer_address(&self.public_key, prefix)
+    }
+}
+
+/// Returns the address of an envelope's x-only or compressed public key.
+pub fn signer_address(public_key: &[u8], prefix: Prefix) -> Address {
+    let version = if public_key.len() == 33 {
+        Version::PubKeyECDSA
+    } else {
+        Version::PubKey
+    };
+    Address::new(prefix, version, public_key)
+}
+
+/// Splits `script` into `(opcode, pushed data)` pairs.
+pub fn parse_ops(script: &[u8]) -> Result<Vec<(u8, Vec<u8>)>> {
+    let mut iter = script.iter();
+    let mut ops = Vec::new();
+    while let Some(opcode) = deserialize_next_opcode::<_, PopulatedTransaction<'static>>(&mut iter)
+    {
+        let opcode = opcode.map_err(|e| Error::Custom(format!("Failed to parse script: {}", e)))?;
+        ops.push((opcode.value(), opcode.get_data().to_vec()));
+    }
+    Ok(ops)
+}
+
+/// Parses a redeem script of the form
+/// `<pubkey> OP_CHECKSIG OP_FALSE OP_IF <protocol> [OP_1 <content type>] OP_0
+/// <body>... OP_ENDIF`, returning `None` if it is not an envelope. The body
+/// pushes are concatenated.
+#[allow(non_upper_case_globals)]
+pub fn parse_envelope(redeem_script: &[u8]) -> Option<Envelope> {
+    let ops = parse_ops(redeem_script).ok()?;
+    let (public_key, rest) = match ops.as_slice() {
+        [(_, key), (OpCheckSig, _), rest @ ..] if key.len() == 32 => (key.clone(), rest),
+        [(_, key), (OpCheckSigECDSA, _), rest @ ..] if key.len() == 33 => (key.clone(), rest),
+        _ => return None,
+    };
+    let (protocol, mut fields) = match rest {
+        [(OpFalse, _), (OpIf, _), (opcode, protocol), fields @ .., (OpEndIf, _)]
+            if *opcode <= OpPushData4 && !protocol.is_empty() =>
+        {
+            (protocol, fields)
+        }
+        _ => return None,
+    };
+    let mut content_type = None;
+    let pushes = loop {
+        match fields {
+            [(OpTrue, _), (opcode, value), rest @ ..] if *opcode <= OpPushData4 => {
+                content_type = Some(String::from_utf8(value.clone()).ok()?);
+                fields = rest;
+            }
+            [(OpFalse, _), pushes @ ..] => break pushes,
+            _ => return None,
+        }
+    };
+    if pushes.iter().any(|(opcode, _)| *opcode > OpPushData4) {
+        return None;
+    }
+    let body: Vec<u8> = pushes.iter().flat_map(|(_, data)| data.clone()).collect();
+    Some(Envelope {
+        public_key,
+        content: Content::new(protocol, content_type.as_deref(), &body),
+    })
+}
+
+/// Returns every input of `tx` that spends a P2SH envelope, with its input
+/// index. The redeem script is the last push of a P2SH signature script.
+pub fn find_envelopes(tx: &Transaction) -> Vec<(usize, Envelope)> {
+    tx.inputs
+        .iter()
+        .enumerate()
+        .filter_map(|(index, input)| {
+            let ops = parse_ops(&input.signature_script).ok()?;
+            let (_, redeem_script) = ops.last()?;
+            parse_envelope(redeem_script).map(|envelope| (index, envelope))
+        })
+        .collect()
+}
+
+/// Finds the first input of `tx` that spends a P2SH `kasplex` envelope and
+/// returns the envelope and the input index.
+pub fn find_envelope(tx: &Transaction) -> Option<(usize, Envelope)> {
+    find_envelopes(tx)
+        .into_iter()
+        .find(|(_, envelope)| envelope.content.protocol == ENVELOPE_MARKER)
+}
+
+/// Decodes the KRC-20 op inscribed in the reveal transaction `tx`.
+pub fn decode_inscription(tx: &Transaction) -> Result<Inscription> {
+    let (input_index, envelope) = find_envelope(tx)
+        .ok_or_else(|| Error::Custom(format!("Transaction {} has no kasplex envelope", tx.id())))?;
+    let json = envelope
+        .content
+        .text()
+        .ok_or_else(|| Error::Custom("Inscription is not UTF-8".to_string()))?;
+    Ok(Inscription {
+        input_index,
+        public_key: envelope.public_key,
+        op: Krc20Op::from_json(json)?,
+    })
+}
+
+/// Fetches transaction `id` from the node. Without a `block_hash` only the
+/// mempool is searched, since the node keeps no transaction index.
+pub async fn fetch_transaction(
+    client: &KaspaRpcClient,
+    id: TransactionId,
+    block_hash: Option<RpcHash>,
+) -> Result<Transaction> {
+    match block_hash {
+        Some(block_hash) => {
+            let block = client.get_block(block_hash, true).await?;
+            for rpc_tx in &block.transactions {
+                let tx = Transaction::try_from(rpc_tx)?;
+                if tx.id() == id {
+                    return Ok(tx);
+                }
+            }
+            Err(Error::Custom(format!(
+                "Transaction {} not found in block {}",
+                id, block_hash
+            )))
+        }
+        None => {
+            let entry = client.get_mempool_entry(id, true, false).await?;
+            Ok(Transaction::try_from(&entry.transaction)?)
+        }
+    }
+}
+
+/// Reads a transaction from a JSON file in the node's RPC format.
+pub fn read_transaction(path: &str) -> Result<Transaction> {
+    let contents = fs::read_to_string(path)
+        .map_err(|e| Error::Custom(format!("Failed to read {}: {}", path, e)))?;
+    let rpc_tx: RpcTransaction = serde_json::from_str(&contents)
+        .map_err(|e| Error::Custom(format!("Failed to parse transaction JSON: {}", e)))?;
+    Ok(Transaction::try_from(&rpc_tx)?)
+}
+
+/// Decodes the envelopes of each transaction listed in `decode_args` and
+/// prints their signer, protocol and content. KRC-20 and KRC-721 content is
+/// parsed and validated as an op.
+///
+/// Decode file layout, one transaction per line: a path to a `.json` file
+/// holding the transaction, or a transaction id optionally followed by the
+/// hash of a block containing it (otherwise the mempool is searched).
+pub async fn decode_transactions(
+    client: &KaspaRpcClient,
+    prefix: Prefix,
+    decode_args: Vec<String>,
+) -> Result<Vec<Envelope>> {
+    let mut decoded = Vec::new();
+    for line in decode_args {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let tx = if line.ends_with(".json") {
+            read_transaction(line)?
+        } else {
+            let mut parts = line.split_whitespace();
+            let id: TransactionId = parts
+                .next()
+                .unwrap_or_default()
+                .parse()
+                .map_err(|e| Error::Custom(format!("Invalid transaction id: {}", e)))?;
+            let block_hash = parts
+                .next()
+                .map(|hash| hash.parse::<RpcHash>())
+                .transpose()
+                .map_err(|e| Error::Custom(format!("Invalid block hash: {}", e)))?;
+            fetch_transaction(client, id, block_hash).await?
+        };
+
+        let envelopes = find_envelopes(&tx);
+        if envelopes.is_empty() {
+            return Err(Error::Custom(format!(
+                "Transaction {} has no envelope",
+                tx.id()
+            )));
+        }
+        println!("Transaction: {}", tx.id());
+        for (input_index, envelope) in envelopes {
+            let content = &envelope.content;
+            println!("Input: {input_index}");
+            println!("Signer: {}", signer_address(&envelope.public_key, prefix));
+            println!("Public key: {}", hex::encode(&envelope.public_key));
+            println!("Protocol: {}", String::from_utf8_lossy(&content.protocol));
+            if let Some(content_type) = &content.content_type {
+                println!("Content type: {content_type}");
+            }
+            let text = content.text().unwrap_or_default();
+            if content.protocol == ENVELOPE_MARKER {
+                match Krc20Op::from_json(text) {
+                    Ok(op) => println!("Op: {}", op.to_json()?),
+                    Err(e) => println!("Invalid KRC-20 op: {e}"),
+                }
+            } else if content.protocol == krc721::ENVELOPE_MARKER {
+                match Krc721Op::from_json(text) {
+                    Ok(op) => println!("Op: {}", op.to_json()?),
+                    Err(e) => println!("Invalid KRC-721 op: {e}"),
+                }
+            } else {
+                println!("Content: {content}");
+            }
+            decoded.push(envelope);
+        }
+    }
+    Ok(decoded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::krc20::Mint;
+    use kaspa_consensus_core::subnets::SUBNETWORK_ID_NATIVE;
+    use kaspa_consensus_core::tx::{TransactionInput, TransactionOutpoint};
+    use kaspa_txscript::pay_to_script_hash_signature_script;
+
+    const KEY: [u8; 32] = [7; 32];
+
+    fn signature() -> Vec<u8> {
+        std::iter::once(65u8).chain([0u8; 65]).collect()
+    }
+
+    /// An input spending the P2SH envelope of `content`.
+    fn envelope_input(content: &Content, index: u32) -> TransactionInput {
+        let redeem_script = content.redeem_script(&KEY).unwrap();
+        let signature_script =
+            pay_to_script_hash_signature_script(redeem_script, signature()).unwrap();
+        let outpoint = TransactionOutpoint::new(TransactionId::from_u64_word(1), index);
+        TransactionInput::new(outpoint, signature_script, 0, 1)
+    }
+
+    /// An input spending a plain pay-to-public-key output.
+    fn wallet_input(index: u32) -> TransactionInput {
+        let outpoint = TransactionOutpoint::new(TransactionId::from_u64_word(2), index);
+        TransactionInput::new(outpoint, signature(), 0, 1)
+    }
+
+    fn transaction(inputs: Vec<TransactionInput>) -> Transaction {
+        Transaction::new(0, inputs, vec![], 0, SUBNETWORK_ID_NATIVE, 0, vec![])
+    }
+
+    #[test]
+    fn decodes_krc20_reveals() {
+        let op = Krc20Op::Mint(Mint::new("test", None).unwrap());
+        let tx = transaction(vec![
+            wallet_input(0),
+            envelope_input(&Content::krc20(&op).unwrap(), 0),
+        ]);
+        let inscription = decode_inscription(&tx).unwrap();
+        assert_eq!(inscription.input_index, 1);
+        assert_eq!(inscription.op, op);
+        assert_eq!(
+            inscription.signer(Prefix::Mainnet),
+            Address::new(Prefix::Mainnet, Version::PubKey, &KEY)
+        );
+    }
+
+    #[test]
+    fn skips_envelopes_of_other_protocols() {
+        let content = Content::new(b"kspr", Some("text/plain"), b"hello");
+        let tx = transaction(vec![envelope_input(&content, 0)]);
+        let envelopes = find_envelopes(&tx);
+        assert_eq!(envelopes.len(), 1);
+        assert_eq!(envelopes[0].1.content, content);
+        assert!(find_envelope(&tx).is_none());
+        assert!(decode_inscription(&tx).is_err());
+
+        // A kasplex envelope that is not a valid op is not an inscription
+        let invalid = Content::new(ENVELOPE_MARKER, None, br#"{"p":"krc-20","op":"nope"}"#);
+        let tx = transaction(vec![envelope_input(&invalid, 0)]);
+        assert!(find_envelope(&tx).is_some());
+        assert!(decode_inscription(&tx).is_err());
+    }
+
+    #[test]
+    fn ignores_inputs_without_an_envelope() {
+        // A P2SH spend whose redeem script is a bare `<key> OP_CHECKSIG`
+        let bare: Vec<u8> = std::iter::once(32u8)
+            .chain(KEY)
+            .chain([OpCheckSig])
+            .collect();
+        let signature_script = pay_to_script_hash_signature_script(bare, signature()).unwrap();
+        let outpoint = TransactionOutpoint::new(TransactionId::from_u64_word(3), 0);
+        let tx = transaction(vec![
+            wallet_input(0),
+            TransactionInput::new(outpoint, signature_script, 0, 1),
+        ]);
+        assert!(find_envelopes(&tx).is_empty());
+        let error = decode_inscription(&tx).unwrap_err().to_string();
+        assert!(error.contains("no kasplex envelope"), "{error}");
+    }
+
+    #[test]
+    fn reads_transactions_from_rpc_json() {
+        let op = Krc20Op::Mint(Mint::new("test", None).unwrap());
+        let tx = transaction(vec![envelope_input(&Content::krc20(&op).unwrap(), 0)]);
+        let path = std::env::temp_dir().join(format!("decoder-{}.json", std::process::id()));
+        fs::write(
+            &path,
+            serde_json::to_string(&RpcTransaction::from(&tx)).unwrap(),
+        )
+        .unwrap();
+        let read = read_transaction(path.to_str().unwrap());
+        let _ = fs::remove_file(&path);
+        let read = read.unwrap();
+        assert_eq!(read.id(), tx.id());
+        assert_eq!(decode_inscription(&read).unwrap().op, op);
+        assert!(read_transaction("missing-transaction.json").is_err());
+    }
+}
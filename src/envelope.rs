@@ -0,0 +1,171 @@
+use kaspa_txscript::{
+    opcodes::codes::*,
+    script_builder::{ScriptBuilder, ScriptBuilderResult},
+    MAX_SCRIPTS_SIZE, MAX_SCRIPT_ELEMENT_SIZE,
+};
+use kaspa_wrpc_client::{error::Error, result::Result};
+
+use crate::decoder::ENVELOPE_MARKER;
+use crate::krc20::Krc20Op;
+use crate::krc721::{self, Krc721Op};
+
+use std::fmt;
+
+/// Largest data push allowed in a script. Longer bodies are split across
+/// consecutive pushes.
+pub const MAX_PUSH_SIZE: usize = MAX_SCRIPT_ELEMENT_SIZE;
+
+/// Tag preceding the content type push.
+const CONTENT_TYPE_TAG: i64 = 1;
+/// Tag preceding the body pushes.
+const BODY_TAG: i64 = 0;
+
+/// Arbitrary bytes inscribed in a commit/reveal envelope:
+/// `<pubkey> OP_CHECKSIG OP_FALSE OP_IF <protocol> [OP_1 <content type>] OP_0
+/// <body>... OP_ENDIF`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Content {
+    /// Protocol tag, e.g. `kasplex` for KRC-20 or `kspr` for KRC-721.
+    pub protocol: Vec<u8>,
+    /// MIME type of the body. Omitted by KRC-20 and KRC-721 envelopes.
+    pub content_type: Option<String>,
+    pub body: Vec<u8>,
+}
+
+impl Content {
+    pub fn new(protocol: &[u8], content_type: Option<&str>, body: &[u8]) -> Content {
+        Content {
+            protocol: protocol.to_vec(),
+            content_type: content_type
+                .map(str::trim)
+                .filter(|content_type| !content_type.is_empty())
+                .map(str::to_string),
+            body: body.to_vec(),
+        }
+    }
+
+    /// Returns the envelope content of a KRC-20 op.
+    pub fn krc20(op: &Krc20Op) -> Result<Content> {
+        Ok(Content::new(
+            ENVELOPE_MARKER,
+            None,
+            op.to_json()?.as_bytes(),
+        ))
+    }
+
+    /// Returns the envelope content of a KRC-721 op.
+    pub fn krc721(op: &Krc721Op) -> Result<Content> {
+        Ok(Content::new(
+            krc721::ENVELOPE_MARKER,
+            None,
+            op.to_json()?.as_bytes(),
+        ))
+    }
+
+    /// Returns the body as text, if it is UTF-8.
+    pub fn text(&self) -> Option<&str> {
+        std::str::from_utf8(&self.body).ok()
+    }
+
+    /// Builds the envelope redeem script, spendable only by the owner of
+    /// `x_only_public_key`.
+    pub fn redeem_script(&self, x_only_public_key: &[u8]) -> Result<Vec<u8>> {
+        fn envelope(content: &Content, x_only_public_key: &[u8]) -> ScriptBuilderResult<Vec<u8>> {
+            let mut builder = ScriptBuilder::new();
+            builder
+                .add_data(x_only_public_key)?
+                .add_op(OpCheckSig)?
+                .add_op(OpFalse)?
+                .add_op(OpIf)?
+                .add_data(&content.protocol)?;
+            if let Some(content_type) = &content.content_type {
+                builder
+                    .add_i64(CONTENT_TYPE_TAG)?
+                    .add_data(content_type.as_bytes())?;
+            }
+            builder.add_i64(BODY_TAG)?;
+            for chunk in content.body.chunks(MAX_PUSH_SIZE) {
+                builder.add_data(chunk)?;
+            }
+            builder.add_op(OpEndIf)?;
+            Ok(builder.drain())
+        }
+
+        envelope(self, x_only_public_key).map_err(|e| {
+            Error::Custom(format!(
+                "Failed to build redeem script for {} bytes of content (scripts are limited to {} bytes): {}",
+                self.body.len(),
+                MAX_SCRIPTS_SIZE,
+                e
+            ))
+        })
+    }
+}
+
+impl fmt::Display for Content {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.text() {
+            Some(text) => write!(f, "{}", text),
+            None => write!(
+                f,
+                "<{} bytes of {}>",
+                self.body.len(),
+                self.content_type.as_deref().unwrap_or("binary data")
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decoder::parse_envelope;
+    use crate::krc20::Mint;
+
+    const KEY: [u8; 32] = [7; 32];
+
+    /// Counts the `OP_PUSHDATA2` pushes of exactly `MAX_PUSH_SIZE` bytes.
+    fn full_pushes(script: &[u8]) -> usize {
+        let header = [OpPushData2, MAX_PUSH_SIZE as u8, (MAX_PUSH_SIZE >> 8) as u8];
+        script.windows(3).filter(|window| *window == header).count()
+    }
+
+    #[test]
+    fn splits_the_body_into_max_size_pushes() {
+        let content = Content::new(ENVELOPE_MARKER, None, &[b'a'; 1200]);
+        let script = content.redeem_script(&KEY).unwrap();
+        assert_eq!(full_pushes(&script), 2);
+        // Key and tags, two full pushes, then the remaining 160 bytes
+        assert_eq!(script.len(), 33 + 3 + 8 + 1 + 2 * 523 + 162 + 1);
+        assert_eq!(script.last(), Some(&OpEndIf));
+
+        let exact = Content::new(ENVELOPE_MARKER, None, &[b'a'; MAX_PUSH_SIZE]);
+        assert_eq!(full_pushes(&exact.redeem_script(&KEY).unwrap()), 1);
+    }
+
+    #[test]
+    fn decodes_what_it_encodes() {
+        let op = Krc20Op::Mint(Mint::new("test", None).unwrap());
+        for content in [
+            Content::krc20(&op).unwrap(),
+            Content::new(b"kspr", Some("text/plain"), &[b'a'; 1200]),
+            Content::new(ENVELOPE_MARKER, None, &[]),
+        ] {
+            let script = content.redeem_script(&KEY).unwrap();
+            let envelope = parse_envelope(&script).unwrap();
+            assert_eq!(envelope.public_key, KEY);
+            assert_eq!(envelope.content, content);
+        }
+        assert_eq!(
+            Content::krc20(&op).unwrap().text(),
+            Some(r#"{"p":"krc-20","op":"mint","tick":"test"}"#)
+        );
+    }
+
+    #[test]
+    fn refuses_bodies_over_the_script_limit() {
+        let content = Content::new(ENVELOPE_MARKER, None, &[b'a'; MAX_SCRIPTS_SIZE]);
+        let error = content.redeem_script(&KEY).unwrap_err().to_string();
+        assert!(error.contains("scripts are limited"), "{error}");
+    }
+}
@@ -0,0 +1,449 @@
+use futures::stream::{self, StreamExt};
+use kaspa_addresses::{Address, Prefix, Version};
+use kaspa_consensus_core::tx::TransactionId;
+use kaspa_wallet_core::utils::sompi_to_kaspa_string;
+use kaspa_wrpc_client::{error::Error, prelude::NetworkType, result::Result, KaspaRpcClient};
+use num_bigint::BigInt;
+use secp256k1::Keypair;
+
+use crate::client::{
+    check_mint_out, fetch_utxos, inscribe, keypair_from_hex, parse_kaspa, pay_in_chunks, preflight,
+    Fees, Utxo,
+};
+use crate::envelope::Content;
+use crate::fees::{CostBreakdown, FeeSchedule};
+use crate::indexer::{parse_number, IndexerClient, OpStatus};
+use crate::krc20::{validate_address, Krc20Op, Mint, Transfer};
+use crate::wallet::build_from_imported_mnemonic;
+
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::Duration;
+
+/// How long to wait for the indexer to settle a child's last mint before
+/// reading its balance.
+const SETTLE_TIMEOUT: u64 = 300_000; // 5 minutes
+const SETTLE_INTERVAL: u64 = 2_000;
+
+/// Outcome of the mints and consolidation of one child wallet.
+#[derive(Debug, Clone)]
+pub struct ChildResult {
+    /// Derivation index of the child.
+    pub index: u32,
+    pub address: Address,
+    pub reveal_ids: Vec<TransactionId>,
+    /// Reveal id of the transfer of the minted balance to the destination.
+    pub consolidation: Option<TransactionId>,
+    /// Why the child stopped early, if it did.
+    pub error: Option<String>,
+}
+
+/// Result of a mint farm run.
+#[derive(Debug, Clone)]
+pub struct FarmResult {
+    /// Funding transactions, if the children were funded.
+    pub funding_ids: Vec<TransactionId>,
+    pub children: Vec<ChildResult>,
+}
+
+/// A child wallet derived from the farm mnemonic.
+struct Child {
+    index: u32,
+    keypair: Keypair,
+    address: Address,
+    /// UTXOs funding the child's first mint.
+    utxos: Vec<Utxo>,
+}
+
+/// Settings shared by every child of a farm.
+struct Farm<'a> {
+    client: &'a KaspaRpcClient,
+    indexer: &'a IndexerClient,
+    prefix: Prefix,
+    ticker: String,
+    content: Content,
+    mint_fees: Fees,
+    transfer_fees: Fees,
+    repeat: u32,
+    destination: String,
+    /// Mints completed across all children, for progress lines.
+    minted: AtomicU32,
+    total: u32,
+}
+
+/// Runs the mint loop of one child, stopping early once the token is minted
+/// out, then transfers its minted balance to the farm destination. Progress
+/// is printed as it happens, prefixed with the child index.
+async fn farm_child(farm: &Farm<'_>, child: Child) -> ChildResult {
+    let Child {
+        index,
+        keypair,
+        address,
+        mut utxos,
+    } = child;
+    let mut result = ChildResult {
+        index,
+        address: address.clone(),
+        reveal_ids: Vec::new(),
+        consolidation: None,
+        error: None,
+    };
+
+    for round in 1..=farm.repeat {
+        if let Some(reason) = check_mint_out(farm.indexer, &farm.ticker).await {
+            println!(
+                "[child {index}] stopping before mint {round}/{}: {reason}",
+                farm.repeat
+            );
+            break;
+        }
+        match inscribe(
+            farm.client,
+            &keypair,
+            farm.prefix,
+            utxos,
+            &farm.content,
+            vec![],
+            farm.mint_fees,
+        )
+        .await
+        {
+            Ok(inscribed) => {
+                let minted = farm.minted.fetch_add(1, Ordering::SeqCst) + 1;
+                println!(
+                    "[child {index}] mint {round}/{}: {} ({minted}/{} farm mints)",
+                    farm.repeat, inscribed.reveal_id, farm.total
+                );
+                result.reveal_ids.push(inscribed.reveal_id);
+                utxos = vec![inscribed.change];
+            }
+            Err(e) => {
+                println!("[child {index}] mint {round}/{} failed: {e}", farm.repeat);
+                result.error = Some(format!("Mint {round}/{} failed: {e}", farm.repeat));
+                // The failed round may still have spent the funding, so read
+                // what the wallet holds now for the consolidation.
+                utxos = match fetch_utxos(farm.client, &address).await {
+                    Ok(utxos) => utxos,
+                    Err(_) => return result,
+                };
+                break;
+            }
+        }
+    }
+
+    let Some(last_reveal) = result.reveal_ids.last() else {
+        return result;
+    };
+    match consolidate(farm, &keypair, &address, utxos, last_reveal).await {
+        Ok(Some(reveal_id)) => {
+            println!(
+                "[child {index}] consolidated to {}: {reveal_id}",
+                farm.destination
+            );
+            result.consolidation = Some(reveal_id);
+        }
+        Ok(None) => println!("[child {index}] no {} balance to consolidate", farm.ticker),
+        Err(e) => {
+            println!("[child {index}] consolidation failed: {e}");
+            let error = format!("Consolidation failed: {e}");
+            result.error = Some(match result.error.take() {
+                Some(mint_error) => format!("{mint_error}; {error}"),
+                None => error,
+            });
+        }
+    }
+    result
+}
+
+/// Waits for the indexer to settle `last_reveal`, then transfers the child's
+/// whole balance of the farm ticker to the destination. Returns `None` if the
+/// child holds none.
+async fn consolidate(
+    farm: &Farm<'_>,
+    keypair: &Keypair,
+    address: &Address,
+    utxos: Vec<Utxo>,
+    last_reveal: &TransactionId,
+) -> Result<Option<TransactionId>> {
+    // Ops of one wallet are indexed in order, so once the last mint is
+    // settled the balance includes every earlier one.
+    let (_, status) = farm
+        .indexer
+        .wait_for_op(
+            &last_reveal.to_string(),
+            Duration::from_millis(SETTLE_TIMEOUT),
+            Duration::from_millis(SETTLE_INTERVAL),
+        )
+        .await;
+    if status == OpStatus::TimedOut {
+        return Err(Error::Custom(format!(
+            "Timed out waiting for the indexer to process {last_reveal}"
+        )));
+    }
+
+    let balance = match farm
+        .indexer
+        .get_balance(&address.to_string(), &farm.ticker)
+        .await?
+    {
+        Some(balance) => parse_number(&balance.balance, "balance")?,
+        None => BigInt::from(0),
+    };
+    if balance <= BigInt::from(0) {
+        return Ok(None);
+    }
+
+    let op = Krc20Op::Transfer(Transfer {
+        tick: Some(farm.ticker.clone()),
+        ca: None,
+        amt: balance,
+        to: farm.destination.clone(),
+    });
+    op.validate()?;
+    let inscribed = inscribe(
+        farm.client,
+        keypair,
+        farm.prefix,
+        utxos,
+        &Content::krc20(&op)?,
+        vec![],
+        farm.transfer_fees,
+    )
+    .await?;
+    Ok(Some(inscribed.reveal_id))
+}
+
+/// KAS each child needs to mint `repeat` times and then consolidate. Before
+/// round `k` a child holds the funding less `k - 1` rounds of fees, which must
+/// cover the commit and lock of that round; after the last round what is left
+/// must cover the transfer.
+fn child_funding(mint: &CostBreakdown, transfer: &CostBreakdown, repeat: u32) -> u64 {
+    mint.total() * repeat as u64
+        + transfer
+            .required_balance()
+            .max(mint.required_balance().saturating_sub(mint.total()))
+}
+
+/// Mints a KRC-20 token from many child wallets of a mnemonic at once. The
+/// master wallet funds the children in chained transactions of at most
+/// `PAYMENT_CHUNK_SIZE` outputs, the children then run their mint loops
+/// concurrently, at most `concurrency` at a time, and each child finally
+/// transfers its minted balance to the destination.
+/// Children that fail are reported without stopping the others; their
+/// funding stays in the child wallet.
+///
+/// Consolidation reads the minted balance from the indexer, so an indexer is
+/// required.
+///
+/// Mint farm file layout, one value per line:
+/// master private key, mnemonic phrase, child count, ticker, mints per child,
+/// priority fee (KAS), concurrency cap, destination address (may be empty,
+/// defaults to the master address), optional funding per child (KAS, defaults
+/// to the estimated cost; 0 mints from what the children already hold).
+pub async fn mint_farm(
+    client: &KaspaRpcClient,
+    preflight_indexer: Option<&IndexerClient>,
+    indexer: &IndexerClient,
+    network: NetworkType,
+    farm_args: Vec<String>,
+) -> Result<FarmResult> {
+    if farm_args.len() < 8 {
+        return Err(Error::Custom(format!(
+            "Expected at least 8 lines of mint farm arguments, found {}",
+            farm_args.len()
+        )));
+    }
+
+    let master = keypair_from_hex(&farm_args[0])?;
+    let phrase = farm_args[1].trim().to_string();
+    let child_count: u32 = farm_args[2]
+        .trim()
+        .parse()
+        .map_err(|e| Error::Custom(format!("Invalid child count: {}", e)))?;
+    let ticker = farm_args[3].trim().to_string();
+    let repeat: u32 = farm_args[4]
+        .trim()
+        .parse()
+        .map_err(|e| Error::Custom(format!("Invalid mints per child: {}", e)))?;
+    let fee = parse_kaspa(&farm_args[5], "priority fee")?;
+    let concurrency: usize = farm_args[6]
+        .trim()
+        .parse()
+        .map_err(|e| Error::Custom(format!("Invalid concurrency cap: {}", e)))?;
+    if child_count == 0 || repeat == 0 || concurrency == 0 {
+        return Err(Error::Custom(
+            "Child count, mints per child and concurrency cap must be positive".to_string(),
+        ));
+    }
+
+    let prefix = Prefix::from(network);
+    let (x_only_public_key, _) = master.x_only_public_key();
+    let master_address = Address::new(prefix, Version::PubKey, &x_only_public_key.serialize());
+    println!("Determining address: {master_address}");
+    let destination = match farm_args[7].trim() {
+        "" => master_address.to_string(),
+        destination => validate_address(destination, "destination")?,
+    };
+
+    let op = Krc20Op::Mint(Mint::new(&ticker, None)?);
+    let ticker = op.token().unwrap_or_default().to_string();
+    if let Some(indexer) = preflight_indexer {
+        preflight(indexer, &op, &master_address).await?;
+    }
+    let schedule = FeeSchedule::load(network)?;
+    let content = Content::krc20(&op)?;
+    let mint_fees = Fees {
+        priority_fee: fee,
+        protocol_fee: schedule.fee(&op),
+    };
+    let transfer_op = Krc20Op::Transfer(Transfer {
+        tick: Some(ticker.clone()),
+        ca: None,
+        amt: BigInt::from(u64::MAX),
+        to: destination.clone(),
+    });
+    let transfer_fees = Fees {
+        priority_fee: fee,
+        protocol_fee: schedule.fee(&transfer_op),
+    };
+
+    // What each round pays depends on the child's balance at the time, so
+    // funding is sized for the most expensive rounds.
+    let funding = match farm_args.get(8).map(|funding| funding.trim()) {
+        Some(funding) if !funding.is_empty() => parse_kaspa(funding, "funding per child")?,
+        _ => child_funding(
+            &CostBreakdown::upper_bound(&[], mint_fees.priority_fee, mint_fees.protocol_fee),
+            &CostBreakdown::upper_bound(
+                &[],
+                transfer_fees.priority_fee,
+                transfer_fees.protocol_fee,
+            ),
+            repeat,
+        ),
+    };
+
+    let mut children = Vec::new();
+    for index in 0..child_count {
+        let private_key = build_from_imported_mnemonic(phrase.clone(), Some(index))?;
+        let keypair = keypair_from_hex(&private_key.to_hex())?;
+        let (x_only_public_key, _) = keypair.x_only_public_key();
+        children.push(Child {
+            index,
+            keypair,
+            address: Address::new(prefix, Version::PubKey, &x_only_public_key.serialize()),
+            utxos: Vec::new(),
+        });
+    }
+
+    let funding_ids = if funding > 0 {
+        let utxos = fetch_utxos(client, &master_address).await?;
+        if utxos.is_empty() {
+            return Err(Error::Custom(format!(
+                "No UTXOs available for address: {master_address}"
+            )));
+        }
+        let payments: Vec<(Address, u64)> = children
+            .iter()
+            .map(|child| (child.address.clone(), funding))
+            .collect();
+        println!("Children:      {child_count}");
+        println!("Per child:     {} KAS", sompi_to_kaspa_string(funding));
+        let paid = pay_in_chunks(client, &master, utxos, &payments, &master_address, fee).await?;
+        let total: u64 = paid.utxos.iter().map(|(_, entry)| entry.amount).sum();
+        println!("Funding fee:   {} KAS", sompi_to_kaspa_string(paid.fee));
+        println!(
+            "Total funding: {} KAS",
+            sompi_to_kaspa_string(total + paid.fee)
+        );
+
+        // Children mint straight from their funding outputs, without waiting
+        // for the funding to be accepted.
+        for (child, utxo) in children.iter_mut().zip(paid.utxos) {
+            child.utxos = vec![utxo];
+        }
+        paid.ids
+    } else {
+        for child in children.iter_mut() {
+            child.utxos = fetch_utxos(client, &child.address).await?;
+        }
+        Vec::new()
+    };
+
+    let farm = Farm {
+        client,
+        indexer,
+        prefix,
+        ticker,
+        content,
+        mint_fees,
+        transfer_fees,
+        repeat,
+        destination,
+        minted: AtomicU32::new(0),
+        total: child_count * repeat,
+    };
+    println!(
+        "Minting {repeat} times from each of {child_count} children, {concurrency} at a time: {}",
+        farm.content
+    );
+
+    let mut results: Vec<ChildResult> = stream::iter(children)
+        .map(|child| {
+            let farm = &farm;
+            async move {
+                if child.utxos.is_empty() {
+                    return ChildResult {
+                        index: child.index,
+                        address: child.address,
+                        reveal_ids: Vec::new(),
+                        consolidation: None,
+                        error: Some("No UTXOs available".to_string()),
+                    };
+                }
+                farm_child(farm, child).await
+            }
+        })
+        .buffer_unordered(concurrency)
+        .collect()
+        .await;
+    results.sort_by_key(|result| result.index);
+
+    Ok(FarmResult {
+        funding_ids,
+        children: results,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn funds_every_round_and_the_consolidation() {
+        let mint = CostBreakdown::upper_bound(&[], 10_000, 100_000_000);
+        let transfer = CostBreakdown::upper_bound(&[], 10_000, 0);
+        for repeat in [1, 2, 10] {
+            let funding = child_funding(&mint, &transfer, repeat);
+            for round in 1..=repeat as u64 {
+                let balance = funding - mint.total() * (round - 1);
+                assert!(
+                    mint.check_balance(balance).is_ok(),
+                    "round {round}/{repeat}"
+                );
+            }
+            let left = funding - mint.total() * repeat as u64;
+            assert!(transfer.check_balance(left).is_ok());
+
+            // One sompi less leaves the last round or the transfer short.
+            let last = funding - 1 - mint.total() * (repeat as u64 - 1);
+            assert!(
+                mint.check_balance(last).is_err()
+                    || transfer.check_balance(last - mint.total()).is_err()
+            );
+        }
+
+        // A transfer dearer than what the last round leaves sizes the rest.
+        let transfer = CostBreakdown::upper_bound(&[], 10_000, 10 * 100_000_000);
+        let funding = child_funding(&mint, &transfer, 3);
+        assert_eq!(funding, mint.total() * 3 + transfer.required_balance());
+    }
+}
@@ -0,0 +1,663 @@
+use kaspa_consensus_core::mass::transaction_estimated_serialized_size;
+use kaspa_consensus_core::subnets::SUBNETWORK_ID_NATIVE;
+use kaspa_consensus_core::tx::{
+    ScriptPublicKey, Transaction, TransactionId, TransactionInput, TransactionOutpoint,
+    TransactionOutput, UtxoEntry,
+};
+use kaspa_txscript::{pay_to_script_hash_script, pay_to_script_hash_signature_script};
+use kaspa_wallet_core::utils::sompi_to_kaspa_string;
+use kaspa_wrpc_client::{error::Error, prelude::NetworkType, result::Result};
+use serde::Deserialize;
+
+use crate::client::parse_kaspa;
+use crate::krc20::Krc20Op;
+use crate::krc721::Krc721Op;
+
+use std::collections::HashMap;
+use std::fs;
+
+pub const MASS_PER_TX_BYTE: u64 = 1;
+pub const MASS_PER_SCRIPT_PUB_KEY_BYTE: u64 = 10;
+pub const MASS_PER_SIG_OP: u64 = 1000;
+/// Minimum relay fee rate in sompi per gram of mass.
+pub const MINIMUM_FEE_RATE: u64 = 1;
+/// Storage mass of an output is this parameter divided by its value (KIP-9).
+pub const STORAGE_MASS_PARAMETER: u64 = 1_000_000_000_000;
+/// Largest mass the node relays.
+pub const MAXIMUM_STANDARD_TRANSACTION_MASS: u64 = 100_000;
+
+/// Protocol fee in sompi paid by a mint reveal (1 KAS).
+pub const MINT_FEE: u64 = 100_000_000;
+/// Protocol fee in sompi paid by a deploy reveal (1000 KAS).
+pub const DEPLOY_FEE: u64 = 100_000_000_000;
+/// Protocol fee in sompi paid by a transfer reveal (0.1 KAS).
+pub const TRANSFER_FEE: u64 = 10_000_000;
+/// Protocol fee in sompi paid by a KRC-721 collection deploy (1000 KAS).
+pub const KRC721_DEPLOY_FEE: u64 = 100_000_000_000;
+/// Protocol fee in sompi paid by a KRC-721 mint (10 KAS).
+pub const KRC721_MINT_FEE: u64 = 1_000_000_000;
+
+/// File overriding the default fee schedule, keyed by network, e.g.
+/// `{"mainnet": {"transfer": "0.2"}}`. Amounts are in KAS.
+pub const FEES_FILE: &str = "krc20-fees.json";
+
+/// Amount left in the P2SH output on top of the reveal fees, so a reveal that
+/// spends only the P2SH output (e.g. during recovery) still has a change
+/// output large enough to avoid heavy storage mass.
+pub const MIN_LOCK_CHANGE: u64 = 20_000_000;
+
+/// Rounds `fee_and_change` takes to settle the fee against the change.
+const FEE_ROUNDS: usize = 8;
+
+/// Length of `OP_DATA_65 <SIGNATURE+SIGHASH_TYPE>`.
+const SIGNATURE_SCRIPT_LEN: usize = 66;
+
+/// Protocol fees in sompi the indexer requires from the reveal of each op.
+/// An op whose reveal pays less is silently rejected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FeeSchedule {
+    pub deploy: u64,
+    /// Also paid by issues of issue-mode tokens.
+    pub mint: u64,
+    pub transfer: u64,
+    pub krc721_deploy: u64,
+    pub krc721_mint: u64,
+}
+
+/// Overrides read from `FEES_FILE` for one network.
+#[derive(Debug, Default, Deserialize)]
+struct FeeOverrides {
+    deploy: Option<String>,
+    mint: Option<String>,
+    transfer: Option<String>,
+    krc721_deploy: Option<String>,
+    krc721_mint: Option<String>,
+}
+
+impl FeeSchedule {
+    /// Returns the default schedule of `network`. Devnet and simnet have no
+    /// public indexer, so nothing is charged there.
+    pub fn for_network(network: NetworkType) -> FeeSchedule {
+        match network {
+            NetworkType::Mainnet | NetworkType::Testnet => FeeSchedule {
+                deploy: DEPLOY_FEE,
+                mint: MINT_FEE,
+                transfer: TRANSFER_FEE,
+                krc721_deploy: KRC721_DEPLOY_FEE,
+                krc721_mint: KRC721_MINT_FEE,
+            },
+            NetworkType::Devnet | NetworkType::Simnet => FeeSchedule {
+                deploy: 0,
+                mint: 0,
+                transfer: 0,
+                krc721_deploy: 0,
+                krc721_mint: 0,
+            },
+        }
+    }
+
+    /// Returns the schedule of `network` with the overrides from `FEES_FILE`
+    /// applied, if the file exists.
+    pub fn load(network: NetworkType) -> Result<FeeSchedule> {
+        match fs::read_to_string(FEES_FILE) {
+            Ok(contents) => FeeSchedule::with_overrides(network, &contents),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                Ok(FeeSchedule::for_network(network))
+            }
+            Err(e) => Err(Error::Custom(format!(
+                "Failed to read {}: {}",
+                FEES_FILE, e
+            ))),
+        }
+    }
+
+    /// Returns the schedule of `network` with the overrides for it in the
+    /// `FEES_FILE` JSON `contents` applied.
+    fn with_overrides(network: NetworkType, contents: &str) -> Result<FeeSchedule> {
+        let mut schedule = FeeSchedule::for_network(network);
+        let mut overrides: HashMap<String, FeeOverrides> = serde_json::from_str(contents)
+            .map_err(|e| Error::Custom(format!("Failed to parse {}: {}", FEES_FILE, e)))?;
+        let Some(overrides) = overrides.remove(&network.to_string()) else {
+            return Ok(schedule);
+        };
+        for (value, fee, name) in [
+            (overrides.deploy, &mut schedule.deploy, "deploy fee"),
+            (overrides.mint, &mut schedule.mint, "mint fee"),
+            (overrides.transfer, &mut schedule.transfer, "transfer fee"),
+            (
+                overrides.krc721_deploy,
+                &mut schedule.krc721_deploy,
+                "KRC-721 deploy fee",
+            ),
+            (
+                overrides.krc721_mint,
+                &mut schedule.krc721_mint,
+                "KRC-721 mint fee",
+            ),
+        ] {
+            if let Some(value) = value {
+                *fee = parse_kaspa(&value, name)?;
+            }
+        }
+        Ok(schedule)
+    }
+
+    /// Returns the protocol fee in sompi that the reveal of `op` must pay.
+    pub fn fee(&self, op: &Krc20Op) -> u64 {
+        match op {
+            Krc20Op::Deploy(_) => self.deploy,
+            Krc20Op::Mint(_) | Krc20Op::Issue(_) => self.mint,
+            Krc20Op::Transfer(_) => self.transfer,
+            _ => 0,
+        }
+    }
+
+    /// Returns the protocol fee in sompi that the reveal of the KRC-721 `op`
+    /// must pay. Mint royalties are paid separately to the collection.
+    pub fn krc721_fee(&self, op: &Krc721Op) -> u64 {
+        match op {
+            Krc721Op::Deploy(_) => self.krc721_deploy,
+            Krc721Op::Mint(_) => self.krc721_mint,
+            Krc721Op::Transfer(_) => 0,
+        }
+    }
+}
+
+/// Returns the compute mass of a signed transaction.
+pub fn compute_mass(tx: &Transaction) -> u64 {
+    let size = transaction_estimated_serialized_size(tx);
+    let script_public_key_size: u64 = tx
+        .outputs
+        .iter()
+        .map(|output| 2 + output.script_public_key.script().len() as u64)
+        .sum();
+    let sig_op_count: u64 = tx
+        .inputs
+        .iter()
+        .map(|input| input.sig_op_count as u64)
+        .sum();
+    size * MASS_PER_TX_BYTE
+        + script_public_key_size * MASS_PER_SCRIPT_PUB_KEY_BYTE
+        + sig_op_count * MASS_PER_SIG_OP
+}
+
+/// Returns the storage mass (KIP-9) of a transaction spending inputs worth
+/// `input_values` into outputs worth `output_values`, i.e.
+/// `max(0, C·(|O|/H(O) - |I|/A(I)))` with H the harmonic and A the arithmetic
+/// mean. A zero-valued output has unbounded storage mass.
+pub fn storage_mass(input_values: &[u64], output_values: &[u64]) -> u64 {
+    let harmonic_outputs = output_values
+        .iter()
+        .map(|value| {
+            STORAGE_MASS_PARAMETER
+                .checked_div(*value)
+                .unwrap_or(u64::MAX)
+        })
+        .fold(0u64, u64::saturating_add);
+    let input_count = input_values.len() as u64;
+    let input_total: u64 = input_values.iter().sum();
+    let arithmetic_inputs = match input_total.checked_div(input_count) {
+        Some(mean) if mean > 0 => input_count.saturating_mul(STORAGE_MASS_PARAMETER / mean),
+        _ => 0,
+    };
+    harmonic_outputs.saturating_sub(arithmetic_inputs)
+}
+
+/// Whether a change output worth `change` keeps the storage mass of a
+/// transaction spending `input_values` into `output_values` and the change
+/// standard. Smaller change is dust and is left to the fee instead.
+pub fn keeps_change(input_values: &[u64], output_values: &[u64], change: u64) -> bool {
+    let values: Vec<u64> = output_values
+        .iter()
+        .copied()
+        .chain(std::iter::once(change))
+        .collect();
+    change > 0 && storage_mass(input_values, &values) <= MAXIMUM_STANDARD_TRANSACTION_MASS
+}
+
+/// Returns the network fee and change of a transaction spending
+/// `input_values` into `output_values` and `paid` (e.g. a protocol fee), plus
+/// a change output. `compute_mass` is the compute mass with the change output.
+/// The fee covers the larger of compute and storage mass plus
+/// `priority_fee`. Dust change is dropped and left to the fee, in which case
+/// the returned change is zero.
+pub fn fee_and_change(
+    input_values: &[u64],
+    output_values: &[u64],
+    paid: u64,
+    compute_mass: u64,
+    priority_fee: u64,
+) -> (u64, u64) {
+    let input_total: u64 = input_values.iter().sum();
+    let surplus = input_total.saturating_sub(output_values.iter().sum::<u64>() + paid);
+    let without_change =
+        fee_for_mass(compute_mass.max(storage_mass(input_values, output_values))) + priority_fee;
+    // A smaller change raises the storage mass, and so the fee, which in turn
+    // shrinks the change. This settles within a few rounds.
+    let mut fee = fee_for_mass(compute_mass) + priority_fee;
+    for _ in 0..FEE_ROUNDS {
+        let change = surplus.saturating_sub(fee);
+        if !keeps_change(input_values, output_values, change) {
+            return (without_change, 0);
+        }
+        let values: Vec<u64> = output_values
+            .iter()
+            .copied()
+            .chain(std::iter::once(change))
+            .collect();
+        let next =
+            fee_for_mass(compute_mass.max(storage_mass(input_values, &values))) + priority_fee;
+        if next <= fee {
+            return (fee, change);
+        }
+        fee = next;
+    }
+    match surplus.checked_sub(fee) {
+        Some(change) if keeps_change(input_values, output_values, change) => (fee, change),
+        _ => (without_change, 0),
+    }
+}
+
+/// Returns the minimum relay fee for `mass`.
+pub fn fee_for_mass(mass: u64) -> u64 {
+    mass * MINIMUM_FEE_RATE
+}
+
+fn placeholder_input(signature_script_len: usize) -> TransactionInput {
+    TransactionInput::new(
+        TransactionOutpoint::new(TransactionId::default(), 0),
+        vec![0; signature_script_len],
+        0,
+        1,
+    )
+}
+
+fn p2sh_signature_script_len(redeem_script: &[u8]) -> usize {
+    pay_to_script_hash_signature_script(redeem_script.to_vec(), vec![0; SIGNATURE_SCRIPT_LEN])
+        .map(|script| script.len())
+        .unwrap_or(SIGNATURE_SCRIPT_LEN + redeem_script.len() + 3)
+}
+
+/// Compute mass of a commit spending `input_count` wallet inputs into the
+/// P2SH output of `redeem_script` and a change output.
+pub fn commit_mass(input_count: usize, redeem_script: &[u8], change: &ScriptPublicKey) -> u64 {
+    batch_commit_mass(input_count, &[redeem_script], change)
+}
+
+/// Compute mass of a commit spending `input_count` wallet inputs into one
+/// P2SH output per redeem script and a change output.
+pub fn batch_commit_mass(
+    input_count: usize,
+    redeem_scripts: &[&[u8]],
+    change: &ScriptPublicKey,
+) -> u64 {
+    let outputs: Vec<TransactionOutput> = redeem_scripts
+        .iter()
+        .map(|redeem_script| TransactionOutput::new(0, pay_to_script_hash_script(redeem_script)))
+        .collect();
+    payment_mass(input_count, &outputs, change)
+}
+
+/// Compute mass of a transaction spending `input_count` wallet inputs into
+/// `outputs` and a change output.
+pub fn payment_mass(
+    input_count: usize,
+    outputs: &[TransactionOutput],
+    change: &ScriptPublicKey,
+) -> u64 {
+    let inputs = (0..input_count)
+        .map(|_| placeholder_input(SIGNATURE_SCRIPT_LEN))
+        .collect();
+    let outputs = outputs
+        .iter()
+        .cloned()
+        .chain(std::iter::once(TransactionOutput::new(0, change.clone())))
+        .collect();
+    let tx = Transaction::new(0, inputs, outputs, 0, SUBNETWORK_ID_NATIVE, 0, vec![]);
+    compute_mass(&tx)
+}
+
+/// Smallest value each of `output_count` outputs of one transaction can have
+/// while their storage mass stays standard.
+pub fn min_output_value(output_count: usize) -> u64 {
+    (STORAGE_MASS_PARAMETER * output_count as u64).div_ceil(MAXIMUM_STANDARD_TRANSACTION_MASS)
+}
+
+/// Compute mass of a reveal spending the P2SH output of `redeem_script` plus
+/// `wallet_input_count` wallet inputs into `outputs` followed by a change
+/// output.
+pub fn reveal_mass(
+    redeem_script: &[u8],
+    wallet_input_count: usize,
+    outputs: &[TransactionOutput],
+    change: &ScriptPublicKey,
+) -> u64 {
+    let inputs = std::iter::once(placeholder_input(p2sh_signature_script_len(redeem_script)))
+        .chain((0..wallet_input_count).map(|_| placeholder_input(SIGNATURE_SCRIPT_LEN)))
+        .collect();
+    let outputs = outputs
+        .iter()
+        .cloned()
+        .chain(std::iter::once(TransactionOutput::new(0, change.clone())))
+        .collect();
+    let tx = Transaction::new(0, inputs, outputs, 0, SUBNETWORK_ID_NATIVE, 0, vec![]);
+    compute_mass(&tx)
+}
+
+/// Fails unless the reveal `tx` spending `entries` pays exactly `network_fee`
+/// plus `protocol_fee`, with a network fee at least the minimum for its mass,
+/// or if its change is dust. `tx` is unsigned, so its compute mass is taken
+/// from `reveal_mass` with the spent `redeem_script`.
+pub fn check_reveal_fee(
+    tx: &Transaction,
+    entries: &[UtxoEntry],
+    redeem_script: &[u8],
+    network_fee: u64,
+    protocol_fee: u64,
+) -> Result<()> {
+    let (change, outputs) = tx
+        .outputs
+        .split_last()
+        .ok_or_else(|| Error::Custom("Reveal transaction has no outputs".to_string()))?;
+    let input_values: Vec<u64> = entries.iter().map(|entry| entry.amount).collect();
+    let output_values: Vec<u64> = outputs.iter().map(|output| output.value).collect();
+    if !keeps_change(&input_values, &output_values, change.value) {
+        return Err(Error::Custom(format!(
+            "Refusing to sign reveal with a change of {} KAS: too small to be standard",
+            sompi_to_kaspa_string(change.value)
+        )));
+    }
+    let input_total: u64 = input_values.iter().sum();
+    let output_total: u64 = tx.outputs.iter().map(|output| output.value).sum();
+    let paid = input_total.saturating_sub(output_total);
+    if paid != network_fee + protocol_fee {
+        return Err(Error::Custom(format!(
+            "Refusing to sign reveal paying {} KAS: its change should leave exactly the network fee of {} KAS plus the protocol fee of {} KAS",
+            sompi_to_kaspa_string(paid),
+            sompi_to_kaspa_string(network_fee),
+            sompi_to_kaspa_string(protocol_fee)
+        )));
+    }
+    let compute_mass = reveal_mass(
+        redeem_script,
+        tx.inputs.len().saturating_sub(1),
+        outputs,
+        &change.script_public_key,
+    );
+    let all_values: Vec<u64> = tx.outputs.iter().map(|output| output.value).collect();
+    let minimum_fee = fee_for_mass(compute_mass.max(storage_mass(&input_values, &all_values)));
+    if network_fee < minimum_fee {
+        return Err(Error::Custom(format!(
+            "Refusing to sign reveal paying {} KAS: the network fee of {} KAS plus the protocol fee of {} KAS is required",
+            sompi_to_kaspa_string(paid),
+            sompi_to_kaspa_string(minimum_fee),
+            sompi_to_kaspa_string(protocol_fee)
+        )));
+    }
+    Ok(())
+}
+
+/// What a commit/reveal round costs, in sompi.
+#[derive(Debug, Clone, Copy)]
+pub struct CostBreakdown {
+    /// Commit network fee, including the priority fee.
+    pub commit_fee: u64,
+    /// Reveal network fee, including the priority fee.
+    pub reveal_fee: u64,
+    pub protocol_fee: u64,
+    /// Amount locked in the P2SH commit output.
+    pub lock_amount: u64,
+    /// Value of reveal outputs other than the change, e.g. a listing UTXO or
+    /// a mint royalty. It is not counted as a fee.
+    pub output_amount: u64,
+}
+
+impl CostBreakdown {
+    /// Estimates the cost of a round whose commit spends wallet inputs worth
+    /// `input_values` and whose reveal spends the P2SH output plus the commit
+    /// change into `outputs` and a change output. Both fees cover the larger
+    /// of compute and storage mass; a commit change too small to be standard
+    /// is left to the commit fee and the reveal then spends the P2SH output
+    /// alone.
+    pub fn estimate(
+        input_values: &[u64],
+        redeem_script: &[u8],
+        outputs: &[TransactionOutput],
+        change: &ScriptPublicKey,
+        priority_fee: u64,
+        protocol_fee: u64,
+    ) -> CostBreakdown {
+        let output_values: Vec<u64> = outputs.iter().map(|output| output.value).collect();
+        let commit_mass = commit_mass(input_values.len(), redeem_script, change);
+        let mut reveal_fee =
+            fee_for_mass(reveal_mass(redeem_script, 1, outputs, change)) + priority_fee;
+        // The reveal fee sets the lock, which sets the commit change the
+        // reveal spends, which sets the reveal's storage mass.
+        let mut round = 1;
+        loop {
+            // The P2SH output alone must be able to pay for a reveal without
+            // the commit change, as during recovery.
+            let lock_amount = reveal_fee + protocol_fee + MIN_LOCK_CHANGE;
+            let (commit_fee, commit_change) =
+                fee_and_change(input_values, &[lock_amount], 0, commit_mass, priority_fee);
+            let reveal_inputs: Vec<u64> = std::iter::once(lock_amount)
+                .chain(Some(commit_change).filter(|change| *change > 0))
+                .collect();
+            let (next_reveal_fee, _) = fee_and_change(
+                &reveal_inputs,
+                &output_values,
+                protocol_fee,
+                reveal_mass(redeem_script, reveal_inputs.len() - 1, outputs, change),
+                priority_fee,
+            );
+            if next_reveal_fee <= reveal_fee || round == FEE_ROUNDS {
+                return CostBreakdown {
+                    commit_fee,
+                    reveal_fee,
+                    protocol_fee,
+                    lock_amount,
+                    output_amount: output_values.iter().sum(),
+                };
+            }
+            reveal_fee = next_reveal_fee;
+            round += 1;
+        }
+    }
+
+    /// Upper bound of `estimate` for a round whose inputs are not known yet,
+    /// e.g. when funding a wallet for it: each transaction pays for the
+    /// largest standard mass.
+    pub fn upper_bound(
+        outputs: &[TransactionOutput],
+        priority_fee: u64,
+        protocol_fee: u64,
+    ) -> CostBreakdown {
+        let network_fee = fee_for_mass(MAXIMUM_STANDARD_TRANSACTION_MASS) + priority_fee;
+        CostBreakdown {
+            commit_fee: network_fee,
+            reveal_fee: network_fee,
+            protocol_fee,
+            lock_amount: network_fee + protocol_fee + MIN_LOCK_CHANGE,
+            output_amount: outputs.iter().map(|output| output.value).sum(),
+        }
+    }
+
+    /// Fees paid by the round, i.e. what the wallet balance goes down by.
+    pub fn total(&self) -> u64 {
+        self.commit_fee + self.reveal_fee + self.protocol_fee
+    }
+
+    /// Balance the wallet needs before the commit is signed.
+    pub fn required_balance(&self) -> u64 {
+        self.commit_fee + self.lock_amount + self.output_amount
+    }
+
+    /// Balance the wallet needs before the first of `rounds` consecutive
+    /// rounds, each later round funded by the previous reveal's change.
+    pub fn required_balance_for(&self, rounds: u32) -> u64 {
+        let later_rounds = rounds.saturating_sub(1) as u64;
+        self.required_balance() + (self.total() + self.output_amount) * later_rounds
+    }
+
+    /// Fails if `balance` cannot fund the round.
+    pub fn check_balance(&self, balance: u64) -> Result<()> {
+        self.check_balance_for(balance, 1)
+    }
+
+    /// Fails if `balance` cannot fund `rounds` consecutive rounds.
+    pub fn check_balance_for(&self, balance: u64, rounds: u32) -> Result<()> {
+        let required = self.required_balance_for(rounds);
+        if balance < required {
+            return Err(Error::Custom(format!(
+                "Insufficient funds: have {} KAS, need {} KAS",
+                sompi_to_kaspa_string(balance),
+                sompi_to_kaspa_string(required)
+            )));
+        }
+        Ok(())
+    }
+
+    pub fn print(&self) {
+        println!(
+            "Commit fee:   {} KAS",
+            sompi_to_kaspa_string(self.commit_fee)
+        );
+        println!(
+            "Reveal fee:   {} KAS",
+            sompi_to_kaspa_string(self.reveal_fee)
+        );
+        println!(
+            "Protocol fee: {} KAS",
+            sompi_to_kaspa_string(self.protocol_fee)
+        );
+        println!(
+            "Locked:       {} KAS",
+            sompi_to_kaspa_string(self.lock_amount)
+        );
+        if self.output_amount > 0 {
+            println!(
+                "Outputs:      {} KAS",
+                sompi_to_kaspa_string(self.output_amount)
+            );
+        }
+        println!("Total cost:   {} KAS", sompi_to_kaspa_string(self.total()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::envelope::Content;
+    use crate::krc20::{List, Mint};
+    use kaspa_addresses::{Address, Prefix, Version};
+    use kaspa_txscript::pay_to_address_script;
+
+    const KAS: u64 = 100_000_000;
+
+    #[test]
+    fn applies_fee_overrides_for_the_network() {
+        let contents =
+            r#"{"mainnet": {"transfer": "0.2", "krc721_mint": "5"}, "testnet": {"mint": "2"}}"#;
+        let schedule = FeeSchedule::with_overrides(NetworkType::Mainnet, contents).unwrap();
+        assert_eq!(
+            schedule,
+            FeeSchedule {
+                transfer: 20_000_000,
+                krc721_mint: 5 * KAS,
+                ..FeeSchedule::for_network(NetworkType::Mainnet)
+            }
+        );
+        let devnet = FeeSchedule::with_overrides(NetworkType::Devnet, contents).unwrap();
+        assert_eq!(devnet, FeeSchedule::for_network(NetworkType::Devnet));
+        assert_eq!(devnet.mint, 0);
+
+        assert!(
+            FeeSchedule::with_overrides(NetworkType::Testnet, r#"{"testnet": {"mint": "x"}}"#)
+                .is_err()
+        );
+        assert!(FeeSchedule::with_overrides(NetworkType::Testnet, "[]").is_err());
+    }
+
+    #[test]
+    fn charges_protocol_fees_by_op() {
+        let schedule = FeeSchedule::for_network(NetworkType::Mainnet);
+        let mint = Krc20Op::Mint(Mint::new("test", None).unwrap());
+        let list = Krc20Op::List(List::new("test", "1", 0).unwrap());
+        assert_eq!(schedule.fee(&mint), MINT_FEE);
+        assert_eq!(schedule.fee(&list), 0);
+    }
+
+    /// A reveal spending a P2SH output worth `lock` into a single change
+    /// output worth `change`, with the redeem script it spends.
+    fn reveal(lock: u64, change: u64) -> (Transaction, Vec<UtxoEntry>, Vec<u8>) {
+        let op = Krc20Op::Mint(Mint::new("test", None).unwrap());
+        let redeem_script = Content::krc20(&op)
+            .unwrap()
+            .redeem_script(&[1; 32])
+            .unwrap();
+        let address = Address::new(Prefix::Mainnet, Version::PubKey, &[1; 32]);
+        let tx = Transaction::new(
+            0,
+            vec![placeholder_input(0)],
+            vec![TransactionOutput::new(
+                change,
+                pay_to_address_script(&address),
+            )],
+            0,
+            SUBNETWORK_ID_NATIVE,
+            0,
+            vec![],
+        );
+        let entry = UtxoEntry::new(lock, pay_to_script_hash_script(&redeem_script), 0, false);
+        (tx, vec![entry], redeem_script)
+    }
+
+    #[test]
+    fn checks_reveal_fees() {
+        // With large values the compute mass outweighs the storage mass
+        let lock = MINT_FEE + 101 * KAS;
+        let (tx, _, redeem_script) = reveal(lock, 100 * KAS);
+        let compute = reveal_mass(&redeem_script, 0, &[], &tx.outputs[0].script_public_key);
+        assert!(storage_mass(&[lock], &[100 * KAS]) < compute);
+        let network_fee = fee_for_mass(compute);
+
+        // Paying exactly the network and protocol fees is enough
+        let change = lock - MINT_FEE - network_fee;
+        let (tx, entries, redeem_script) = reveal(lock, change);
+        assert!(check_reveal_fee(&tx, &entries, &redeem_script, network_fee, MINT_FEE).is_ok());
+        let (tx, entries, redeem_script) = reveal(lock, change + 1);
+        let error = check_reveal_fee(&tx, &entries, &redeem_script, network_fee - 1, MINT_FEE)
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("is required"), "{error}");
+
+        // A change that does not match the fees is refused either way
+        for change in [change - 1, change + 1] {
+            let (tx, entries, redeem_script) = reveal(lock, change);
+            let error = check_reveal_fee(&tx, &entries, &redeem_script, network_fee, MINT_FEE)
+                .unwrap_err()
+                .to_string();
+            assert!(error.contains("should leave exactly"), "{error}");
+        }
+
+        // Dust change is refused even when the fees are covered
+        let (tx, entries, redeem_script) = reveal(lock, 1000);
+        let error = check_reveal_fee(&tx, &entries, &redeem_script, lock - 1000, MINT_FEE)
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("too small to be standard"), "{error}");
+    }
+
+    #[test]
+    fn leaves_dust_change_to_the_fee() {
+        assert_eq!(min_output_value(1), 10_000_000);
+        assert_eq!(storage_mass(&[2 * KAS], &[KAS]), 5_000);
+        assert_eq!(storage_mass(&[KAS], &[KAS]), 0);
+
+        let (fee, change) = fee_and_change(&[KAS], &[], 0, 1_000, 0);
+        assert_eq!(fee + change, KAS);
+        assert!(change > 0);
+        let (fee, change) = fee_and_change(&[KAS], &[KAS - 10_000], 0, 1_000, 0);
+        assert_eq!((fee, change), (1_000, 0));
+    }
+}
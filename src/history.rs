@@ -0,0 +1,191 @@
+use kaspa_wrpc_client::{error::Error, result::Result};
+
+use crate::indexer::{parse_number, IndexerClient, OpInfo};
+use crate::krc20::{format_amount, parse_decimals, validate_address, DEFAULT_DECIMALS};
+
+use std::collections::HashMap;
+use std::fs;
+
+/// Default location of the exported history.
+pub const HISTORY_FILE: &str = "krc20-history.csv";
+
+/// The indexer's op score is the DAA score of the accepting block scaled by
+/// this factor, plus the op's position within it.
+const OP_SCORE_SCALE: u64 = 10_000;
+
+/// One op involving an address, as exported.
+#[derive(Debug, Clone)]
+pub struct HistoryEntry {
+    pub address: String,
+    pub op: String,
+    /// `sent`, `received`, `minted`, `listed` or the op name for other ops.
+    pub direction: String,
+    pub tick: String,
+    /// Amount in token units.
+    pub amount: String,
+    /// The other side of a transfer, empty for ops without one.
+    pub counterparty: String,
+    pub reveal_id: String,
+    pub daa_score: u64,
+    /// `accepted`, `rejected` or `pending`.
+    pub status: String,
+    pub error: String,
+}
+
+impl HistoryEntry {
+    fn new(address: &str, info: OpInfo, dec: u8) -> Result<HistoryEntry> {
+        let (direction, counterparty) = match info.op.as_str() {
+            "mint" => ("minted".to_string(), String::new()),
+            "list" => ("listed".to_string(), String::new()),
+            _ if info.from == address && info.to != address => ("sent".to_string(), info.to),
+            _ if info.to == address && info.from != address => ("received".to_string(), info.from),
+            op => (op.to_string(), String::new()),
+        };
+        let status = match info.op_accept.as_str() {
+            "1" => "accepted",
+            "-1" => "rejected",
+            _ => "pending",
+        };
+        let op_score: u64 = info.op_score.trim().parse().unwrap_or_default();
+        Ok(HistoryEntry {
+            address: address.to_string(),
+            op: info.op,
+            direction,
+            tick: info.tick,
+            amount: format_amount(&parse_number(&info.amt, "amt")?, dec),
+            counterparty,
+            reveal_id: info.hash_rev,
+            daa_score: op_score / OP_SCORE_SCALE,
+            status: status.to_string(),
+            error: info.op_error,
+        })
+    }
+}
+
+/// Quotes a CSV field if it holds a separator, quote or line break.
+pub fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// Returns the history as CSV with a header row.
+pub fn to_csv(entries: &[HistoryEntry]) -> String {
+    let mut csv = String::from(
+        "address,op,direction,tick,amount,counterparty,reveal_id,daa_score,status,error\n",
+    );
+    for entry in entries {
+        let daa_score = entry.daa_score.to_string();
+        let fields = [
+            &entry.address,
+            &entry.op,
+            &entry.direction,
+            &entry.tick,
+            &entry.amount,
+            &entry.counterparty,
+            &entry.reveal_id,
+            &daa_score,
+            &entry.status,
+            &entry.error,
+        ];
+        let row: Vec<String> = fields.iter().map(|field| csv_field(field)).collect();
+        csv.push_str(&row.join(","));
+        csv.push('\n');
+    }
+    csv
+}
+
+/// Exports every KRC-20 op that touched the given addresses to a CSV file at
+/// `output`, oldest first for each address. Amounts are scaled by each
+/// token's decimals.
+///
+/// Takes the lines of a `--b` or `--bs` file: one address per line.
+pub async fn export_op_history(
+    indexer: &IndexerClient,
+    history_args: Vec<String>,
+    output: &str,
+) -> Result<Vec<HistoryEntry>> {
+    let addresses = history_args
+        .iter()
+        .map(|line| line.trim())
+        .filter(|line| !line.is_empty())
+        .map(|line| validate_address(line, "address"))
+        .collect::<Result<Vec<String>>>()?;
+    if addresses.is_empty() {
+        return Err(Error::Custom("History file has no addresses".to_string()));
+    }
+
+    let mut decimals: HashMap<String, u8> = HashMap::new();
+    let mut entries = Vec::new();
+    for address in &addresses {
+        let mut ops = indexer.get_all_ops(address).await?;
+        ops.sort_by_key(|info| info.op_score.trim().parse::<u64>().unwrap_or_default());
+        println!("{address}: {} ops", ops.len());
+        for info in ops {
+            let tick = info.tick.to_lowercase();
+            let dec = match decimals.get(&tick) {
+                Some(dec) => *dec,
+                None if tick.is_empty() => DEFAULT_DECIMALS,
+                None => {
+                    let dec = match indexer.get_token_info(&tick).await? {
+                        Some(token) => parse_decimals(&token.dec)?,
+                        None => DEFAULT_DECIMALS,
+                    };
+                    decimals.insert(tick, dec);
+                    dec
+                }
+            };
+            entries.push(HistoryEntry::new(address, info, dec)?);
+        }
+    }
+
+    fs::write(output, to_csv(&entries))
+        .map_err(|e| Error::Custom(format!("Failed to write {}: {}", output, e)))?;
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(error: &str) -> HistoryEntry {
+        HistoryEntry {
+            address: "kaspa:qz".to_string(),
+            op: "transfer".to_string(),
+            direction: "sent".to_string(),
+            tick: "TEST".to_string(),
+            amount: "1.5".to_string(),
+            counterparty: "kaspa:qr".to_string(),
+            reveal_id: "ab".to_string(),
+            daa_score: 42,
+            status: "rejected".to_string(),
+            error: error.to_string(),
+        }
+    }
+
+    #[test]
+    fn quotes_csv_fields_only_when_needed() {
+        assert_eq!(csv_field("plain"), "plain");
+        assert_eq!(csv_field(""), "");
+        assert_eq!(csv_field("a,b"), "\"a,b\"");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+        assert_eq!(csv_field("two\nlines"), "\"two\nlines\"");
+        assert_eq!(csv_field("cr\r"), "\"cr\r\"");
+    }
+
+    #[test]
+    fn writes_one_row_per_entry() {
+        let csv = to_csv(&[entry(""), entry("balance insufficient, \"fee\" low")]);
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(
+            lines,
+            [
+                "address,op,direction,tick,amount,counterparty,reveal_id,daa_score,status,error",
+                "kaspa:qz,transfer,sent,TEST,1.5,kaspa:qr,ab,42,rejected,",
+                "kaspa:qz,transfer,sent,TEST,1.5,kaspa:qr,ab,42,rejected,\"balance insufficient, \"\"fee\"\" low\"",
+            ]
+        );
+    }
+}
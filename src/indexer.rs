@@ -0,0 +1,520 @@
+use kaspa_wrpc_client::{error::Error, result::Result};
+use num_bigint::BigInt;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+
+pub const MAINNET_URL: &str = "https://api.kasplex.org/v1";
+pub const TESTNET_10_URL: &str = "https://tn10api.kasplex.org/v1";
+pub const TESTNET_11_URL: &str = "https://tn11api.kasplex.org/v1";
+
+/// Envelope shared by every indexer response.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Response<T> {
+    #[serde(default)]
+    pub message: String,
+    #[serde(default = "Vec::new")]
+    pub result: Vec<T>,
+    #[serde(default)]
+    pub next: Option<String>,
+    #[serde(default)]
+    pub prev: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Holder {
+    pub address: String,
+    pub amount: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct TokenInfo {
+    pub tick: String,
+    /// `issue` for issue-mode tokens, empty for mint-mode tokens.
+    #[serde(rename = "mod")]
+    pub mode: String,
+    /// Issue-mode token name.
+    pub name: String,
+    /// Issue-mode contract address, the deploy reveal hash.
+    pub ca: String,
+    /// Current owner of an issue-mode token.
+    pub owner: String,
+    pub max: String,
+    pub lim: String,
+    pub pre: String,
+    pub to: String,
+    pub dec: String,
+    pub minted: String,
+    pub op_score_add: String,
+    pub op_score_mod: String,
+    /// `deployed`, `finished` or `unused`.
+    pub state: String,
+    pub hash_rev: String,
+    pub mts_add: String,
+    pub holder_total: String,
+    pub transfer_total: String,
+    pub mint_total: String,
+    pub holder: Vec<Holder>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Balance {
+    pub tick: String,
+    pub balance: String,
+    pub locked: String,
+    pub dec: String,
+    pub op_score_mod: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct OpInfo {
+    pub p: String,
+    pub op: String,
+    pub tick: String,
+    pub amt: String,
+    pub from: String,
+    pub to: String,
+    pub op_score: String,
+    pub hash_rev: String,
+    pub fee_rev: String,
+    pub tx_accept: String,
+    pub op_accept: String,
+    pub op_error: String,
+    pub mts_add: String,
+    pub mts_mod: String,
+}
+
+/// Final state of a revealed op as seen by the indexer.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "status", content = "error", rename_all = "lowercase")]
+pub enum OpStatus {
+    Accepted,
+    /// Rejected with the indexer's `opError` reason.
+    Rejected(String),
+    #[serde(rename = "timeout")]
+    TimedOut,
+}
+
+/// Machine-readable status line printed once an op is settled.
+#[derive(Debug, Clone, Serialize)]
+pub struct OpReport<'a> {
+    pub reveal_id: &'a str,
+    pub op: &'a str,
+    #[serde(flatten)]
+    pub status: &'a OpStatus,
+}
+
+impl OpReport<'_> {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap_or_default()
+    }
+}
+
+/// Parses an indexer numeric string, treating an empty string as zero.
+pub fn parse_number(value: &str, name: &str) -> Result<BigInt> {
+    if value.trim().is_empty() {
+        return Ok(BigInt::from(0));
+    }
+    value
+        .trim()
+        .parse()
+        .map_err(|e| Error::Custom(format!("Invalid indexer {} '{}': {}", name, value, e)))
+}
+
+/// Client for the Kasplex KRC-20 indexer REST API.
+#[derive(Debug, Clone)]
+pub struct IndexerClient {
+    base_url: String,
+    http: reqwest::Client,
+}
+
+impl IndexerClient {
+    /// Creates a client for the public indexer of `network`.
+    pub fn new(network: &str) -> Result<IndexerClient> {
+        let base_url = match network {
+            "mainnet" => MAINNET_URL,
+            "testnet-10" => TESTNET_10_URL,
+            "testnet-11" => TESTNET_11_URL,
+            _ => {
+                return Err(Error::Custom(format!(
+                    "No KRC-20 indexer known for network {}",
+                    network
+                )))
+            }
+        };
+        Ok(IndexerClient::with_base_url(base_url))
+    }
+
+    /// Creates a client for an indexer at `base_url`, e.g. a self-hosted one.
+    pub fn with_base_url(base_url: &str) -> IndexerClient {
+        IndexerClient {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            http: reqwest::Client::new(),
+        }
+    }
+
+    async fn get<T: DeserializeOwned>(
+        &self,
+        path: &str,
+        query: &[(&str, &str)],
+    ) -> Result<Response<T>> {
+        let url = format!("{}{}", self.base_url, path);
+        let response = self
+            .http
+            .get(&url)
+            .query(query)
+            .send()
+            .await
+            .map_err(|e| Error::Custom(format!("Indexer request to {} failed: {}", url, e)))?;
+        let status = response.status();
+        let body = response
+            .text()
+            .await
+            .map_err(|e| Error::Custom(format!("Failed to read indexer response: {}", e)))?;
+        if !status.is_success() {
+            return Err(Error::Custom(format!(
+                "Indexer returned {} for {}: {}",
+                status, url, body
+            )));
+        }
+        serde_json::from_str(&body)
+            .map_err(|e| Error::Custom(format!("Failed to parse indexer response: {}", e)))
+    }
+
+    /// Returns info for `tick`, or `None` if the indexer does not know it.
+    pub async fn get_token_info(&self, tick: &str) -> Result<Option<TokenInfo>> {
+        let path = format!("/krc20/token/{}", tick.to_lowercase());
+        let response: Response<TokenInfo> = self.get(&path, &[]).await?;
+        Ok(response.result.into_iter().next())
+    }
+
+    /// Returns info for `tick` with its holder list, or `None` if the indexer
+    /// does not know it. The indexer only lists the largest holders, up to
+    /// `holder_total`.
+    pub async fn get_token_holders(&self, tick: &str) -> Result<Option<TokenInfo>> {
+        let path = format!("/krc20/token/{}", tick.to_lowercase());
+        let response: Response<TokenInfo> = self.get(&path, &[("holder", "true")]).await?;
+        Ok(response.result.into_iter().next())
+    }
+
+    /// Returns one page of the token balances held by `address`.
+    pub async fn get_balance_list(
+        &self,
+        address: &str,
+        next: Option<&str>,
+    ) -> Result<Response<Balance>> {
+        let path = format!("/krc20/address/{}/tokenlist", address);
+        let query: Vec<(&str, &str)> = next.map(|next| ("next", next)).into_iter().collect();
+        self.get(&path, &query).await
+    }
+
+    /// Returns every token balance held by `address`, following pagination.
+    pub async fn get_all_balances(&self, address: &str) -> Result<Vec<Balance>> {
+        let mut balances = Vec::new();
+        let mut next: Option<String> = None;
+        loop {
+            let page = self.get_balance_list(address, next.as_deref()).await?;
+            let done = page.result.is_empty();
+            balances.extend(page.result);
+            match page.next.filter(|next| !next.is_empty()) {
+                Some(cursor) if !done => next = Some(cursor),
+                _ => break,
+            }
+        }
+        Ok(balances)
+    }
+
+    /// Returns the balance of `tick` held by `address`, if any.
+    pub async fn get_balance(&self, address: &str, tick: &str) -> Result<Option<Balance>> {
+        let path = format!("/krc20/address/{}/token/{}", address, tick.to_lowercase());
+        let response: Response<Balance> = self.get(&path, &[]).await?;
+        Ok(response.result.into_iter().next())
+    }
+
+    /// Returns the indexer's view of the op revealed in transaction `hash`,
+    /// or `None` if it has not been indexed yet.
+    pub async fn get_op(&self, hash: &str) -> Result<Option<OpInfo>> {
+        let path = format!("/krc20/op/{}", hash);
+        let response: Response<OpInfo> = self.get(&path, &[]).await?;
+        Ok(response.result.into_iter().next())
+    }
+
+    /// Returns one page of the ops involving `address`, optionally limited to
+    /// `tick`.
+    pub async fn get_op_list(
+        &self,
+        address: &str,
+        tick: Option<&str>,
+        next: Option<&str>,
+    ) -> Result<Response<OpInfo>> {
+        let mut query = vec![("address", address)];
+        if let Some(tick) = tick {
+            query.push(("tick", tick));
+        }
+        if let Some(next) = next {
+            query.push(("next", next));
+        }
+        self.get("/krc20/oplist", &query).await
+    }
+
+    /// Returns every op involving `address`, following pagination.
+    pub async fn get_all_ops(&self, address: &str) -> Result<Vec<OpInfo>> {
+        let mut ops = Vec::new();
+        let mut next: Option<String> = None;
+        loop {
+            let page = self.get_op_list(address, None, next.as_deref()).await?;
+            let done = page.result.is_empty();
+            ops.extend(page.result);
+            match page.next.filter(|next| !next.is_empty()) {
+                Some(cursor) if !done => next = Some(cursor),
+                _ => break,
+            }
+        }
+        Ok(ops)
+    }
+
+    /// Polls the op for reveal `hash` every `interval` until the indexer
+    /// accepts or rejects it, or `timeout` elapses. Transient request errors
+    /// are retried until the timeout.
+    pub async fn wait_for_op(
+        &self,
+        hash: &str,
+        timeout: Duration,
+        interval: Duration,
+    ) -> (Option<OpInfo>, OpStatus) {
+        let started = Instant::now();
+        let mut last: Option<OpInfo> = None;
+        while started.elapsed() < timeout {
+            if let Ok(Some(info)) = self.get_op(hash).await {
+                match info.op_accept.as_str() {
+                    "1" => return (Some(info), OpStatus::Accepted),
+                    "-1" => {
+                        let error = info.op_error.clone();
+                        return (Some(info), OpStatus::Rejected(error));
+                    }
+                    _ => last = Some(info),
+                }
+            }
+            tokio::time::sleep(interval).await;
+        }
+        (last, OpStatus::TimedOut)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    const ADDRESS: &str = "kaspa:qyp4ax6yvnd0wes4ds0wmg0jq9tkuu8ftdls0ndcfdhhqj8alvfn6kqpc6xs9pq";
+    const REVEAL: &str = "a1b2c3";
+
+    /// Serves canned Kasplex responses on a local port. `route` maps each
+    /// request's path and query to a status and body. Returns a client for
+    /// the server and the requests it received.
+    async fn serve<F>(route: F) -> (IndexerClient, Arc<Mutex<Vec<String>>>)
+    where
+        F: Fn(&str) -> (u16, String) + Send + Sync + 'static,
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base_url = format!("http://{}/v1", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let received = requests.clone();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let mut request = Vec::new();
+                let mut buf = [0u8; 1024];
+                while !request.ends_with(b"\r\n\r\n") {
+                    match stream.read(&mut buf).await {
+                        Ok(0) | Err(_) => break,
+                        Ok(n) => request.extend_from_slice(&buf[..n]),
+                    }
+                }
+                let request = String::from_utf8_lossy(&request);
+                let target = request.split(' ').nth(1).unwrap_or_default();
+                let target = target.strip_prefix("/v1").unwrap_or(target).to_string();
+                let (status, body) = route(&target);
+                received.lock().unwrap().push(target);
+                let response = format!(
+                    "HTTP/1.1 {} Status\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    status,
+                    body.len(),
+                    body
+                );
+                let _ = stream.write_all(response.as_bytes()).await;
+            }
+        });
+        (IndexerClient::with_base_url(&base_url), requests)
+    }
+
+    fn op(hash: &str, op_accept: &str, op_error: &str) -> String {
+        format!(
+            r#"{{"p":"KRC-20","op":"transfer","tick":"TEST","amt":"100","hashRev":"{}","opAccept":"{}","opError":"{}"}}"#,
+            hash, op_accept, op_error
+        )
+    }
+
+    fn page(result: &[String], next: Option<&str>) -> String {
+        let next = next
+            .map(|next| format!(r#""{}""#, next))
+            .unwrap_or("null".to_string());
+        format!(
+            r#"{{"message":"successful","result":[{}],"next":{},"prev":null}}"#,
+            result.join(","),
+            next
+        )
+    }
+
+    #[tokio::test]
+    async fn walks_every_op_page() {
+        let (indexer, requests) = serve(|target| {
+            let body = if target.contains("next=page2") {
+                page(&[op("c", "1", "")], Some("page3"))
+            } else if target.contains("next=page3") {
+                page(&[], Some("page4"))
+            } else {
+                page(&[op("a", "1", ""), op("b", "-1", "")], Some("page2"))
+            };
+            (200, body)
+        })
+        .await;
+
+        let ops = indexer.get_all_ops(ADDRESS).await.unwrap();
+        let hashes: Vec<&str> = ops.iter().map(|op| op.hash_rev.as_str()).collect();
+        assert_eq!(hashes, ["a", "b", "c"]);
+        // The empty third page ends the walk even though it has a cursor
+        let requests = requests.lock().unwrap();
+        let address = ADDRESS.replace(':', "%3A");
+        assert_eq!(requests.len(), 3);
+        assert_eq!(requests[0], format!("/krc20/oplist?address={}", address));
+        assert_eq!(
+            requests[1],
+            format!("/krc20/oplist?address={}&next=page2", address)
+        );
+        assert!(requests[2].ends_with("&next=page3"));
+    }
+
+    #[tokio::test]
+    async fn walks_every_balance_page() {
+        let balance = |tick: &str| format!(r#"{{"tick":"{}","balance":"5","dec":"8"}}"#, tick);
+        let (indexer, requests) = serve(move |target| {
+            let body = if target.ends_with("?next=page2") {
+                page(&[balance("TWO")], None)
+            } else {
+                page(&[balance("ONE")], Some("page2"))
+            };
+            (200, body)
+        })
+        .await;
+
+        let balances = indexer.get_all_balances(ADDRESS).await.unwrap();
+        let ticks: Vec<&str> = balances
+            .iter()
+            .map(|balance| balance.tick.as_str())
+            .collect();
+        assert_eq!(ticks, ["ONE", "TWO"]);
+        assert_eq!(
+            *requests.lock().unwrap(),
+            [
+                format!("/krc20/address/{}/tokenlist", ADDRESS),
+                format!("/krc20/address/{}/tokenlist?next=page2", ADDRESS),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn reads_token_holders() {
+        let (indexer, requests) = serve(|_| {
+            let token = r#"{"tick":"TEST","max":"1000","dec":"8","minted":"300","holderTotal":"2","holder":[{"address":"a","amount":"200"},{"address":"b","amount":"100"}]}"#;
+            (200, page(&[token.to_string()], None))
+        })
+        .await;
+
+        let info = indexer.get_token_holders("TEST").await.unwrap().unwrap();
+        assert_eq!(info.holder_total, "2");
+        assert_eq!(info.holder.len(), 2);
+        assert_eq!(info.holder[1].amount, "100");
+        assert_eq!(requests.lock().unwrap()[0], "/krc20/token/test?holder=true");
+    }
+
+    #[tokio::test]
+    async fn maps_op_outcomes() {
+        let (indexer, _) = serve(|target| {
+            let result = match target.rsplit('/').next().unwrap_or_default() {
+                "accepted" => vec![op("accepted", "1", "")],
+                "rejected" => vec![op("rejected", "-1", "insufficient fee")],
+                "pending" => vec![op("pending", "0", "")],
+                _ => vec![],
+            };
+            (200, page(&result, None))
+        })
+        .await;
+        let poll = |hash: &'static str| {
+            let indexer = indexer.clone();
+            async move {
+                indexer
+                    .wait_for_op(hash, Duration::from_millis(200), Duration::from_millis(20))
+                    .await
+            }
+        };
+
+        let (info, status) = poll("accepted").await;
+        assert_eq!(status, OpStatus::Accepted);
+        assert_eq!(info.unwrap().hash_rev, "accepted");
+
+        let (_, status) = poll("rejected").await;
+        assert_eq!(status, OpStatus::Rejected("insufficient fee".to_string()));
+
+        // An op that stays pending times out with its last known info
+        let (info, status) = poll("pending").await;
+        assert_eq!(status, OpStatus::TimedOut);
+        assert_eq!(info.unwrap().op_accept, "0");
+
+        // So does one the indexer never lists
+        let (info, status) = poll(REVEAL).await;
+        assert_eq!(status, OpStatus::TimedOut);
+        assert!(info.is_none());
+        assert!(indexer.get_op(REVEAL).await.unwrap().is_none());
+
+        let report = OpReport {
+            reveal_id: REVEAL,
+            op: "transfer",
+            status: &OpStatus::Rejected("insufficient fee".to_string()),
+        };
+        assert_eq!(
+            report.to_json(),
+            r#"{"reveal_id":"a1b2c3","op":"transfer","status":"rejected","error":"insufficient fee"}"#
+        );
+    }
+
+    #[tokio::test]
+    async fn reports_error_bodies() {
+        let (indexer, _) = serve(|target| {
+            if target.contains("broken") {
+                (200, "not json".to_string())
+            } else {
+                (500, r#"{"message":"internal error"}"#.to_string())
+            }
+        })
+        .await;
+
+        let error = indexer
+            .get_token_info("TEST")
+            .await
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("500"), "{error}");
+        assert!(error.contains("internal error"), "{error}");
+        let error = indexer.get_op("broken").await.unwrap_err().to_string();
+        assert!(
+            error.contains("Failed to parse indexer response"),
+            "{error}"
+        );
+    }
+}
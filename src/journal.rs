@@ -0,0 +1,168 @@
+use kaspa_consensus_core::tx::{ScriptPublicKey, TransactionOutput};
+use kaspa_wrpc_client::{error::Error, result::Result};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File, OpenOptions};
+use std::io::prelude::*;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+
+/// Default location of the reveal recovery journal.
+pub const JOURNAL_FILE: &str = "reveal-journal.jsonl";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EntryStatus {
+    /// The commit was (or is about to be) broadcast and has not been spent.
+    Pending,
+    Revealed,
+    Refunded,
+}
+
+/// Everything needed to spend a P2SH commit output without the process that
+/// created it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JournalEntry {
+    /// Inscribed op JSON.
+    pub op: String,
+    /// Hex encoded redeem script.
+    pub redeem_script: String,
+    pub p2sh_address: String,
+    pub commit_id: String,
+    /// Index of the P2SH output in the commit. Batch commits lock several.
+    #[serde(default)]
+    pub output_index: u32,
+    /// Address of the key that signs the reveal.
+    pub key: String,
+    pub status: EntryStatus,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reveal_id: Option<String>,
+    /// Reveal outputs before the change, e.g. the P2SH output a listing locks
+    /// its tokens in or a mint royalty.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub outputs: Vec<JournalOutput>,
+}
+
+/// A reveal output as recorded in the journal.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JournalOutput {
+    pub value: u64,
+    pub version: u16,
+    /// Hex encoded script public key.
+    pub script: String,
+}
+
+impl From<&TransactionOutput> for JournalOutput {
+    fn from(output: &TransactionOutput) -> JournalOutput {
+        JournalOutput {
+            value: output.value,
+            version: output.script_public_key.version(),
+            script: hex::encode(output.script_public_key.script()),
+        }
+    }
+}
+
+impl JournalOutput {
+    pub fn to_output(&self) -> Result<TransactionOutput> {
+        let script = hex::decode(&self.script)
+            .map_err(|e| Error::Custom(format!("Invalid journal output script: {}", e)))?;
+        Ok(TransactionOutput::new(
+            self.value,
+            ScriptPublicKey::from_vec(self.version, script),
+        ))
+    }
+}
+
+/// A JSON lines file with one entry per commit transaction.
+pub struct Journal {
+    path: PathBuf,
+}
+
+impl Journal {
+    pub fn new<P: AsRef<Path>>(path: P) -> Journal {
+        Journal {
+            path: path.as_ref().to_path_buf(),
+        }
+    }
+
+    pub fn entries(&self) -> Result<Vec<JournalEntry>> {
+        let contents = match fs::read_to_string(&self.path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(e) => return Err(Error::Custom(format!("Failed to read journal: {}", e))),
+        };
+        contents
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| {
+                serde_json::from_str(line)
+                    .map_err(|e| Error::Custom(format!("Failed to parse journal entry: {}", e)))
+            })
+            .collect()
+    }
+
+    pub fn pending(&self) -> Result<Vec<JournalEntry>> {
+        Ok(self
+            .entries()?
+            .into_iter()
+            .filter(|entry| entry.status == EntryStatus::Pending)
+            .collect())
+    }
+
+    /// Appends an entry and flushes it to disk.
+    pub fn append(&self, entry: &JournalEntry) -> Result<()> {
+        let line = serde_json::to_string(entry)
+            .map_err(|e| Error::Custom(format!("Failed to serialize journal entry: {}", e)))?;
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .map_err(|e| Error::Custom(format!("Failed to open journal: {}", e)))?;
+        writeln!(file, "{}", line)
+            .and_then(|_| file.sync_all())
+            .map_err(|e| Error::Custom(format!("Failed to write journal: {}", e)))
+    }
+
+    /// Marks the entry for output `output_index` of `commit_id` as spent by
+    /// `reveal_id`.
+    pub fn update(
+        &self,
+        commit_id: &str,
+        output_index: u32,
+        status: EntryStatus,
+        reveal_id: &str,
+    ) -> Result<()> {
+        let mut entries = self.entries()?;
+        let entry = entries
+            .iter_mut()
+            .find(|entry| entry.commit_id == commit_id && entry.output_index == output_index)
+            .ok_or_else(|| {
+                Error::Custom(format!(
+                    "No journal entry for commit {}:{}",
+                    commit_id, output_index
+                ))
+            })?;
+        entry.status = status;
+        entry.reveal_id = Some(reveal_id.to_string());
+        self.write(&entries)
+    }
+
+    fn write(&self, entries: &[JournalEntry]) -> Result<()> {
+        let tmp_path = self.path.with_extension("jsonl.tmp");
+        let file = File::create(&tmp_path)
+            .map_err(|e| Error::Custom(format!("Failed to create journal: {}", e)))?;
+        let mut writer = BufWriter::new(file);
+        for entry in entries {
+            let line = serde_json::to_string(entry)
+                .map_err(|e| Error::Custom(format!("Failed to serialize journal entry: {}", e)))?;
+            writeln!(writer, "{}", line)
+                .map_err(|e| Error::Custom(format!("Failed to write journal: {}", e)))?;
+        }
+        writer
+            .into_inner()
+            .map_err(|e| Error::Custom(format!("Failed to write journal: {}", e)))?
+            .sync_all()
+            .map_err(|e| Error::Custom(format!("Failed to write journal: {}", e)))?;
+        fs::rename(&tmp_path, &self.path)
+            .map_err(|e| Error::Custom(format!("Failed to replace journal: {}", e)))
+    }
+}
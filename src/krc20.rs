@@ -0,0 +1,823 @@
+use kaspa_addresses::Address;
+use kaspa_wrpc_client::{error::Error, result::Result};
+use num_bigint::{BigInt, Sign};
+use serde::{Deserialize, Serialize};
+
+/// Largest supply, mint limit or pre-allocation accepted by the protocol.
+pub const MAX_AMOUNT: u64 = u64::MAX;
+pub const MAX_DECIMALS: u8 = 18;
+pub const DEFAULT_DECIMALS: u8 = 8;
+pub const PROTOCOL: &str = "krc-20";
+
+/// Checks that a ticker is 4-6 ASCII letters and returns it lowercased.
+pub fn validate_ticker(tick: &str) -> Result<String> {
+    let tick = tick.trim();
+    if !(4..=6).contains(&tick.len()) || !tick.chars().all(|c| c.is_ascii_alphabetic()) {
+        return Err(Error::Custom(format!(
+            "Invalid ticker '{}': must be 4 to 6 ASCII letters",
+            tick
+        )));
+    }
+    Ok(tick.to_lowercase())
+}
+
+/// Parses a positive integer amount in atomic units that fits the protocol bounds.
+pub fn parse_amount(value: &str, name: &str) -> Result<BigInt> {
+    let value = value.trim();
+    if value.is_empty() || !value.chars().all(|c| c.is_ascii_digit()) {
+        return Err(Error::Custom(format!(
+            "Invalid {} '{}': must be a positive integer",
+            name, value
+        )));
+    }
+    let amount: BigInt = value
+        .parse()
+        .map_err(|e| Error::Custom(format!("Invalid {} '{}': {}", name, value, e)))?;
+    check_amount(&amount, name)?;
+    Ok(amount)
+}
+
+/// Checks that an amount is positive and within the protocol bounds.
+pub fn check_amount(amount: &BigInt, name: &str) -> Result<()> {
+    if *amount <= BigInt::from(0) {
+        return Err(Error::Custom(format!(
+            "Invalid {}: must be greater than 0",
+            name
+        )));
+    }
+    if *amount > BigInt::from(MAX_AMOUNT) {
+        return Err(Error::Custom(format!(
+            "Invalid {}: must not exceed {}",
+            name, MAX_AMOUNT
+        )));
+    }
+    Ok(())
+}
+
+/// Converts a human amount such as "12.5" into atomic units scaled by `dec`.
+pub fn parse_human_amount(value: &str, dec: u8, name: &str) -> Result<BigInt> {
+    let value = value.trim();
+    if value.starts_with('-') {
+        return Err(Error::Custom(format!(
+            "Invalid {} '{}': must not be negative",
+            name, value
+        )));
+    }
+    let (integer, fraction) = value.split_once('.').unwrap_or((value, ""));
+    let is_digits = |part: &str| part.chars().all(|c| c.is_ascii_digit());
+    if (integer.is_empty() && fraction.is_empty()) || !is_digits(integer) || !is_digits(fraction) {
+        return Err(Error::Custom(format!(
+            "Invalid {} '{}': must be a decimal number",
+            name, value
+        )));
+    }
+    if fraction.len() > dec as usize {
+        return Err(Error::Custom(format!(
+            "Invalid {} '{}': at most {} fractional digits allowed",
+            name, value, dec
+        )));
+    }
+    let digits = format!("{}{:0<width$}", integer, fraction, width = dec as usize);
+    let amount: BigInt = digits
+        .parse()
+        .map_err(|e| Error::Custom(format!("Invalid {} '{}': {}", name, value, e)))?;
+    check_amount(&amount, name)?;
+    Ok(amount)
+}
+
+/// Formats an atomic amount in human units, e.g. 1250000000 with 8 decimals
+/// as "12.5".
+pub fn format_amount(amount: &BigInt, dec: u8) -> String {
+    let sign = if amount.sign() == Sign::Minus {
+        "-"
+    } else {
+        ""
+    };
+    let digits = amount.magnitude().to_string();
+    let dec = dec as usize;
+    if dec == 0 {
+        return format!("{}{}", sign, digits);
+    }
+    let padded = format!("{:0>width$}", digits, width = dec + 1);
+    let (integer, fraction) = padded.split_at(padded.len() - dec);
+    let fraction = fraction.trim_end_matches('0');
+    if fraction.is_empty() {
+        format!("{}{}", sign, integer)
+    } else {
+        format!("{}{}.{}", sign, integer, fraction)
+    }
+}
+
+/// Parses the token decimals, defaulting to 8 when empty.
+pub fn parse_decimals(value: &str) -> Result<u8> {
+    let value = value.trim();
+    if value.is_empty() {
+        return Ok(DEFAULT_DECIMALS);
+    }
+    match value.parse::<u8>() {
+        Ok(dec) if dec <= MAX_DECIMALS => Ok(dec),
+        _ => Err(Error::Custom(format!(
+            "Invalid dec '{}': must be between 0 and {}",
+            value, MAX_DECIMALS
+        ))),
+    }
+}
+
+/// Checks that `address` is a valid Kaspa address.
+pub fn validate_address(address: &str, name: &str) -> Result<String> {
+    let address = address.trim();
+    match Address::try_from(address) {
+        Ok(_) => Ok(address.to_string()),
+        Err(e) => Err(Error::Custom(format!(
+            "Invalid {} address '{}': {}",
+            name, address, e
+        ))),
+    }
+}
+
+/// Checks that an issue-mode contract address (the deploy reveal hash) is a
+/// 64 character hex string and returns it lowercased.
+pub fn validate_contract(ca: &str) -> Result<String> {
+    let ca = ca.trim();
+    if ca.len() != 64 || !ca.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(Error::Custom(format!(
+            "Invalid ca '{}': must be a 64 character hex string",
+            ca
+        )));
+    }
+    Ok(ca.to_lowercase())
+}
+
+/// Treats a missing or blank optional value as absent.
+pub fn optional(value: Option<&str>) -> Option<&str> {
+    value.map(str::trim).filter(|value| !value.is_empty())
+}
+
+/// Serializes numeric fields as strings, which is how the indexer expects
+/// amounts and decimals to be encoded.
+pub mod as_string {
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+    use std::fmt::Display;
+    use std::str::FromStr;
+
+    pub fn serialize<T: Display, S: Serializer>(
+        value: &T,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(value)
+    }
+
+    pub fn deserialize<'de, T, D>(deserializer: D) -> Result<T, D::Error>
+    where
+        T: FromStr,
+        T::Err: Display,
+        D: Deserializer<'de>,
+    {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(D::Error::custom)
+    }
+}
+
+pub mod option_as_string {
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+    use std::fmt::Display;
+    use std::str::FromStr;
+
+    pub fn serialize<T: Display, S: Serializer>(
+        value: &Option<T>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match value {
+            Some(value) => serializer.collect_str(value),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, T, D>(deserializer: D) -> Result<Option<T>, D::Error>
+    where
+        T: FromStr,
+        T::Err: Display,
+        D: Deserializer<'de>,
+    {
+        Option::<String>::deserialize(deserializer)?
+            .map(|value| value.parse().map_err(D::Error::custom))
+            .transpose()
+    }
+}
+
+/// Token mode of a deploy. Issue-mode tokens are minted on demand by an owner.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DeployMode {
+    Issue,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Deploy {
+    #[serde(rename = "mod", default, skip_serializing_if = "Option::is_none")]
+    pub mode: Option<DeployMode>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tick: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(with = "as_string")]
+    pub max: BigInt,
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "option_as_string"
+    )]
+    pub lim: Option<BigInt>,
+    #[serde(with = "as_string")]
+    pub dec: u8,
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "option_as_string"
+    )]
+    pub pre: Option<BigInt>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub to: Option<String>,
+}
+
+impl Deploy {
+    /// Builds a validated mint-mode deploy.
+    pub fn new(
+        tick: &str,
+        max: &str,
+        lim: &str,
+        dec: &str,
+        pre: Option<&str>,
+        to: Option<&str>,
+    ) -> Result<Deploy> {
+        let deploy = Deploy {
+            mode: None,
+            tick: Some(validate_ticker(tick)?),
+            name: None,
+            max: parse_amount(max, "max")?,
+            lim: Some(parse_amount(lim, "lim")?),
+            dec: parse_decimals(dec)?,
+            pre: optional(pre)
+                .map(|pre| parse_amount(pre, "pre"))
+                .transpose()?,
+            to: optional(to)
+                .map(|to| validate_address(to, "to"))
+                .transpose()?,
+        };
+        deploy.validate()?;
+        Ok(deploy)
+    }
+
+    /// Builds a validated issue-mode deploy. `to` is the initial owner and
+    /// receiver of `pre`, defaulting to the deployer.
+    pub fn new_issue(
+        name: &str,
+        max: &str,
+        dec: &str,
+        pre: Option<&str>,
+        to: Option<&str>,
+    ) -> Result<Deploy> {
+        let deploy = Deploy {
+            mode: Some(DeployMode::Issue),
+            tick: None,
+            name: Some(validate_ticker(name)?),
+            max: parse_amount(max, "max")?,
+            lim: None,
+            dec: parse_decimals(dec)?,
+            pre: optional(pre)
+                .map(|pre| parse_amount(pre, "pre"))
+                .transpose()?,
+            to: optional(to)
+                .map(|to| validate_address(to, "to"))
+                .transpose()?,
+        };
+        deploy.validate()?;
+        Ok(deploy)
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        match self.mode {
+            None => {
+                let tick = self
+                    .tick
+                    .as_deref()
+                    .ok_or_else(|| Error::Custom("Deploy is missing tick".to_string()))?;
+                validate_ticker(tick)?;
+                let lim = self
+                    .lim
+                    .as_ref()
+                    .ok_or_else(|| Error::Custom("Deploy is missing lim".to_string()))?;
+                check_amount(lim, "lim")?;
+                if lim > &self.max {
+                    return Err(Error::Custom(
+                        "Invalid lim: must not exceed max".to_string(),
+                    ));
+                }
+            }
+            Some(DeployMode::Issue) => {
+                let name = self.name.as_deref().ok_or_else(|| {
+                    Error::Custom("Issue-mode deploy is missing name".to_string())
+                })?;
+                validate_ticker(name)?;
+                if self.lim.is_some() {
+                    return Err(Error::Custom(
+                        "Issue-mode deploy must not set lim".to_string(),
+                    ));
+                }
+            }
+        }
+        check_amount(&self.max, "max")?;
+        if self.dec > MAX_DECIMALS {
+            return Err(Error::Custom(format!(
+                "Invalid dec '{}': must be between 0 and {}",
+                self.dec, MAX_DECIMALS
+            )));
+        }
+        if let Some(pre) = &self.pre {
+            check_amount(pre, "pre")?;
+            if pre > &self.max {
+                return Err(Error::Custom(
+                    "Invalid pre: must not exceed max".to_string(),
+                ));
+            }
+        }
+        if let Some(to) = &self.to {
+            validate_address(to, "to")?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Mint {
+    pub tick: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub to: Option<String>,
+}
+
+impl Mint {
+    pub fn new(tick: &str, to: Option<&str>) -> Result<Mint> {
+        let mint = Mint {
+            tick: validate_ticker(tick)?,
+            to: optional(to)
+                .map(|to| validate_address(to, "to"))
+                .transpose()?,
+        };
+        Ok(mint)
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        validate_ticker(&self.tick)?;
+        if let Some(to) = &self.to {
+            validate_address(to, "to")?;
+        }
+        Ok(())
+    }
+}
+
+/// Transfer of a mint-mode token by `tick` or an issue-mode token by `ca`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Transfer {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tick: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ca: Option<String>,
+    #[serde(with = "as_string")]
+    pub amt: BigInt,
+    pub to: String,
+}
+
+impl Transfer {
+    /// Builds a validated transfer of `amt` human units of a token with `dec`
+    /// decimals.
+    pub fn new(tick: &str, amt: &str, dec: u8, to: &str) -> Result<Transfer> {
+        let transfer = Transfer {
+            tick: Some(validate_ticker(tick)?),
+            ca: None,
+            amt: parse_human_amount(amt, dec, "amt")?,
+            to: validate_address(to, "to")?,
+        };
+        Ok(transfer)
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        match (&self.tick, &self.ca) {
+            (Some(tick), None) => {
+                validate_ticker(tick)?;
+            }
+            (None, Some(ca)) => {
+                validate_contract(ca)?;
+            }
+            _ => {
+                return Err(Error::Custom(
+                    "Transfer must set exactly one of tick or ca".to_string(),
+                ))
+            }
+        }
+        check_amount(&self.amt, "amt")?;
+        validate_address(&self.to, "to")?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct List {
+    pub tick: String,
+    #[serde(with = "as_string")]
+    pub amt: BigInt,
+}
+
+impl List {
+    /// Builds a validated listing of `amt` human units of a token with `dec`
+    /// decimals.
+    pub fn new(tick: &str, amt: &str, dec: u8) -> Result<List> {
+        Ok(List {
+            tick: validate_ticker(tick)?,
+            amt: parse_human_amount(amt, dec, "amt")?,
+        })
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        validate_ticker(&self.tick)?;
+        check_amount(&self.amt, "amt")?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Send {
+    pub tick: String,
+}
+
+impl Send {
+    pub fn new(tick: &str) -> Result<Send> {
+        Ok(Send {
+            tick: validate_ticker(tick)?,
+        })
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        validate_ticker(&self.tick)?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Issue {
+    pub ca: String,
+    #[serde(with = "as_string")]
+    pub amt: BigInt,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub to: Option<String>,
+}
+
+impl Issue {
+    /// Builds a validated issue of `amt` human units of a token with `dec`
+    /// decimals, to `to` or the owner if unset.
+    pub fn new(ca: &str, amt: &str, dec: u8, to: Option<&str>) -> Result<Issue> {
+        Ok(Issue {
+            ca: validate_contract(ca)?,
+            amt: parse_human_amount(amt, dec, "amt")?,
+            to: optional(to)
+                .map(|to| validate_address(to, "to"))
+                .transpose()?,
+        })
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        validate_contract(&self.ca)?;
+        check_amount(&self.amt, "amt")?;
+        if let Some(to) = &self.to {
+            validate_address(to, "to")?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Burn {
+    pub ca: String,
+    #[serde(with = "as_string")]
+    pub amt: BigInt,
+}
+
+impl Burn {
+    /// Builds a validated burn of `amt` human units of a token with `dec`
+    /// decimals.
+    pub fn new(ca: &str, amt: &str, dec: u8) -> Result<Burn> {
+        Ok(Burn {
+            ca: validate_contract(ca)?,
+            amt: parse_human_amount(amt, dec, "amt")?,
+        })
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        validate_contract(&self.ca)?;
+        check_amount(&self.amt, "amt")?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Chown {
+    pub ca: String,
+    pub to: String,
+}
+
+impl Chown {
+    pub fn new(ca: &str, to: &str) -> Result<Chown> {
+        Ok(Chown {
+            ca: validate_contract(ca)?,
+            to: validate_address(to, "to")?,
+        })
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        validate_contract(&self.ca)?;
+        validate_address(&self.to, "to")?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BlacklistMode {
+    Add,
+    Remove,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Blacklist {
+    pub ca: String,
+    #[serde(rename = "mod")]
+    pub mode: BlacklistMode,
+    pub to: String,
+}
+
+impl Blacklist {
+    pub fn new(ca: &str, mode: BlacklistMode, to: &str) -> Result<Blacklist> {
+        Ok(Blacklist {
+            ca: validate_contract(ca)?,
+            mode,
+            to: validate_address(to, "to")?,
+        })
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        validate_contract(&self.ca)?;
+        validate_address(&self.to, "to")?;
+        Ok(())
+    }
+}
+
+/// A KRC-20 operation as inscribed in a reveal transaction.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub enum Krc20Op {
+    Deploy(Deploy),
+    Mint(Mint),
+    Transfer(Transfer),
+    List(List),
+    Send(Send),
+    Issue(Issue),
+    Burn(Burn),
+    Chown(Chown),
+    Blacklist(Blacklist),
+}
+
+/// The `p` field followed by the flattened op, so the JSON fields come out in
+/// the order the indexer expects.
+#[derive(Serialize, Deserialize)]
+struct Envelope {
+    p: String,
+    #[serde(flatten)]
+    op: Krc20Op,
+}
+
+impl Krc20Op {
+    pub fn validate(&self) -> Result<()> {
+        match self {
+            Krc20Op::Deploy(op) => op.validate(),
+            Krc20Op::Mint(op) => op.validate(),
+            Krc20Op::Transfer(op) => op.validate(),
+            Krc20Op::List(op) => op.validate(),
+            Krc20Op::Send(op) => op.validate(),
+            Krc20Op::Issue(op) => op.validate(),
+            Krc20Op::Burn(op) => op.validate(),
+            Krc20Op::Chown(op) => op.validate(),
+            Krc20Op::Blacklist(op) => op.validate(),
+        }
+    }
+
+    /// Lowercases the tickers and contract addresses, which the protocol
+    /// compares case-insensitively.
+    fn normalize(&mut self) {
+        fn lowercase(value: &mut String) {
+            *value = value.trim().to_lowercase();
+        }
+        match self {
+            Krc20Op::Deploy(op) => {
+                op.tick.iter_mut().for_each(lowercase);
+                op.name.iter_mut().for_each(lowercase);
+            }
+            Krc20Op::Mint(op) => lowercase(&mut op.tick),
+            Krc20Op::Transfer(op) => {
+                op.tick.iter_mut().for_each(lowercase);
+                op.ca.iter_mut().for_each(lowercase);
+            }
+            Krc20Op::List(op) => lowercase(&mut op.tick),
+            Krc20Op::Send(op) => lowercase(&mut op.tick),
+            Krc20Op::Issue(op) => lowercase(&mut op.ca),
+            Krc20Op::Burn(op) => lowercase(&mut op.ca),
+            Krc20Op::Chown(op) => lowercase(&mut op.ca),
+            Krc20Op::Blacklist(op) => lowercase(&mut op.ca),
+        }
+    }
+
+    /// Returns the lowercase `op` name.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Krc20Op::Deploy(_) => "deploy",
+            Krc20Op::Mint(_) => "mint",
+            Krc20Op::Transfer(_) => "transfer",
+            Krc20Op::List(_) => "list",
+            Krc20Op::Send(_) => "send",
+            Krc20Op::Issue(_) => "issue",
+            Krc20Op::Burn(_) => "burn",
+            Krc20Op::Chown(_) => "chown",
+            Krc20Op::Blacklist(_) => "blacklist",
+        }
+    }
+
+    /// Returns the ticker for mint-mode ops, or the contract address for
+    /// issue-mode ops.
+    pub fn token(&self) -> Option<&str> {
+        match self {
+            Krc20Op::Deploy(op) => op.tick.as_deref().or(op.name.as_deref()),
+            Krc20Op::Mint(op) => Some(&op.tick),
+            Krc20Op::Transfer(op) => op.tick.as_deref().or(op.ca.as_deref()),
+            Krc20Op::List(op) => Some(&op.tick),
+            Krc20Op::Send(op) => Some(&op.tick),
+            Krc20Op::Issue(op) => Some(&op.ca),
+            Krc20Op::Burn(op) => Some(&op.ca),
+            Krc20Op::Chown(op) => Some(&op.ca),
+            Krc20Op::Blacklist(op) => Some(&op.ca),
+        }
+    }
+
+    /// Returns the canonical inscription JSON for this op.
+    pub fn to_json(&self) -> Result<String> {
+        let envelope = Envelope {
+            p: PROTOCOL.to_string(),
+            op: self.clone(),
+        };
+        serde_json::to_string(&envelope)
+            .map_err(|e| Error::Custom(format!("Failed to serialize KRC-20 op: {}", e)))
+    }
+
+    /// Parses and validates op JSON taken from an inscription, with its
+    /// tickers lowercased.
+    pub fn from_json(json: &str) -> Result<Krc20Op> {
+        let mut envelope: Envelope = serde_json::from_str(json)
+            .map_err(|e| Error::Custom(format!("Failed to parse KRC-20 op: {}", e)))?;
+        if !envelope.p.eq_ignore_ascii_case(PROTOCOL) {
+            return Err(Error::Custom(format!(
+                "Unexpected protocol '{}', expected '{}'",
+                envelope.p, PROTOCOL
+            )));
+        }
+        envelope.op.validate()?;
+        envelope.op.normalize();
+        Ok(envelope.op)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use kaspa_addresses::{Prefix, Version};
+
+    fn address() -> String {
+        Address::new(Prefix::Mainnet, Version::PubKey, &[1; 32]).to_string()
+    }
+
+    fn amount(value: &str, dec: u8) -> Result<BigInt> {
+        parse_human_amount(value, dec, "amt")
+    }
+
+    #[test]
+    fn parses_human_amounts() {
+        assert_eq!(amount("12.5", 8).unwrap(), BigInt::from(1_250_000_000u64));
+        assert_eq!(amount(" 7 ", 0).unwrap(), BigInt::from(7));
+        assert_eq!(amount(".5", 1).unwrap(), BigInt::from(5));
+        assert_eq!(amount("5.", 2).unwrap(), BigInt::from(500));
+        assert_eq!(amount("0.00000001", 8).unwrap(), BigInt::from(1));
+        assert_eq!(
+            amount("18446744073709551615", 0).unwrap(),
+            BigInt::from(u64::MAX)
+        );
+
+        for (value, dec) in [
+            ("", 8),
+            (".", 8),
+            ("0", 8),
+            ("0.0", 8),
+            ("-1", 8),
+            ("1.2.3", 8),
+            ("1e3", 8),
+            ("0.1", 0),
+            ("1.123456789", 8),
+            ("18446744073709551616", 0),
+            ("184467440737.09551616", 8),
+        ] {
+            assert!(amount(value, dec).is_err(), "{value} with {dec} decimals");
+        }
+    }
+
+    #[test]
+    fn formats_amounts() {
+        assert_eq!(format_amount(&BigInt::from(0), 8), "0");
+        assert_eq!(format_amount(&BigInt::from(1), 8), "0.00000001");
+        assert_eq!(format_amount(&BigInt::from(1_250_000_000u64), 8), "12.5");
+        assert_eq!(format_amount(&BigInt::from(100_000_000u64), 8), "1");
+        assert_eq!(format_amount(&BigInt::from(-150), 2), "-1.5");
+        assert_eq!(format_amount(&BigInt::from(42), 0), "42");
+        let max = BigInt::from(u64::MAX);
+        assert_eq!(amount(&format_amount(&max, 18), 18).unwrap(), max);
+    }
+
+    #[test]
+    fn checks_amount_bounds() {
+        assert!(check_amount(&BigInt::from(0), "amt").is_err());
+        assert!(check_amount(&BigInt::from(-1), "amt").is_err());
+        assert!(check_amount(&BigInt::from(1), "amt").is_ok());
+        assert!(check_amount(&BigInt::from(MAX_AMOUNT), "amt").is_ok());
+        assert!(check_amount(&(BigInt::from(MAX_AMOUNT) + 1), "amt").is_err());
+        assert!(parse_amount("01", "max").is_ok());
+        assert!(parse_amount("+1", "max").is_err());
+    }
+
+    #[test]
+    fn serializes_ops_in_canonical_field_order() {
+        let to = address();
+        let cases = [
+            (
+                Krc20Op::Deploy(Deploy::new("TEST", "21000000", "1000", "8", None, None).unwrap()),
+                r#"{"p":"krc-20","op":"deploy","tick":"test","max":"21000000","lim":"1000","dec":"8"}"#.to_string(),
+            ),
+            (
+                Krc20Op::Deploy(
+                    Deploy::new_issue("test", "1000", "2", Some("10"), Some(&to)).unwrap(),
+                ),
+                format!(
+                    r#"{{"p":"krc-20","op":"deploy","mod":"issue","name":"test","max":"1000","dec":"2","pre":"10","to":"{}"}}"#,
+                    to
+                ),
+            ),
+            (
+                Krc20Op::Mint(Mint::new("test", None).unwrap()),
+                r#"{"p":"krc-20","op":"mint","tick":"test"}"#.to_string(),
+            ),
+            (
+                Krc20Op::Transfer(Transfer::new("test", "1.5", 8, &to).unwrap()),
+                format!(
+                    r#"{{"p":"krc-20","op":"transfer","tick":"test","amt":"150000000","to":"{}"}}"#,
+                    to
+                ),
+            ),
+            (
+                Krc20Op::List(List::new("test", "2", 0).unwrap()),
+                r#"{"p":"krc-20","op":"list","tick":"test","amt":"2"}"#.to_string(),
+            ),
+        ];
+        for (op, json) in cases {
+            assert_eq!(op.to_json().unwrap(), json);
+            assert_eq!(Krc20Op::from_json(&json).unwrap(), op);
+        }
+    }
+
+    #[test]
+    fn rejects_foreign_or_invalid_op_json() {
+        let mint = r#"{"p":"KRC-20","op":"mint","tick":"TEST"}"#;
+        assert_eq!(
+            Krc20Op::from_json(mint).unwrap(),
+            Krc20Op::Mint(Mint {
+                tick: "test".to_string(),
+                to: None,
+            })
+        );
+        let transfer = format!(
+            r#"{{"p":"krc-20","op":"transfer","tick":"Test","amt":"1","to":"{}"}}"#,
+            address()
+        );
+        assert_eq!(Krc20Op::from_json(&transfer).unwrap().token(), Some("test"));
+        assert!(Krc20Op::from_json(r#"{"p":"brc-20","op":"mint","tick":"test"}"#).is_err());
+        assert!(Krc20Op::from_json(r#"{"p":"krc-20","op":"mint","tick":"te"}"#).is_err());
+        assert!(Krc20Op::from_json(r#"{"p":"krc-20","op":"burn","tick":"test"}"#).is_err());
+        assert!(Krc20Op::from_json(
+            r#"{"p":"krc-20","op":"transfer","tick":"test","amt":"0","to":"x"}"#
+        )
+        .is_err());
+    }
+}
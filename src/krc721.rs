@@ -0,0 +1,480 @@
+use kaspa_wrpc_client::{error::Error, result::Result};
+use serde::{Deserialize, Serialize};
+
+use crate::krc20::{as_string, option_as_string, optional, validate_address};
+
+use std::fs;
+
+pub const PROTOCOL: &str = "krc-721";
+/// Marker pushed at the start of a KRC-721 envelope, in place of `kasplex`.
+pub const ENVELOPE_MARKER: &[u8] = b"kspr";
+/// Largest collection supply accepted by the protocol.
+pub const MAX_SUPPLY: u64 = 1_000_000;
+
+/// Checks that a collection ticker is 1-10 ASCII letters or digits and returns
+/// it uppercased.
+pub fn validate_collection(tick: &str) -> Result<String> {
+    let tick = tick.trim();
+    if !(1..=10).contains(&tick.len()) || !tick.chars().all(|c| c.is_ascii_alphanumeric()) {
+        return Err(Error::Custom(format!(
+            "Invalid collection ticker '{}': must be 1 to 10 ASCII letters or digits",
+            tick
+        )));
+    }
+    Ok(tick.to_uppercase())
+}
+
+/// Parses a positive integer no larger than `max`.
+fn parse_count(value: &str, max: u64, name: &str) -> Result<u64> {
+    let count = value.trim().parse::<u64>().map_err(|_| {
+        Error::Custom(format!(
+            "Invalid {} '{}': must be a positive integer",
+            name,
+            value.trim()
+        ))
+    })?;
+    check_count(count, max, name)?;
+    Ok(count)
+}
+
+/// Checks that `count` is between 1 and `max`.
+fn check_count(count: u64, max: u64, name: &str) -> Result<()> {
+    if count == 0 || count > max {
+        return Err(Error::Custom(format!(
+            "Invalid {}: must be between 1 and {}",
+            name, max
+        )));
+    }
+    Ok(())
+}
+
+/// Collection metadata inscribed in a deploy that has no `buri`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Metadata {
+    pub name: String,
+    pub description: String,
+    pub image: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub attributes: Vec<serde_json::Value>,
+}
+
+impl Metadata {
+    /// Reads and validates collection metadata from a JSON file.
+    pub fn read(path: &str) -> Result<Metadata> {
+        let contents = fs::read_to_string(path)
+            .map_err(|e| Error::Custom(format!("Failed to read {}: {}", path, e)))?;
+        let metadata: Metadata = serde_json::from_str(&contents)
+            .map_err(|e| Error::Custom(format!("Invalid metadata in {}: {}", path, e)))?;
+        metadata.validate()?;
+        Ok(metadata)
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        for (value, name) in [
+            (&self.name, "name"),
+            (&self.description, "description"),
+            (&self.image, "image"),
+        ] {
+            if value.trim().is_empty() {
+                return Err(Error::Custom(format!("Metadata is missing {}", name)));
+            }
+        }
+        if self
+            .attributes
+            .iter()
+            .any(|attribute| !attribute.is_object())
+        {
+            return Err(Error::Custom(
+                "Metadata attributes must be JSON objects".to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Deploy {
+    pub tick: String,
+    #[serde(with = "as_string")]
+    pub max: u64,
+    /// Base URI of the token metadata, e.g. an IPFS folder.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub buri: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<Metadata>,
+    /// Royalty in sompi paid to `royalty_to` by every mint.
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "option_as_string"
+    )]
+    pub royalty_fee: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub royalty_to: Option<String>,
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "option_as_string"
+    )]
+    pub premint: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub to: Option<String>,
+}
+
+impl Deploy {
+    /// Builds a validated deploy. `metadata` is either a `buri` or the path to
+    /// a `.json` file holding the collection metadata. `royalty_fee` is in
+    /// sompi.
+    pub fn new(
+        tick: &str,
+        max: &str,
+        metadata: &str,
+        royalty_fee: Option<u64>,
+        royalty_to: Option<&str>,
+        premint: Option<&str>,
+        to: Option<&str>,
+    ) -> Result<Deploy> {
+        let metadata = metadata.trim();
+        let (buri, metadata) = if metadata.ends_with(".json") {
+            (None, Some(Metadata::read(metadata)?))
+        } else {
+            (optional(Some(metadata)).map(str::to_string), None)
+        };
+        let deploy = Deploy {
+            tick: validate_collection(tick)?,
+            max: parse_count(max, MAX_SUPPLY, "max")?,
+            buri,
+            metadata,
+            royalty_fee: royalty_fee.filter(|fee| *fee > 0),
+            royalty_to: optional(royalty_to)
+                .map(|to| validate_address(to, "royaltyTo"))
+                .transpose()?,
+            premint: optional(premint)
+                .map(|premint| parse_count(premint, MAX_SUPPLY, "premint"))
+                .transpose()?,
+            to: optional(to)
+                .map(|to| validate_address(to, "to"))
+                .transpose()?,
+        };
+        deploy.validate()?;
+        Ok(deploy)
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        validate_collection(&self.tick)?;
+        check_count(self.max, MAX_SUPPLY, "max")?;
+        match (&self.buri, &self.metadata) {
+            (Some(buri), None) if !buri.trim().is_empty() => {}
+            (None, Some(metadata)) => metadata.validate()?,
+            _ => {
+                return Err(Error::Custom(
+                    "Deploy must set exactly one of buri or metadata".to_string(),
+                ))
+            }
+        }
+        match (self.royalty_fee, &self.royalty_to) {
+            (Some(fee), Some(to)) if fee > 0 => {
+                validate_address(to, "royaltyTo")?;
+            }
+            (None, None) => {}
+            _ => {
+                return Err(Error::Custom(
+                    "Deploy must set both royaltyFee and royaltyTo, or neither".to_string(),
+                ))
+            }
+        }
+        if let Some(premint) = self.premint {
+            if premint > self.max {
+                return Err(Error::Custom(
+                    "Invalid premint: must not exceed max".to_string(),
+                ));
+            }
+        }
+        if let Some(to) = &self.to {
+            validate_address(to, "to")?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Mint {
+    pub tick: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub to: Option<String>,
+}
+
+impl Mint {
+    pub fn new(tick: &str, to: Option<&str>) -> Result<Mint> {
+        Ok(Mint {
+            tick: validate_collection(tick)?,
+            to: optional(to)
+                .map(|to| validate_address(to, "to"))
+                .transpose()?,
+        })
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        validate_collection(&self.tick)?;
+        if let Some(to) = &self.to {
+            validate_address(to, "to")?;
+        }
+        Ok(())
+    }
+}
+
+/// Transfer of a single token of a collection.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Transfer {
+    pub tick: String,
+    #[serde(with = "as_string")]
+    pub token_id: u64,
+    pub to: String,
+}
+
+impl Transfer {
+    pub fn new(tick: &str, token_id: &str, to: &str) -> Result<Transfer> {
+        Ok(Transfer {
+            tick: validate_collection(tick)?,
+            token_id: parse_count(token_id, MAX_SUPPLY, "tokenId")?,
+            to: validate_address(to, "to")?,
+        })
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        validate_collection(&self.tick)?;
+        check_count(self.token_id, MAX_SUPPLY, "tokenId")?;
+        validate_address(&self.to, "to")?;
+        Ok(())
+    }
+}
+
+/// A KRC-721 operation as inscribed in a reveal transaction.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub enum Krc721Op {
+    Deploy(Deploy),
+    Mint(Mint),
+    Transfer(Transfer),
+}
+
+/// The `p` field followed by the flattened op.
+#[derive(Serialize, Deserialize)]
+struct Envelope {
+    p: String,
+    #[serde(flatten)]
+    op: Krc721Op,
+}
+
+impl Krc721Op {
+    pub fn validate(&self) -> Result<()> {
+        match self {
+            Krc721Op::Deploy(op) => op.validate(),
+            Krc721Op::Mint(op) => op.validate(),
+            Krc721Op::Transfer(op) => op.validate(),
+        }
+    }
+
+    /// Returns the lowercase `op` name.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Krc721Op::Deploy(_) => "deploy",
+            Krc721Op::Mint(_) => "mint",
+            Krc721Op::Transfer(_) => "transfer",
+        }
+    }
+
+    /// Returns the collection ticker.
+    pub fn collection(&self) -> &str {
+        match self {
+            Krc721Op::Deploy(op) => &op.tick,
+            Krc721Op::Mint(op) => &op.tick,
+            Krc721Op::Transfer(op) => &op.tick,
+        }
+    }
+
+    /// Returns the canonical inscription JSON for this op.
+    pub fn to_json(&self) -> Result<String> {
+        let envelope = Envelope {
+            p: PROTOCOL.to_string(),
+            op: self.clone(),
+        };
+        serde_json::to_string(&envelope)
+            .map_err(|e| Error::Custom(format!("Failed to serialize KRC-721 op: {}", e)))
+    }
+
+    /// Parses and validates op JSON taken from an inscription.
+    pub fn from_json(json: &str) -> Result<Krc721Op> {
+        let envelope: Envelope = serde_json::from_str(json)
+            .map_err(|e| Error::Custom(format!("Failed to parse KRC-721 op: {}", e)))?;
+        if !envelope.p.eq_ignore_ascii_case(PROTOCOL) {
+            return Err(Error::Custom(format!(
+                "Unexpected protocol '{}', expected '{}'",
+                envelope.p, PROTOCOL
+            )));
+        }
+        envelope.op.validate()?;
+        Ok(envelope.op)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use kaspa_addresses::{Address, Prefix, Version};
+
+    fn address() -> String {
+        Address::new(Prefix::Mainnet, Version::PubKey, &[1; 32]).to_string()
+    }
+
+    fn deploy() -> Deploy {
+        Deploy {
+            tick: "CATS".to_string(),
+            max: 1_000,
+            buri: Some("ipfs://cats".to_string()),
+            metadata: None,
+            royalty_fee: None,
+            royalty_to: None,
+            premint: None,
+            to: None,
+        }
+    }
+
+    fn metadata() -> Metadata {
+        Metadata {
+            name: "Cats".to_string(),
+            description: "Some cats".to_string(),
+            image: "ipfs://cats/cover.png".to_string(),
+            attributes: vec![],
+        }
+    }
+
+    fn error(deploy: Deploy) -> String {
+        deploy.validate().unwrap_err().to_string()
+    }
+
+    #[test]
+    fn requires_exactly_one_of_buri_or_metadata() {
+        assert!(deploy().validate().is_ok());
+        let with_metadata = Deploy {
+            buri: None,
+            metadata: Some(metadata()),
+            ..deploy()
+        };
+        assert!(with_metadata.validate().is_ok());
+
+        for (buri, metadata) in [
+            (None, None),
+            (Some(" ".to_string()), None),
+            (Some("ipfs://cats".to_string()), Some(metadata())),
+        ] {
+            let error = error(Deploy {
+                buri,
+                metadata,
+                ..deploy()
+            });
+            assert!(error.contains("exactly one of buri or metadata"), "{error}");
+        }
+
+        let untitled = Metadata {
+            name: String::new(),
+            ..metadata()
+        };
+        let error = error(Deploy {
+            buri: None,
+            metadata: Some(untitled),
+            ..deploy()
+        });
+        assert!(error.contains("missing name"), "{error}");
+    }
+
+    #[test]
+    fn pairs_royalty_fee_and_recipient() {
+        let royalty = Deploy {
+            royalty_fee: Some(100_000_000),
+            royalty_to: Some(address()),
+            ..deploy()
+        };
+        assert!(royalty.validate().is_ok());
+
+        for (royalty_fee, royalty_to) in [
+            (Some(100_000_000), None),
+            (None, Some(address())),
+            (Some(0), Some(address())),
+        ] {
+            let error = error(Deploy {
+                royalty_fee,
+                royalty_to,
+                ..deploy()
+            });
+            assert!(error.contains("royaltyFee and royaltyTo"), "{error}");
+        }
+        assert!(Deploy {
+            royalty_fee: Some(1),
+            royalty_to: Some("kaspa:invalid".to_string()),
+            ..deploy()
+        }
+        .validate()
+        .is_err());
+    }
+
+    #[test]
+    fn bounds_max_and_premint() {
+        for max in [0, MAX_SUPPLY + 1] {
+            let error = error(Deploy { max, ..deploy() });
+            assert!(error.contains("Invalid max"), "{error}");
+        }
+        assert!(Deploy {
+            max: MAX_SUPPLY,
+            ..deploy()
+        }
+        .validate()
+        .is_ok());
+
+        assert!(Deploy {
+            premint: Some(1_000),
+            ..deploy()
+        }
+        .validate()
+        .is_ok());
+        let error = error(Deploy {
+            premint: Some(1_001),
+            ..deploy()
+        });
+        assert!(error.contains("premint"), "{error}");
+        assert!(Deploy::new("cats", "1000", "ipfs://cats", None, None, Some("0"), None).is_err());
+    }
+
+    #[test]
+    fn round_trips_deploy_json() {
+        let deploy = Deploy::new(
+            "cats",
+            "1000",
+            "ipfs://cats",
+            Some(100_000_000),
+            Some(&address()),
+            Some("10"),
+            None,
+        )
+        .unwrap();
+        assert_eq!(deploy.tick, "CATS");
+        let op = Krc721Op::Deploy(deploy);
+        let json = op.to_json().unwrap();
+        assert_eq!(
+            json,
+            format!(
+                r#"{{"p":"krc-721","op":"deploy","tick":"CATS","max":"1000","buri":"ipfs://cats","royaltyFee":"100000000","royaltyTo":"{}","premint":"10"}}"#,
+                address()
+            )
+        );
+        assert_eq!(Krc721Op::from_json(&json).unwrap(), op);
+
+        let krc20 = json.replace("krc-721", "krc-20");
+        let error = Krc721Op::from_json(&krc20).unwrap_err().to_string();
+        assert!(error.contains("Unexpected protocol"), "{error}");
+        let invalid = json.replace(r#""max":"1000""#, r#""max":"0""#);
+        assert!(Krc721Op::from_json(&invalid).is_err());
+    }
+}
@@ -0,0 +1,1429 @@
+use kaspa_addresses::Prefix;
+use kaspa_consensus_core::tx::{Transaction, TransactionId};
+use kaspa_notify::listener::ListenerId;
+use kaspa_notify::scope::{Scope, VirtualChainChangedScope};
+use kaspa_rpc_core::{api::rpc::RpcApi, Notification, RpcAcceptedTransactionIds, RpcHash};
+use kaspa_txscript::extract_script_pub_key_address;
+use kaspa_wrpc_client::{error::Error, prelude::NetworkType, result::Result, KaspaRpcClient};
+use num_bigint::BigInt;
+use serde::{Deserialize, Serialize};
+
+use crate::decoder::decode_inscription;
+use crate::fees::FeeSchedule;
+use crate::krc20::{as_string, BlacklistMode, DeployMode, Krc20Op};
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
+use std::fs::{self, File, OpenOptions};
+use std::io::prelude::*;
+use std::path::{Path, PathBuf};
+
+/// Default location of the local index store.
+pub const INDEX_FILE: &str = "krc20-index.json";
+
+/// Number of chain blocks kept with undo data. Reorgs deeper than this
+/// cannot be rolled back.
+const MAX_UNDO_BLOCKS: usize = 10_000;
+
+/// Chain blocks applied between two writes of the index store. Ops are
+/// appended to the ops log as they come; the store only needs rewriting often
+/// enough to keep catching up after a restart short.
+const SNAPSHOT_BLOCKS: usize = 1_000;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TokenState {
+    /// Ticker, or the contract address of an issue-mode token.
+    pub tick: String,
+    /// `issue` for issue-mode tokens, empty for mint-mode tokens.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub mode: String,
+    /// Issue-mode token name.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub name: String,
+    #[serde(with = "as_string")]
+    pub max: BigInt,
+    #[serde(with = "as_string")]
+    pub lim: BigInt,
+    pub dec: u8,
+    #[serde(with = "as_string")]
+    pub pre: BigInt,
+    #[serde(with = "as_string")]
+    pub minted: BigInt,
+    pub deployer: String,
+    /// Deploy reveal transaction id.
+    pub hash_rev: String,
+    /// `deployed` or `finished`.
+    pub state: String,
+    /// Current owner of an issue-mode token.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub owner: String,
+    /// Amount burned by the owner of an issue-mode token.
+    #[serde(default, with = "as_string")]
+    pub burned: BigInt,
+    /// Addresses the owner of an issue-mode token barred from transferring.
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub blacklist: BTreeSet<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Holding {
+    #[serde(with = "as_string")]
+    pub balance: BigInt,
+    /// Amount locked in open listings.
+    #[serde(with = "as_string")]
+    pub locked: BigInt,
+}
+
+impl Holding {
+    fn is_empty(&self) -> bool {
+        self.balance == BigInt::from(0) && self.locked == BigInt::from(0)
+    }
+}
+
+/// An open listing, keyed by the outpoint of the UTXO holding it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ListingState {
+    pub tick: String,
+    #[serde(with = "as_string")]
+    pub amt: BigInt,
+    pub owner: String,
+}
+
+/// Outcome of an indexed op, similar to the indexer's op info.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OpRecord {
+    /// Reveal transaction id.
+    pub hash: String,
+    pub op: String,
+    pub tick: String,
+    pub from: String,
+    pub to: String,
+    #[serde(with = "as_string")]
+    pub amt: BigInt,
+    /// Chain block that accepted the reveal.
+    pub block: String,
+    pub accepted: bool,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub error: String,
+}
+
+/// The value a key had before a chain block changed it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+enum Undo {
+    Token {
+        tick: String,
+        previous: Option<Box<TokenState>>,
+    },
+    Holding {
+        address: String,
+        tick: String,
+        previous: Option<Holding>,
+    },
+    Listing {
+        outpoint: String,
+        previous: Option<ListingState>,
+    },
+    Output {
+        outpoint: String,
+        previous: Option<u64>,
+    },
+    Unfollowed {
+        tick: String,
+        previous: Option<String>,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct ChainBlock {
+    hash: String,
+    /// Length of the ops log before the block's ops.
+    #[serde(default)]
+    ops_offset: u64,
+    undo: Vec<Undo>,
+}
+
+/// KRC-20 state built from the reveals accepted by the virtual chain.
+///
+/// The store holds the state and the undo data of the last `MAX_UNDO_BLOCKS`
+/// chain blocks. Op records go to a JSON lines log next to it instead, so
+/// they are never held in memory or rewritten.
+///
+/// The index starts empty at its start block: tokens deployed before it are
+/// not tracked, and ops on them are rejected as "tick not found".
+///
+/// Reveals must pay the protocol fee of the `FeeSchedule`, the difference
+/// between their inputs and outputs. Blocks do not carry the values of spent
+/// outputs, so the index keeps the values of the unspent outputs it has seen.
+/// A reveal whose known inputs fall short of the fee but that also spends
+/// outputs created before the index started cannot be checked, and its token
+/// is no longer followed: its holders are not reported.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct LocalIndex {
+    #[serde(skip)]
+    path: PathBuf,
+    pub tokens: BTreeMap<String, TokenState>,
+    /// Holdings by address, then by ticker.
+    pub holdings: BTreeMap<String, BTreeMap<String, Holding>>,
+    pub listings: BTreeMap<String, ListingState>,
+    /// Values of the unspent outputs, keyed by outpoint.
+    #[serde(default)]
+    outputs: BTreeMap<String, u64>,
+    /// Tokens whose state is unknown, with the reason.
+    #[serde(default)]
+    pub unfollowed: BTreeMap<String, String>,
+    /// Length of the ops log, including ops not written yet.
+    #[serde(default)]
+    ops_len: u64,
+    /// Op records not appended to the ops log yet.
+    #[serde(skip)]
+    pending_ops: String,
+    /// Chain blocks applied since the store was last written.
+    #[serde(skip)]
+    unsaved_blocks: usize,
+    /// Applied chain blocks, oldest first, with what they changed.
+    chain: VecDeque<ChainBlock>,
+}
+
+impl LocalIndex {
+    /// Loads the index stored at `path`, or starts an empty one. Ops logged
+    /// after the store was last written are dropped, since their blocks will
+    /// be applied again.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<LocalIndex> {
+        let path = path.as_ref().to_path_buf();
+        let mut index: LocalIndex = match fs::read_to_string(&path) {
+            Ok(contents) => serde_json::from_str(&contents)
+                .map_err(|e| Error::Custom(format!("Failed to parse local index: {}", e)))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => LocalIndex::default(),
+            Err(e) => return Err(Error::Custom(format!("Failed to read local index: {}", e))),
+        };
+        index.path = path;
+        let logged = match fs::metadata(index.ops_path()) {
+            Ok(metadata) => metadata.len(),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => 0,
+            Err(e) => return Err(Error::Custom(format!("Failed to read ops log: {}", e))),
+        };
+        if logged < index.ops_len {
+            return Err(Error::Custom(format!(
+                "Ops log {} is shorter than the local index expects",
+                index.ops_path().display()
+            )));
+        }
+        if logged > index.ops_len {
+            OpenOptions::new()
+                .write(true)
+                .open(index.ops_path())
+                .and_then(|file| file.set_len(index.ops_len))
+                .map_err(|e| Error::Custom(format!("Failed to truncate ops log: {}", e)))?;
+        }
+        Ok(index)
+    }
+
+    /// Returns the path of the ops log, next to the store.
+    pub fn ops_path(&self) -> PathBuf {
+        self.path.with_extension("ops.jsonl")
+    }
+
+    /// Returns every op record, oldest first.
+    pub fn ops(&self) -> Result<Vec<OpRecord>> {
+        let logged = match fs::read_to_string(self.ops_path()) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(Error::Custom(format!("Failed to read ops log: {}", e))),
+        };
+        logged
+            .lines()
+            .chain(self.pending_ops.lines())
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| {
+                serde_json::from_str(line)
+                    .map_err(|e| Error::Custom(format!("Failed to parse op record: {}", e)))
+            })
+            .collect()
+    }
+
+    fn push_op(&mut self, record: &OpRecord) {
+        // Serializing a record of strings and numbers cannot fail
+        let line = serde_json::to_string(record).unwrap_or_default();
+        self.pending_ops.push_str(&line);
+        self.pending_ops.push('\n');
+        self.ops_len += line.len() as u64 + 1;
+    }
+
+    /// Appends the pending op records to the ops log.
+    pub fn flush_ops(&mut self) -> Result<()> {
+        if self.pending_ops.is_empty() {
+            return Ok(());
+        }
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.ops_path())
+            .map_err(|e| Error::Custom(format!("Failed to open ops log: {}", e)))?;
+        file.write_all(self.pending_ops.as_bytes())
+            .map_err(|e| Error::Custom(format!("Failed to write ops log: {}", e)))?;
+        self.pending_ops.clear();
+        Ok(())
+    }
+
+    /// Drops the op records past `len` bytes of the ops log.
+    fn truncate_ops(&mut self, len: u64) -> Result<()> {
+        let logged = self.ops_len - self.pending_ops.len() as u64;
+        if len >= logged {
+            self.pending_ops.truncate((len - logged) as usize);
+        } else {
+            self.pending_ops.clear();
+            OpenOptions::new()
+                .write(true)
+                .open(self.ops_path())
+                .and_then(|file| file.set_len(len))
+                .map_err(|e| Error::Custom(format!("Failed to truncate ops log: {}", e)))?;
+        }
+        self.ops_len = len;
+        Ok(())
+    }
+
+    /// Returns true once enough chain blocks were applied since the store was
+    /// last written.
+    pub fn snapshot_due(&self) -> bool {
+        self.unsaved_blocks >= SNAPSHOT_BLOCKS
+    }
+
+    /// Flushes the ops log and writes the index to its store, replacing the
+    /// previous one atomically.
+    pub fn save(&mut self) -> Result<()> {
+        self.flush_ops()?;
+        if let Ok(file) = File::open(self.ops_path()) {
+            file.sync_all()
+                .map_err(|e| Error::Custom(format!("Failed to write ops log: {}", e)))?;
+        }
+        let contents = serde_json::to_string(self)
+            .map_err(|e| Error::Custom(format!("Failed to serialize local index: {}", e)))?;
+        let tmp_path = self.path.with_extension("json.tmp");
+        let mut file = File::create(&tmp_path)
+            .map_err(|e| Error::Custom(format!("Failed to create local index: {}", e)))?;
+        file.write_all(contents.as_bytes())
+            .and_then(|_| file.sync_all())
+            .map_err(|e| Error::Custom(format!("Failed to write local index: {}", e)))?;
+        fs::rename(&tmp_path, &self.path)
+            .map_err(|e| Error::Custom(format!("Failed to replace local index: {}", e)))?;
+        self.unsaved_blocks = 0;
+        Ok(())
+    }
+
+    /// Returns the last applied chain block.
+    pub fn sink(&self) -> Option<&str> {
+        self.chain.back().map(|block| block.hash.as_str())
+    }
+
+    pub fn holding(&self, address: &str, tick: &str) -> Holding {
+        self.holdings
+            .get(address)
+            .and_then(|ticks| ticks.get(tick))
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Returns every holder of `tick` with their holding. Fails for tokens
+    /// the index could not follow.
+    pub fn holders(&self, tick: &str) -> Result<Vec<(String, Holding)>> {
+        if let Some(reason) = self.unfollowed.get(tick) {
+            return Err(Error::Custom(format!(
+                "Local index cannot report holders of {}: {}",
+                tick, reason
+            )));
+        }
+        Ok(self
+            .holdings
+            .iter()
+            .filter_map(|(address, ticks)| {
+                ticks
+                    .get(tick)
+                    .map(|holding| (address.clone(), holding.clone()))
+            })
+            .collect())
+    }
+
+    fn put_token(&mut self, token: TokenState, undo: &mut Vec<Undo>) {
+        let tick = token.tick.clone();
+        let previous = self.tokens.insert(tick.clone(), token);
+        undo.push(Undo::Token {
+            tick,
+            previous: previous.map(Box::new),
+        });
+    }
+
+    fn put_holding(&mut self, address: &str, tick: &str, holding: Holding, undo: &mut Vec<Undo>) {
+        let previous = self.restore_holding(address, tick, Some(holding));
+        undo.push(Undo::Holding {
+            address: address.to_string(),
+            tick: tick.to_string(),
+            previous,
+        });
+    }
+
+    /// Sets or removes a holding without recording undo data and returns the
+    /// previous one.
+    fn restore_holding(
+        &mut self,
+        address: &str,
+        tick: &str,
+        holding: Option<Holding>,
+    ) -> Option<Holding> {
+        let ticks = self.holdings.entry(address.to_string()).or_default();
+        let previous = match holding.filter(|holding| !holding.is_empty()) {
+            Some(holding) => ticks.insert(tick.to_string(), holding),
+            None => ticks.remove(tick),
+        };
+        if ticks.is_empty() {
+            self.holdings.remove(address);
+        }
+        previous
+    }
+
+    fn put_listing(&mut self, outpoint: &str, listing: Option<ListingState>, undo: &mut Vec<Undo>) {
+        let previous = match listing {
+            Some(listing) => self.listings.insert(outpoint.to_string(), listing),
+            None => self.listings.remove(outpoint),
+        };
+        undo.push(Undo::Listing {
+            outpoint: outpoint.to_string(),
+            previous,
+        });
+    }
+
+    fn put_output(&mut self, outpoint: String, value: Option<u64>, undo: &mut Vec<Undo>) {
+        let previous = match value {
+            Some(value) => self.outputs.insert(outpoint.clone(), value),
+            None => self.outputs.remove(&outpoint),
+        };
+        undo.push(Undo::Output { outpoint, previous });
+    }
+
+    fn unfollow(&mut self, tick: &str, reason: String, undo: &mut Vec<Undo>) {
+        let previous = self.unfollowed.insert(tick.to_string(), reason);
+        undo.push(Undo::Unfollowed {
+            tick: tick.to_string(),
+            previous,
+        });
+    }
+
+    /// Spends the outputs `tx` consumes and records the ones it creates.
+    /// Returns what `tx` pays on top of its outputs from the inputs of known
+    /// value, and whether every input value was known.
+    fn track_outputs(&mut self, tx: &Transaction, undo: &mut Vec<Undo>) -> (u64, bool) {
+        let mut total = 0u64;
+        let mut known = true;
+        for input in &tx.inputs {
+            let outpoint = input.previous_outpoint;
+            let key = format!("{}:{}", outpoint.transaction_id, outpoint.index);
+            match self.outputs.get(&key).copied() {
+                Some(value) => {
+                    total += value;
+                    self.put_output(key, None, undo);
+                }
+                None => known = false,
+            }
+        }
+        for (index, output) in tx.outputs.iter().enumerate() {
+            self.put_output(format!("{}:{}", tx.id(), index), Some(output.value), undo);
+        }
+        let outputs: u64 = tx.outputs.iter().map(|output| output.value).sum();
+        (total.saturating_sub(outputs), known)
+    }
+
+    /// Returns the token at contract address `ca` if it is an issue-mode
+    /// token owned by `signer`.
+    fn owned_token(&self, ca: &str, signer: &str) -> std::result::Result<TokenState, String> {
+        let token = self
+            .tokens
+            .get(ca)
+            .cloned()
+            .ok_or_else(|| "tick not found".to_string())?;
+        if token.mode != "issue" {
+            return Err("mode invalid".to_string());
+        }
+        if token.owner != signer {
+            return Err("no ownership".to_string());
+        }
+        Ok(token)
+    }
+
+    fn credit(&mut self, address: &str, tick: &str, amt: &BigInt, undo: &mut Vec<Undo>) {
+        let mut holding = self.holding(address, tick);
+        holding.balance += amt;
+        self.put_holding(address, tick, holding, undo);
+    }
+
+    fn debit(
+        &mut self,
+        address: &str,
+        tick: &str,
+        amt: &BigInt,
+        undo: &mut Vec<Undo>,
+    ) -> std::result::Result<(), String> {
+        let mut holding = self.holding(address, tick);
+        if holding.balance < *amt {
+            return Err("balance insufficient".to_string());
+        }
+        holding.balance -= amt;
+        self.put_holding(address, tick, holding, undo);
+        Ok(())
+    }
+
+    /// Applies `op` revealed by `signer` in `tx` and returns the sender,
+    /// recipient and amount, or the reason the op is rejected.
+    fn apply_op(
+        &mut self,
+        op: &Krc20Op,
+        tx: &Transaction,
+        input_index: usize,
+        signer: &str,
+        prefix: Prefix,
+        undo: &mut Vec<Undo>,
+    ) -> std::result::Result<(String, String, BigInt), String> {
+        match op {
+            Krc20Op::Deploy(deploy) => {
+                // Issue-mode tokens are keyed by their contract address, the
+                // deploy reveal id
+                let (tick, mode, owner) = match deploy.mode {
+                    None => (
+                        deploy.tick.clone().unwrap_or_default(),
+                        String::new(),
+                        String::new(),
+                    ),
+                    Some(DeployMode::Issue) => (
+                        tx.id().to_string(),
+                        "issue".to_string(),
+                        deploy.to.clone().unwrap_or_else(|| signer.to_string()),
+                    ),
+                };
+                if self.tokens.contains_key(&tick) {
+                    return Err("tick existed".to_string());
+                }
+                let pre = deploy.pre.clone().unwrap_or_default();
+                let to = deploy.to.clone().unwrap_or_else(|| signer.to_string());
+                let state = if mode.is_empty() && pre >= deploy.max {
+                    "finished"
+                } else {
+                    "deployed"
+                };
+                let token = TokenState {
+                    tick: tick.clone(),
+                    mode,
+                    name: deploy.name.clone().unwrap_or_default(),
+                    max: deploy.max.clone(),
+                    lim: deploy.lim.clone().unwrap_or_default(),
+                    dec: deploy.dec,
+                    pre: pre.clone(),
+                    minted: pre.clone(),
+                    deployer: signer.to_string(),
+                    hash_rev: tx.id().to_string(),
+                    state: state.to_string(),
+                    owner,
+                    burned: BigInt::from(0),
+                    blacklist: BTreeSet::new(),
+                };
+                self.put_token(token, undo);
+                if pre > BigInt::from(0) {
+                    self.credit(&to, &tick, &pre, undo);
+                }
+                Ok((signer.to_string(), to, pre))
+            }
+            Krc20Op::Mint(mint) => {
+                let mut token = self
+                    .tokens
+                    .get(&mint.tick)
+                    .cloned()
+                    .ok_or_else(|| "tick not found".to_string())?;
+                if token.minted >= token.max {
+                    return Err("mint finished".to_string());
+                }
+                let amt = (&token.max - &token.minted).min(token.lim.clone());
+                token.minted += &amt;
+                if token.minted >= token.max {
+                    token.state = "finished".to_string();
+                }
+                let to = mint.to.clone().unwrap_or_else(|| signer.to_string());
+                self.put_token(token, undo);
+                self.credit(&to, &mint.tick, &amt, undo);
+                Ok((signer.to_string(), to, amt))
+            }
+            Krc20Op::Transfer(transfer) => {
+                let tick = transfer
+                    .tick
+                    .as_deref()
+                    .or(transfer.ca.as_deref())
+                    .unwrap_or_default();
+                let token = self
+                    .tokens
+                    .get(tick)
+                    .ok_or_else(|| "tick not found".to_string())?;
+                if token.blacklist.contains(signer) {
+                    return Err("blacklisted".to_string());
+                }
+                self.debit(signer, tick, &transfer.amt, undo)?;
+                self.credit(&transfer.to, tick, &transfer.amt, undo);
+                Ok((
+                    signer.to_string(),
+                    transfer.to.clone(),
+                    transfer.amt.clone(),
+                ))
+            }
+            Krc20Op::List(list) => {
+                if !self.tokens.contains_key(&list.tick) {
+                    return Err("tick not found".to_string());
+                }
+                let mut holding = self.holding(signer, &list.tick);
+                if holding.balance < list.amt {
+                    return Err("balance insufficient".to_string());
+                }
+                holding.balance -= &list.amt;
+                holding.locked += &list.amt;
+                self.put_holding(signer, &list.tick, holding, undo);
+                let listing = ListingState {
+                    tick: list.tick.clone(),
+                    amt: list.amt.clone(),
+                    owner: signer.to_string(),
+                };
+                self.put_listing(&format!("{}:0", tx.id()), Some(listing), undo);
+                Ok((signer.to_string(), String::new(), list.amt.clone()))
+            }
+            Krc20Op::Send(send) => {
+                let outpoint = tx.inputs[input_index].previous_outpoint;
+                let key = format!("{}:{}", outpoint.transaction_id, outpoint.index);
+                let listing = self
+                    .listings
+                    .get(&key)
+                    .cloned()
+                    .ok_or_else(|| "listing not found".to_string())?;
+                if listing.tick != send.tick {
+                    return Err("tick mismatch".to_string());
+                }
+                // Buyers receive at output 1, a seller cancelling at output 0
+                let output = tx
+                    .outputs
+                    .get(1)
+                    .or(tx.outputs.first())
+                    .ok_or_else(|| "no recipient output".to_string())?;
+                let to = extract_script_pub_key_address(&output.script_public_key, prefix)
+                    .map_err(|_| "invalid recipient".to_string())?
+                    .to_string();
+
+                let mut holding = self.holding(&listing.owner, &listing.tick);
+                holding.locked -= &listing.amt;
+                self.put_holding(&listing.owner, &listing.tick, holding, undo);
+                self.credit(&to, &listing.tick, &listing.amt, undo);
+                self.put_listing(&key, None, undo);
+                Ok((listing.owner, to, listing.amt))
+            }
+            Krc20Op::Issue(issue) => {
+                let mut token = self.owned_token(&issue.ca, signer)?;
+                if &token.minted + &issue.amt > token.max {
+                    return Err("max exceeded".to_string());
+                }
+                token.minted += &issue.amt;
+                let to = issue.to.clone().unwrap_or_else(|| signer.to_string());
+                self.put_token(token, undo);
+                self.credit(&to, &issue.ca, &issue.amt, undo);
+                Ok((signer.to_string(), to, issue.amt.clone()))
+            }
+            Krc20Op::Burn(burn) => {
+                let mut token = self.owned_token(&burn.ca, signer)?;
+                self.debit(signer, &burn.ca, &burn.amt, undo)?;
+                token.burned += &burn.amt;
+                self.put_token(token, undo);
+                Ok((signer.to_string(), String::new(), burn.amt.clone()))
+            }
+            Krc20Op::Chown(chown) => {
+                let mut token = self.owned_token(&chown.ca, signer)?;
+                token.owner = chown.to.clone();
+                self.put_token(token, undo);
+                Ok((signer.to_string(), chown.to.clone(), BigInt::from(0)))
+            }
+            Krc20Op::Blacklist(blacklist) => {
+                let mut token = self.owned_token(&blacklist.ca, signer)?;
+                match blacklist.mode {
+                    BlacklistMode::Add => token.blacklist.insert(blacklist.to.clone()),
+                    BlacklistMode::Remove => token.blacklist.remove(&blacklist.to),
+                };
+                self.put_token(token, undo);
+                Ok((signer.to_string(), blacklist.to.clone(), BigInt::from(0)))
+            }
+        }
+    }
+
+    /// Applies the reveal in `tx`, if any, and records the outcome.
+    fn apply_transaction(
+        &mut self,
+        tx: &Transaction,
+        block: &str,
+        prefix: Prefix,
+        schedule: &FeeSchedule,
+        undo: &mut Vec<Undo>,
+    ) -> Option<OpRecord> {
+        let (paid, known) = self.track_outputs(tx, undo);
+        let inscription = decode_inscription(tx).ok()?;
+        let signer = inscription.signer(prefix).to_string();
+        let tick = match &inscription.op {
+            Krc20Op::Deploy(deploy) if deploy.mode.is_some() => tx.id().to_string(),
+            op => op.token().unwrap_or_default().to_string(),
+        };
+        let required = schedule.fee(&inscription.op);
+        // Inputs of unknown value only matter if the known ones fall short
+        let result = if paid >= required {
+            self.apply_op(
+                &inscription.op,
+                tx,
+                inscription.input_index,
+                &signer,
+                prefix,
+                undo,
+            )
+        } else if known {
+            Err("fee insufficient".to_string())
+        } else {
+            self.unfollow(
+                &tick,
+                format!("protocol fee of reveal {} is unknown", tx.id()),
+                undo,
+            );
+            Err("fee unknown".to_string())
+        };
+        let (from, to, amt, error) = match result {
+            Ok((from, to, amt)) => (from, to, amt, String::new()),
+            Err(error) => (signer, String::new(), BigInt::from(0), error),
+        };
+        let record = OpRecord {
+            hash: tx.id().to_string(),
+            op: inscription.op.name().to_string(),
+            tick,
+            from,
+            to,
+            amt,
+            block: block.to_string(),
+            accepted: error.is_empty(),
+            error,
+        };
+        self.push_op(&record);
+        Some(record)
+    }
+
+    /// Applies the transactions accepted by chain block `hash`, in acceptance
+    /// order, and returns the ops they carried.
+    pub fn apply_block(
+        &mut self,
+        hash: &str,
+        transactions: &[Transaction],
+        prefix: Prefix,
+        schedule: &FeeSchedule,
+    ) -> Vec<OpRecord> {
+        let ops_offset = self.ops_len;
+        let mut undo = Vec::new();
+        let records = transactions
+            .iter()
+            .filter_map(|tx| self.apply_transaction(tx, hash, prefix, schedule, &mut undo))
+            .collect();
+        self.chain.push_back(ChainBlock {
+            hash: hash.to_string(),
+            ops_offset,
+            undo,
+        });
+        if self.chain.len() > MAX_UNDO_BLOCKS {
+            self.chain.pop_front();
+        }
+        self.unsaved_blocks += 1;
+        records
+    }
+
+    /// Reverts chain blocks down to and including `hash`, dropping their
+    /// ops from the ops log. Returns false if `hash` was never applied, in
+    /// which case nothing is reverted.
+    pub fn rollback(&mut self, hash: &str) -> Result<bool> {
+        if !self.chain.iter().any(|block| block.hash == hash) {
+            return Ok(false);
+        }
+        while let Some(block) = self.chain.pop_back() {
+            self.truncate_ops(block.ops_offset)?;
+            for undo in block.undo.into_iter().rev() {
+                match undo {
+                    Undo::Token { tick, previous } => match previous {
+                        Some(token) => {
+                            self.tokens.insert(tick, *token);
+                        }
+                        None => {
+                            self.tokens.remove(&tick);
+                        }
+                    },
+                    Undo::Holding {
+                        address,
+                        tick,
+                        previous,
+                    } => {
+                        self.restore_holding(&address, &tick, previous);
+                    }
+                    Undo::Listing { outpoint, previous } => match previous {
+                        Some(listing) => {
+                            self.listings.insert(outpoint, listing);
+                        }
+                        None => {
+                            self.listings.remove(&outpoint);
+                        }
+                    },
+                    Undo::Output { outpoint, previous } => match previous {
+                        Some(value) => {
+                            self.outputs.insert(outpoint, value);
+                        }
+                        None => {
+                            self.outputs.remove(&outpoint);
+                        }
+                    },
+                    Undo::Unfollowed { tick, previous } => match previous {
+                        Some(reason) => {
+                            self.unfollowed.insert(tick, reason);
+                        }
+                        None => {
+                            self.unfollowed.remove(&tick);
+                        }
+                    },
+                }
+            }
+            if block.hash == hash {
+                break;
+            }
+        }
+        self.unsaved_blocks += 1;
+        Ok(true)
+    }
+
+    fn contains_block(&self, hash: &RpcHash) -> bool {
+        let hash = hash.to_string();
+        self.chain.iter().rev().any(|block| block.hash == hash)
+    }
+
+    /// Rolls back the `removed` chain blocks. Fails if the oldest one was
+    /// never applied or its undo data was pruned, since the state cannot be
+    /// reverted past it.
+    fn revert(&mut self, removed: &[RpcHash]) -> Result<()> {
+        // Removed blocks are listed from the old tip down, so rolling back
+        // the last one reverts all of them
+        let Some(hash) = removed.last() else {
+            return Ok(());
+        };
+        if !self.rollback(&hash.to_string())? {
+            return Err(Error::Custom(format!(
+                "Chain block {} removed by a reorg is not in the local index, which keeps undo data for {} blocks. Delete {} to rebuild it",
+                hash,
+                MAX_UNDO_BLOCKS,
+                self.path.display()
+            )));
+        }
+        println!("Rolled back {} chain blocks", removed.len());
+        Ok(())
+    }
+
+    /// Applies a virtual chain change: removed chain blocks are rolled back,
+    /// then the transactions accepted by each added chain block are fetched
+    /// from the node and applied.
+    ///
+    /// A change removing blocks the index never applied, such as ones it
+    /// skipped while catching up, is replaced by the chain change from the
+    /// index's last block to the current sink.
+    pub async fn apply_chain_changed(
+        &mut self,
+        client: &KaspaRpcClient,
+        removed: &[RpcHash],
+        added: &[RpcHash],
+        accepted: &[RpcAcceptedTransactionIds],
+        prefix: Prefix,
+        schedule: &FeeSchedule,
+    ) -> Result<Vec<OpRecord>> {
+        let unknown = removed
+            .last()
+            .is_some_and(|hash| !self.contains_block(hash));
+        if unknown {
+            if let Some(sink) = self.sink() {
+                let sink = sink
+                    .parse()
+                    .map_err(|e| Error::Custom(format!("Invalid stored sink: {}", e)))?;
+                println!("Resyncing local index from block {sink}...");
+                let chain = client.get_virtual_chain_from_block(sink, true).await?;
+                self.revert(&chain.removed_chain_block_hashes)?;
+                return self
+                    .apply_added(
+                        client,
+                        &chain.added_chain_block_hashes,
+                        &chain.accepted_transaction_ids,
+                        prefix,
+                        schedule,
+                    )
+                    .await;
+            }
+        } else {
+            self.revert(removed)?;
+        }
+        self.apply_added(client, added, accepted, prefix, schedule)
+            .await
+    }
+
+    /// Fetches and applies the transactions accepted by each added chain
+    /// block.
+    async fn apply_added(
+        &mut self,
+        client: &KaspaRpcClient,
+        added: &[RpcHash],
+        accepted: &[RpcAcceptedTransactionIds],
+        prefix: Prefix,
+        schedule: &FeeSchedule,
+    ) -> Result<Vec<OpRecord>> {
+        let accepted: HashMap<RpcHash, &RpcAcceptedTransactionIds> = accepted
+            .iter()
+            .map(|ids| (ids.accepting_block_hash, ids))
+            .collect();
+        let mut records = Vec::new();
+        for hash in added {
+            // Notifications received while catching up can repeat blocks
+            if self.contains_block(hash) {
+                continue;
+            }
+            let transactions = match accepted.get(hash) {
+                Some(ids) => accepted_transactions(client, ids).await?,
+                None => vec![],
+            };
+            records.extend(self.apply_block(&hash.to_string(), &transactions, prefix, schedule));
+        }
+        Ok(records)
+    }
+}
+
+/// Fetches the transactions accepted by a chain block, in acceptance order.
+/// They come from the blocks of its merge set.
+async fn accepted_transactions(
+    client: &KaspaRpcClient,
+    accepted: &RpcAcceptedTransactionIds,
+) -> Result<Vec<Transaction>> {
+    let accepting_block = client
+        .get_block(accepted.accepting_block_hash, false)
+        .await?;
+    let verbose_data = accepting_block.verbose_data.ok_or_else(|| {
+        Error::Custom(format!(
+            "Block {} has no verbose data",
+            accepted.accepting_block_hash
+        ))
+    })?;
+
+    let mut transactions: HashMap<TransactionId, Transaction> = HashMap::new();
+    for hash in verbose_data
+        .merge_set_blues_hashes
+        .iter()
+        .chain(verbose_data.merge_set_reds_hashes.iter())
+    {
+        let block = client.get_block(*hash, true).await?;
+        for rpc_tx in &block.transactions {
+            let tx = Transaction::try_from(rpc_tx)?;
+            transactions.insert(tx.id(), tx);
+        }
+    }
+    Ok(accepted
+        .accepted_transaction_ids
+        .iter()
+        .filter_map(|id| transactions.remove(id))
+        .collect())
+}
+
+fn print_records(records: &[OpRecord]) {
+    for record in records {
+        if record.accepted {
+            println!(
+                "{} {} {} {} -> {} ({})",
+                record.op, record.amt, record.tick, record.from, record.to, record.hash
+            );
+        } else {
+            println!(
+                "{} {} rejected: {} ({})",
+                record.op, record.tick, record.error, record.hash
+            );
+        }
+    }
+}
+
+/// Follows the virtual chain and keeps the local index up to date. Catches up
+/// from the index's last chain block (or `start_hash` for a new index, or the
+/// current sink) and then applies virtual-chain-changed notifications until
+/// the connection closes. Tokens deployed before the start block are not
+/// tracked. Ops are appended to the ops log as they are
+/// applied, and the store is rewritten every `SNAPSHOT_BLOCKS` chain blocks.
+///
+/// Local index file layout, one value per line:
+/// optional start block hash, optional store path (defaults to
+/// `krc20-index.json`).
+pub async fn follow_chain(
+    client: &KaspaRpcClient,
+    network: NetworkType,
+    index_args: Vec<String>,
+) -> Result<()> {
+    let prefix = Prefix::from(network);
+    let schedule = FeeSchedule::load(network)?;
+    let start_hash = index_args
+        .first()
+        .map(|hash| hash.trim())
+        .filter(|hash| !hash.is_empty())
+        .map(|hash| hash.parse::<RpcHash>())
+        .transpose()
+        .map_err(|e| Error::Custom(format!("Invalid start block hash: {}", e)))?;
+    let path = index_args
+        .get(1)
+        .map(|path| path.trim())
+        .filter(|path| !path.is_empty())
+        .unwrap_or(INDEX_FILE);
+
+    let mut index = LocalIndex::open(path)?;
+    let start_hash = match (index.sink(), start_hash) {
+        (Some(sink), _) => sink
+            .parse()
+            .map_err(|e| Error::Custom(format!("Invalid stored sink: {}", e)))?,
+        (None, Some(start_hash)) => start_hash,
+        (None, None) => client.get_sink().await?.sink,
+    };
+
+    // Subscribe before catching up so no chain change is missed
+    client
+        .start_notify(
+            ListenerId::default(),
+            Scope::VirtualChainChanged(VirtualChainChangedScope::new(true)),
+        )
+        .await?;
+    let notifications = client.notification_channel_receiver();
+
+    println!("Catching up from block {start_hash}...");
+    let chain = client
+        .get_virtual_chain_from_block(start_hash, true)
+        .await?;
+    let records = index
+        .apply_chain_changed(
+            client,
+            &chain.removed_chain_block_hashes,
+            &chain.added_chain_block_hashes,
+            &chain.accepted_transaction_ids,
+            prefix,
+            &schedule,
+        )
+        .await?;
+    print_records(&records);
+    index.save()?;
+    println!("Local index synced to {}", index.sink().unwrap_or_default());
+
+    while let Ok(notification) = notifications.recv().await {
+        if let Notification::VirtualChainChanged(changed) = notification {
+            let records = index
+                .apply_chain_changed(
+                    client,
+                    &changed.removed_chain_block_hashes,
+                    &changed.added_chain_block_hashes,
+                    &changed.accepted_transaction_ids,
+                    prefix,
+                    &schedule,
+                )
+                .await?;
+            print_records(&records);
+            index.flush_ops()?;
+            if index.snapshot_due() {
+                index.save()?;
+            }
+        }
+    }
+    index.save()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::envelope::Content;
+    use crate::krc20::{Blacklist, Burn, Deploy, Issue, List, Mint, Send, Transfer};
+    use kaspa_addresses::{Address, Version};
+    use kaspa_consensus_core::subnets::SUBNETWORK_ID_NATIVE;
+    use kaspa_consensus_core::tx::{TransactionInput, TransactionOutpoint, TransactionOutput};
+    use kaspa_txscript::{
+        pay_to_address_script, pay_to_script_hash_script, pay_to_script_hash_signature_script,
+    };
+
+    const KAS: u64 = 100_000_000;
+
+    /// A local index in a fresh store under the temp directory.
+    fn open_index(name: &str) -> LocalIndex {
+        let path =
+            std::env::temp_dir().join(format!("krc20-index-{}-{}.json", name, std::process::id()));
+        let _ = fs::remove_file(&path);
+        let _ = fs::remove_file(path.with_extension("ops.jsonl"));
+        LocalIndex::open(path).unwrap()
+    }
+
+    fn remove_store(index: LocalIndex) {
+        let _ = fs::remove_file(index.ops_path());
+        let _ = fs::remove_file(&index.path);
+    }
+
+    fn schedule() -> FeeSchedule {
+        FeeSchedule::for_network(NetworkType::Mainnet)
+    }
+
+    fn key(seed: u8) -> [u8; 32] {
+        [seed; 32]
+    }
+
+    fn address(seed: u8) -> String {
+        Address::new(Prefix::Mainnet, Version::PubKey, &key(seed)).to_string()
+    }
+
+    fn output(value: u64, seed: u8) -> TransactionOutput {
+        let address = Address::new(Prefix::Mainnet, Version::PubKey, &key(seed));
+        TransactionOutput::new(value, pay_to_address_script(&address))
+    }
+
+    fn transaction(inputs: Vec<TransactionInput>, outputs: Vec<TransactionOutput>) -> Transaction {
+        Transaction::new(0, inputs, outputs, 0, SUBNETWORK_ID_NATIVE, 0, vec![])
+    }
+
+    /// A commit locking `value` into the envelope of `op` signed by `signer`,
+    /// and the reveal spending it into `outputs`. `nonce` keeps the ids of
+    /// identical ops apart.
+    fn inscribe(
+        signer: u8,
+        op: &Krc20Op,
+        value: u64,
+        outputs: Vec<TransactionOutput>,
+        nonce: u64,
+    ) -> (Transaction, Transaction) {
+        let redeem_script = Content::krc20(op)
+            .unwrap()
+            .redeem_script(&key(signer))
+            .unwrap();
+        let funding = TransactionOutpoint::new(TransactionId::from_u64_word(nonce), 0);
+        let commit = transaction(
+            vec![TransactionInput::new(funding, vec![], 0, 1)],
+            vec![TransactionOutput::new(
+                value,
+                pay_to_script_hash_script(&redeem_script),
+            )],
+        );
+        let reveal = spend(
+            TransactionOutpoint::new(commit.id(), 0),
+            redeem_script,
+            outputs,
+        );
+        (commit, reveal)
+    }
+
+    /// A transaction spending the envelope `redeem_script` at `outpoint`.
+    fn spend(
+        outpoint: TransactionOutpoint,
+        redeem_script: Vec<u8>,
+        outputs: Vec<TransactionOutput>,
+    ) -> Transaction {
+        let signature: Vec<u8> = std::iter::once(65u8).chain([0u8; 65]).collect();
+        let signature_script =
+            pay_to_script_hash_signature_script(redeem_script, signature).unwrap();
+        transaction(
+            vec![TransactionInput::new(outpoint, signature_script, 0, 1)],
+            outputs,
+        )
+    }
+
+    /// Inscribes `op` paying exactly its protocol fee.
+    fn paid(signer: u8, op: &Krc20Op, nonce: u64) -> Vec<Transaction> {
+        let fee = schedule().fee(op);
+        let (commit, reveal) = inscribe(signer, op, fee + KAS, vec![output(KAS, signer)], nonce);
+        vec![commit, reveal]
+    }
+
+    fn deploy(tick: &str) -> Krc20Op {
+        Krc20Op::Deploy(Deploy::new(tick, "1000", "100", "0", None, None).unwrap())
+    }
+
+    fn mint(tick: &str) -> Krc20Op {
+        Krc20Op::Mint(Mint::new(tick, None).unwrap())
+    }
+
+    fn transfer(tick: &str, amt: &str, to: u8) -> Krc20Op {
+        Krc20Op::Transfer(Transfer::new(tick, amt, 0, &address(to)).unwrap())
+    }
+
+    fn balance(index: &LocalIndex, seed: u8, tick: &str) -> BigInt {
+        index.holding(&address(seed), tick).balance
+    }
+
+    /// Applies one block per entry of `blocks`, named `block-<n>`.
+    fn apply(index: &mut LocalIndex, blocks: Vec<Vec<Transaction>>) -> Vec<OpRecord> {
+        let start = index.chain.len();
+        blocks
+            .iter()
+            .enumerate()
+            .flat_map(|(n, transactions)| {
+                index.apply_block(
+                    &format!("block-{}", start + n),
+                    transactions,
+                    Prefix::Mainnet,
+                    &schedule(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn applies_deploy_mint_and_transfer() {
+        let mut index = open_index("apply");
+        let records = apply(
+            &mut index,
+            vec![
+                paid(1, &deploy("test"), 1),
+                [paid(1, &mint("test"), 2), paid(2, &mint("test"), 3)].concat(),
+                paid(1, &transfer("test", "30", 3), 4),
+            ],
+        );
+        assert!(records.iter().all(|record| record.accepted));
+        assert_eq!(index.tokens["test"].minted, BigInt::from(200));
+        assert_eq!(balance(&index, 1, "test"), BigInt::from(70));
+        assert_eq!(balance(&index, 2, "test"), BigInt::from(100));
+        assert_eq!(balance(&index, 3, "test"), BigInt::from(30));
+        assert_eq!(index.holders("test").unwrap().len(), 3);
+        assert_eq!(index.sink(), Some("block-2"));
+        assert_eq!(index.ops().unwrap().len(), 4);
+
+        let records = apply(&mut index, vec![paid(3, &transfer("test", "31", 1), 5)]);
+        assert_eq!(records[0].error, "balance insufficient");
+        assert_eq!(balance(&index, 3, "test"), BigInt::from(30));
+        remove_store(index);
+    }
+
+    #[test]
+    fn checks_protocol_fees() {
+        let mut index = open_index("fees");
+        let op = deploy("test");
+        let (commit, reveal) = inscribe(1, &op, schedule().fee(&op), vec![output(KAS, 1)], 1);
+        let records = apply(&mut index, vec![vec![commit, reveal]]);
+        assert_eq!(records[0].error, "fee insufficient");
+        assert!(!index.tokens.contains_key("test"));
+
+        // A reveal whose commit the index never saw cannot be checked
+        let (_, reveal) = inscribe(1, &op, 2 * schedule().fee(&op), vec![], 2);
+        let records = apply(&mut index, vec![vec![reveal]]);
+        assert_eq!(records[0].error, "fee unknown");
+        assert!(index.holders("test").is_err());
+        remove_store(index);
+    }
+
+    #[test]
+    fn counts_every_known_input_towards_the_fee() {
+        let mut index = open_index("inputs");
+        let op = deploy("test");
+        let fee = schedule().fee(&op);
+        let redeem_script = Content::krc20(&op).unwrap().redeem_script(&key(1)).unwrap();
+
+        // Like `client::inscribe`, the commit locks little in the envelope
+        // and the reveal also spends the commit change
+        let funding = TransactionOutpoint::new(TransactionId::from_u64_word(1), 0);
+        let commit = transaction(
+            vec![TransactionInput::new(funding, vec![], 0, 1)],
+            vec![
+                TransactionOutput::new(KAS / 2, pay_to_script_hash_script(&redeem_script)),
+                output(fee + KAS, 1),
+            ],
+        );
+        let reveal = |value: u64, extra: &[TransactionOutpoint]| {
+            let mut reveal = spend(
+                TransactionOutpoint::new(commit.id(), 0),
+                redeem_script.clone(),
+                vec![output(value, 1)],
+            );
+            for outpoint in extra {
+                reveal
+                    .inputs
+                    .push(TransactionInput::new(*outpoint, vec![], 0, 1));
+            }
+            reveal.finalize();
+            vec![commit.clone(), reveal]
+        };
+        let change = TransactionOutpoint::new(commit.id(), 1);
+        let records = apply(&mut index, vec![reveal(KAS + KAS / 2 + 1, &[change])]);
+        assert_eq!(records[0].error, "fee insufficient");
+        assert!(index.rollback("block-0").unwrap());
+
+        let records = apply(&mut index, vec![reveal(KAS + KAS / 2, &[change])]);
+        assert!(records[0].accepted);
+        assert!(index.unfollowed.is_empty());
+        assert!(index.rollback("block-0").unwrap());
+
+        // Inputs from before the index started only matter when the known
+        // ones fall short
+        let unseen = TransactionOutpoint::new(TransactionId::from_u64_word(2), 0);
+        let records = apply(&mut index, vec![reveal(KAS, &[change, unseen])]);
+        assert!(records[0].accepted);
+        assert!(index.rollback("block-0").unwrap());
+        let records = apply(&mut index, vec![reveal(KAS / 4, &[unseen])]);
+        assert_eq!(records[0].error, "fee unknown");
+        assert!(index.holders("test").is_err());
+        remove_store(index);
+    }
+
+    #[test]
+    fn rollback_reverts_removed_blocks() {
+        let mut index = open_index("rollback");
+        let list = Krc20Op::List(List::new("test", "40", 0).unwrap());
+        apply(
+            &mut index,
+            vec![
+                [paid(1, &deploy("test"), 1), paid(1, &mint("test"), 2)].concat(),
+                paid(1, &transfer("test", "10", 2), 3),
+                paid(1, &list, 4),
+            ],
+        );
+        assert_eq!(index.holding(&address(1), "test").locked, BigInt::from(40));
+        assert_eq!(index.listings.len(), 1);
+
+        // Removed chain blocks come from the old tip down, and rolling back
+        // the last one reverts them all
+        let removed = ["block-2", "block-1"];
+        assert!(index.rollback(removed.last().unwrap()).unwrap());
+        assert_eq!(index.sink(), Some("block-0"));
+        assert_eq!(balance(&index, 1, "test"), BigInt::from(100));
+        assert_eq!(index.holding(&address(1), "test").locked, BigInt::from(0));
+        assert!(!index.holdings.contains_key(&address(2)));
+        assert!(index.listings.is_empty());
+        assert_eq!(index.ops().unwrap().len(), 2);
+
+        // The reverted transfer applies again on the new chain
+        apply(&mut index, vec![paid(1, &transfer("test", "10", 2), 3)]);
+        assert_eq!(balance(&index, 2, "test"), BigInt::from(10));
+        assert!(!index.rollback("unknown").unwrap());
+        assert_eq!(index.ops().unwrap().len(), 3);
+
+        // Blocks that cannot be rolled back must not be built upon
+        assert!(index.revert(&[RpcHash::from_u64_word(1)]).is_err());
+        assert_eq!(balance(&index, 2, "test"), BigInt::from(10));
+        remove_store(index);
+    }
+
+    #[test]
+    fn send_pays_output_one_or_falls_back_to_output_zero() {
+        let mut index = open_index("send");
+        let list = Krc20Op::List(List::new("test", "40", 0).unwrap());
+        let send = Krc20Op::Send(Send::new("test").unwrap());
+        let send_script = Content::krc20(&send)
+            .unwrap()
+            .redeem_script(&key(1))
+            .unwrap();
+        let listing = |nonce| {
+            let (commit, reveal) = inscribe(
+                1,
+                &list,
+                KAS,
+                vec![TransactionOutput::new(
+                    KAS / 2,
+                    pay_to_script_hash_script(&send_script),
+                )],
+                nonce,
+            );
+            (reveal.id(), vec![commit, reveal])
+        };
+        let (bought, list_bought) = listing(3);
+        let (cancelled, list_cancelled) = listing(4);
+        apply(
+            &mut index,
+            vec![
+                [paid(1, &deploy("test"), 1), paid(1, &mint("test"), 2)].concat(),
+                [list_bought, list_cancelled].concat(),
+            ],
+        );
+        assert_eq!(balance(&index, 1, "test"), BigInt::from(20));
+        assert_eq!(index.holding(&address(1), "test").locked, BigInt::from(80));
+
+        // A buy pays the seller at output 0 and the buyer at output 1
+        let buy = spend(
+            TransactionOutpoint::new(bought, 0),
+            send_script.clone(),
+            vec![output(KAS, 1), output(KAS / 4, 2)],
+        );
+        // A cancel has a single output back to the seller
+        let cancel = spend(
+            TransactionOutpoint::new(cancelled, 0),
+            send_script,
+            vec![output(KAS / 4, 1)],
+        );
+        let records = apply(&mut index, vec![vec![buy, cancel]]);
+        assert!(records.iter().all(|record| record.accepted));
+        assert_eq!(records[0].to, address(2));
+        assert_eq!(records[1].to, address(1));
+        assert_eq!(balance(&index, 2, "test"), BigInt::from(40));
+        assert_eq!(balance(&index, 1, "test"), BigInt::from(60));
+        assert_eq!(index.holding(&address(1), "test").locked, BigInt::from(0));
+        assert!(index.listings.is_empty());
+        remove_store(index);
+    }
+
+    #[test]
+    fn follows_issue_mode_tokens() {
+        let mut index = open_index("issue");
+        let deploy = Krc20Op::Deploy(Deploy::new_issue("test", "1000", "0", None, None).unwrap());
+        let deploy_txs = paid(1, &deploy, 1);
+        let ca = deploy_txs[1].id().to_string();
+        let issue = |amt: &str, to: u8| {
+            Krc20Op::Issue(Issue::new(&ca, amt, 0, Some(&address(to))).unwrap())
+        };
+        let records = apply(
+            &mut index,
+            vec![
+                deploy_txs,
+                [paid(1, &issue("300", 2), 2), paid(2, &issue("300", 2), 3)].concat(),
+                paid(1, &issue("800", 1), 4),
+            ],
+        );
+        assert!(records[1].accepted);
+        assert_eq!(records[2].error, "no ownership");
+        assert_eq!(records[3].error, "max exceeded");
+        assert_eq!(index.tokens[&ca].minted, BigInt::from(300));
+        assert_eq!(balance(&index, 2, "test"), BigInt::from(0));
+        assert_eq!(balance(&index, 2, &ca), BigInt::from(300));
+
+        let ca_transfer = |amt: &str| {
+            Krc20Op::Transfer(Transfer {
+                tick: None,
+                ca: Some(ca.clone()),
+                amt: BigInt::from(amt.parse::<u64>().unwrap()),
+                to: address(1),
+            })
+        };
+        let blacklist =
+            Krc20Op::Blacklist(Blacklist::new(&ca, BlacklistMode::Add, &address(2)).unwrap());
+        let records = apply(
+            &mut index,
+            vec![
+                paid(2, &ca_transfer("100"), 5),
+                paid(1, &blacklist, 6),
+                paid(2, &ca_transfer("100"), 7),
+                paid(1, &Krc20Op::Burn(Burn::new(&ca, "50", 0).unwrap()), 8),
+            ],
+        );
+        assert!(records[0].accepted);
+        assert_eq!(records[2].error, "blacklisted");
+        assert!(records[3].accepted);
+        assert_eq!(balance(&index, 1, &ca), BigInt::from(50));
+        assert_eq!(balance(&index, 2, &ca), BigInt::from(200));
+        assert_eq!(index.tokens[&ca].burned, BigInt::from(50));
+        remove_store(index);
+    }
+
+    #[test]
+    fn reopening_drops_ops_logged_after_the_store() {
+        let mut index = open_index("reopen");
+        apply(&mut index, vec![paid(1, &deploy("test"), 1)]);
+        index.save().unwrap();
+        apply(&mut index, vec![paid(1, &mint("test"), 2)]);
+        index.flush_ops().unwrap();
+        assert_eq!(index.ops().unwrap().len(), 2);
+
+        let index = LocalIndex::open(&index.path).unwrap();
+        assert_eq!(index.sink(), Some("block-0"));
+        assert_eq!(index.ops().unwrap().len(), 1);
+        assert_eq!(balance(&index, 1, "test"), BigInt::from(0));
+        remove_store(index);
+    }
+}
@@ -1,9 +1,24 @@
 #![allow(unused)]
 mod args;
+mod batch;
 mod client;
+mod decoder;
+mod envelope;
+mod farm;
+mod fees;
+mod history;
+mod indexer;
+mod journal;
+mod krc20;
+mod krc721;
+mod local_indexer;
+mod market;
+mod snapshot;
+mod sweep;
 mod wallet;
 
 use args::{parse_args, Args};
+use indexer::{parse_number, IndexerClient, OpReport, OpStatus};
 use kaspa_addresses::{Address, Prefix, Version};
 use kaspa_bip32::{DerivationPath, ExtendedPrivateKey, Language, Mnemonic, SecretKey, WordCount};
 use kaspa_consensus_client::{
@@ -28,6 +43,7 @@ use kaspa_wrpc_client::{
     result::Result,
     KaspaRpcClient, Resolver, WrpcEncoding,
 };
+use krc20::{format_amount, parse_decimals};
 use num_bigint::BigInt;
 use secp256k1::Secp256k1;
 use std::fs::read;
@@ -37,6 +53,8 @@ use wallet::*;
 use std::time::Duration;
 
 const TIMEOUT: u64 = 120_000; // 2 minutes
+const OP_STATUS_TIMEOUT: u64 = 300_000; // 5 minutes
+const OP_STATUS_INTERVAL: u64 = 2_000;
 
 fn read_file_to_vec(file_path: &str) -> Result<Vec<String>> {
     // Read the entire file into a single String
@@ -54,6 +72,31 @@ fn read_file_to_vec(file_path: &str) -> Result<Vec<String>> {
     Ok(lines)
 }
 
+/// Waits for the indexer to settle the op revealed in `reveal_id`, then prints
+/// the outcome followed by a JSON status line for scripts.
+async fn track_op(indexer: &IndexerClient, reveal_id: &str) {
+    println!("Waiting for indexer to process {reveal_id}...");
+    let (info, status) = indexer
+        .wait_for_op(
+            reveal_id,
+            Duration::from_millis(OP_STATUS_TIMEOUT),
+            Duration::from_millis(OP_STATUS_INTERVAL),
+        )
+        .await;
+    let op = info.as_ref().map(|info| info.op.as_str()).unwrap_or("");
+    match &status {
+        OpStatus::Accepted => println!("Operation {op} accepted"),
+        OpStatus::Rejected(error) => println!("Operation {op} rejected: {error}"),
+        OpStatus::TimedOut => println!("Timed out waiting for operation status"),
+    }
+    let report = OpReport {
+        reveal_id,
+        op,
+        status: &status,
+    };
+    println!("{}", report.to_json());
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
     let args: Args = parse_args();
@@ -123,6 +166,24 @@ async fn main() -> Result<()> {
 
     client.connect(Some(options)).await?;
 
+    let indexer: Option<IndexerClient> = if args.indexer.is_empty() {
+        IndexerClient::new(&args.network).ok()
+    } else {
+        Some(IndexerClient::with_base_url(&args.indexer))
+    };
+    // Indexer used to check KRC-20 operations before broadcasting them
+    let preflight_indexer: Option<&IndexerClient> = if args.skip_preflight {
+        None
+    } else {
+        indexer.as_ref()
+    };
+    if !args.skip_preflight && indexer.is_none() {
+        eprintln!(
+            "No KRC-20 indexer for {}, preflight checks are disabled. Use --indexer to enable them",
+            args.network
+        );
+    }
+
     if args.get_sync_status {
         println!("Getting Sync Status...");
         let is_synced: bool = client.get_sync_status().await?;
@@ -257,6 +318,588 @@ async fn main() -> Result<()> {
             Ok(lines) => {
                 let transfer_args = lines;
                 println!("Transfering KRC20 Tokens...");
+                match client::transfer_krc20_tokens(
+                    &client,
+                    preflight_indexer,
+                    network_type,
+                    transfer_args,
+                )
+                .await
+                {
+                    Ok((commit_id, reveal_id)) => {
+                        println!("Commit transaction id: {commit_id}");
+                        println!("Reveal transaction id: {reveal_id}");
+                        if let Some(indexer) = &indexer {
+                            track_op(indexer, &reveal_id.to_string()).await;
+                        }
+                    }
+                    Err(e) => eprintln!("Error: {e}"),
+                }
+            }
+            Err(e) => eprintln!("Error: {e}"),
+        }
+    }
+
+    if !args.batch_transfer_krc20_tokens.is_empty() {
+        let file_path: String = args.batch_transfer_krc20_tokens;
+
+        match read_file_to_vec(&file_path) {
+            Ok(lines) => {
+                println!("Transfering KRC20 Tokens in Batch...");
+                match batch::batch_transfer_krc20_tokens(
+                    &client,
+                    preflight_indexer,
+                    network_type,
+                    lines,
+                )
+                .await
+                {
+                    Ok(batch) => {
+                        println!("Commit transaction id: {}", batch.commit_id);
+                        for transfer in &batch.transfers {
+                            match &transfer.result {
+                                Ok(reveal_id) => println!(
+                                    "{} {}: reveal transaction id: {reveal_id}",
+                                    transfer.recipient, transfer.amount
+                                ),
+                                Err(e) => println!(
+                                    "{} {}: failed (commit output {}): {e}",
+                                    transfer.recipient, transfer.amount, transfer.output_index
+                                ),
+                            }
+                        }
+                        if let Some(indexer) = &indexer {
+                            for transfer in &batch.transfers {
+                                if let Ok(reveal_id) = &transfer.result {
+                                    track_op(indexer, &reveal_id.to_string()).await;
+                                }
+                            }
+                        }
+                    }
+                    Err(e) => eprintln!("Error: {e}"),
+                }
+            }
+            Err(e) => eprintln!("Error: {e}"),
+        }
+    }
+
+    if !args.mint_krc20_tokens.is_empty() {
+        let file_path: String = args.mint_krc20_tokens;
+
+        match read_file_to_vec(&file_path) {
+            Ok(lines) => {
+                println!("Minting KRC20 Tokens...");
+                match client::mint_krc20_tokens(
+                    &client,
+                    preflight_indexer,
+                    indexer.as_ref(),
+                    network_type,
+                    lines,
+                )
+                .await
+                {
+                    Ok(reveal_ids) => {
+                        for reveal_id in &reveal_ids {
+                            println!("Reveal transaction id: {reveal_id}");
+                        }
+                        if let Some(indexer) = &indexer {
+                            for reveal_id in &reveal_ids {
+                                track_op(indexer, &reveal_id.to_string()).await;
+                            }
+                        }
+                    }
+                    Err(e) => eprintln!("Error: {e}"),
+                }
+            }
+            Err(e) => eprintln!("Error: {e}"),
+        }
+    }
+
+    if !args.mint_farm.is_empty() {
+        let file_path: String = args.mint_farm;
+
+        match (read_file_to_vec(&file_path), &indexer) {
+            (Ok(lines), Some(farm_indexer)) => {
+                println!("Minting KRC20 Tokens from Child Wallets...");
+                match farm::mint_farm(
+                    &client,
+                    preflight_indexer,
+                    farm_indexer,
+                    network_type,
+                    lines,
+                )
+                .await
+                {
+                    Ok(farm) => {
+                        for funding_id in &farm.funding_ids {
+                            println!("Funding transaction id: {funding_id}");
+                        }
+                        for child in &farm.children {
+                            let consolidation = child
+                                .consolidation
+                                .map(|id| id.to_string())
+                                .unwrap_or_else(|| "none".to_string());
+                            match &child.error {
+                                None => println!(
+                                    "Child {} {}: {} mints, consolidation {consolidation}",
+                                    child.index,
+                                    child.address,
+                                    child.reveal_ids.len()
+                                ),
+                                Some(e) => println!(
+                                    "Child {} {}: failed after {} mints, consolidation {consolidation}: {e}",
+                                    child.index,
+                                    child.address,
+                                    child.reveal_ids.len()
+                                ),
+                            }
+                        }
+                        let failed = farm
+                            .children
+                            .iter()
+                            .filter(|child| child.error.is_some())
+                            .count();
+                        println!(
+                            "{} of {} children finished",
+                            farm.children.len() - failed,
+                            farm.children.len()
+                        );
+                    }
+                    Err(e) => eprintln!("Error: {e}"),
+                }
+            }
+            (Ok(_), None) => eprintln!(
+                "Error: The mint farm needs a KRC-20 indexer to consolidate balances. Use --indexer to set one"
+            ),
+            (Err(e), _) => eprintln!("Error: {e}"),
+        }
+    }
+
+    if !args.sweep_krc20_tokens.is_empty() {
+        let file_path: String = args.sweep_krc20_tokens;
+
+        match (read_file_to_vec(&file_path), &indexer) {
+            (Ok(lines), Some(sweep_indexer)) => {
+                println!("Sweeping KRC20 Tokens from Child Wallets...");
+                match sweep::sweep_krc20_tokens(&client, sweep_indexer, network_type, lines).await {
+                    Ok(sweep) => {
+                        for top_up_id in &sweep.top_up_ids {
+                            println!("Top-up transaction id: {top_up_id}");
+                        }
+                        for child in &sweep.children {
+                            match &child.result {
+                                Ok(reveal_id) => println!(
+                                    "Child {} {} {}: reveal transaction id: {reveal_id}",
+                                    child.index, child.address, child.amount
+                                ),
+                                Err(e) => println!(
+                                    "Child {} {} {}: failed: {e}",
+                                    child.index, child.address, child.amount
+                                ),
+                            }
+                        }
+                        let failed = sweep
+                            .children
+                            .iter()
+                            .filter(|child| child.result.is_err())
+                            .count();
+                        println!(
+                            "Swept {} of {} children ({} still settling). Run again to retry failures",
+                            sweep.children.len() - failed,
+                            sweep.children.len(),
+                            sweep.in_flight
+                        );
+                    }
+                    Err(e) => eprintln!("Error: {e}"),
+                }
+            }
+            (Ok(_), None) => eprintln!(
+                "Error: The sweep needs a KRC-20 indexer to find balances. Use --indexer to set one"
+            ),
+            (Err(e), _) => eprintln!("Error: {e}"),
+        }
+    }
+
+    if !args.deploy_krc20_token.is_empty() {
+        let file_path: String = args.deploy_krc20_token;
+
+        match read_file_to_vec(&file_path) {
+            Ok(lines) => {
+                println!("Deploying KRC20 Token...");
+                match client::deploy_krc20_token(&client, preflight_indexer, network_type, lines)
+                    .await
+                {
+                    Ok((commit_id, reveal_id)) => {
+                        println!("Commit transaction id: {commit_id}");
+                        println!("Reveal transaction id: {reveal_id}");
+                        if let Some(indexer) = &indexer {
+                            track_op(indexer, &reveal_id.to_string()).await;
+                        }
+                    }
+                    Err(e) => eprintln!("Error: {e}"),
+                }
+            }
+            Err(e) => eprintln!("Error: {e}"),
+        }
+    }
+
+    if !args.deploy_issue_krc20_token.is_empty() {
+        let file_path: String = args.deploy_issue_krc20_token;
+
+        match read_file_to_vec(&file_path) {
+            Ok(lines) => {
+                println!("Deploying Issue-Mode KRC20 Token...");
+                match client::deploy_issue_krc20_token(
+                    &client,
+                    preflight_indexer,
+                    network_type,
+                    lines,
+                )
+                .await
+                {
+                    Ok((commit_id, reveal_id)) => {
+                        println!("Commit transaction id: {commit_id}");
+                        println!("Reveal transaction id: {reveal_id}");
+                        println!("Contract address (ca): {reveal_id}");
+                        if let Some(indexer) = &indexer {
+                            track_op(indexer, &reveal_id.to_string()).await;
+                        }
+                    }
+                    Err(e) => eprintln!("Error: {e}"),
+                }
+            }
+            Err(e) => eprintln!("Error: {e}"),
+        }
+    }
+
+    if !args.krc20_owner_op.is_empty() {
+        let file_path: String = args.krc20_owner_op;
+
+        match read_file_to_vec(&file_path) {
+            Ok(lines) => {
+                println!("Running KRC20 Owner Operation...");
+                match client::krc20_owner_op(&client, preflight_indexer, network_type, lines).await
+                {
+                    Ok((commit_id, reveal_id)) => {
+                        println!("Commit transaction id: {commit_id}");
+                        println!("Reveal transaction id: {reveal_id}");
+                        if let Some(indexer) = &indexer {
+                            track_op(indexer, &reveal_id.to_string()).await;
+                        }
+                    }
+                    Err(e) => eprintln!("Error: {e}"),
+                }
+            }
+            Err(e) => eprintln!("Error: {e}"),
+        }
+    }
+
+    if !args.deploy_krc721_collection.is_empty() {
+        let file_path: String = args.deploy_krc721_collection;
+
+        match read_file_to_vec(&file_path) {
+            Ok(lines) => {
+                println!("Deploying KRC721 Collection...");
+                match client::deploy_krc721_collection(&client, network_type, lines).await {
+                    Ok((commit_id, reveal_id)) => {
+                        println!("Commit transaction id: {commit_id}");
+                        println!("Reveal transaction id: {reveal_id}");
+                    }
+                    Err(e) => eprintln!("Error: {e}"),
+                }
+            }
+            Err(e) => eprintln!("Error: {e}"),
+        }
+    }
+
+    if !args.mint_krc721_tokens.is_empty() {
+        let file_path: String = args.mint_krc721_tokens;
+
+        match read_file_to_vec(&file_path) {
+            Ok(lines) => {
+                println!("Minting KRC721 Tokens...");
+                match client::mint_krc721_tokens(&client, network_type, lines).await {
+                    Ok(reveal_ids) => {
+                        for reveal_id in &reveal_ids {
+                            println!("Reveal transaction id: {reveal_id}");
+                        }
+                    }
+                    Err(e) => eprintln!("Error: {e}"),
+                }
+            }
+            Err(e) => eprintln!("Error: {e}"),
+        }
+    }
+
+    if !args.transfer_krc721_token.is_empty() {
+        let file_path: String = args.transfer_krc721_token;
+
+        match read_file_to_vec(&file_path) {
+            Ok(lines) => {
+                println!("Transfering KRC721 Token...");
+                match client::transfer_krc721_token(&client, network_type, lines).await {
+                    Ok((commit_id, reveal_id)) => {
+                        println!("Commit transaction id: {commit_id}");
+                        println!("Reveal transaction id: {reveal_id}");
+                    }
+                    Err(e) => eprintln!("Error: {e}"),
+                }
+            }
+            Err(e) => eprintln!("Error: {e}"),
+        }
+    }
+
+    if !args.inscribe_data.is_empty() {
+        let file_path: String = args.inscribe_data;
+
+        match read_file_to_vec(&file_path) {
+            Ok(lines) => {
+                println!("Inscribing Data...");
+                match client::inscribe_data(&client, network_type, lines).await {
+                    Ok((commit_id, reveal_id)) => {
+                        println!("Commit transaction id: {commit_id}");
+                        println!("Reveal transaction id: {reveal_id}");
+                    }
+                    Err(e) => eprintln!("Error: {e}"),
+                }
+            }
+            Err(e) => eprintln!("Error: {e}"),
+        }
+    }
+
+    if !args.recover.is_empty() {
+        let file_path: String = args.recover;
+
+        match read_file_to_vec(&file_path) {
+            Ok(lines) => {
+                println!("Recovering Pending Commits...");
+                match client::recover(&client, network_type, lines).await {
+                    Ok(reveal_ids) => {
+                        for reveal_id in reveal_ids {
+                            println!("Recovery transaction id: {reveal_id}");
+                        }
+                    }
+                    Err(e) => eprintln!("Error: {e}"),
+                }
+            }
+            Err(e) => eprintln!("Error: {e}"),
+        }
+    }
+
+    if !args.list_krc20_tokens.is_empty() {
+        let file_path: String = args.list_krc20_tokens;
+
+        match read_file_to_vec(&file_path) {
+            Ok(lines) => {
+                println!("Listing KRC20 Tokens...");
+                match market::list_krc20_tokens(&client, preflight_indexer, network_type, lines)
+                    .await
+                {
+                    Ok((reveal_id, pskt)) => {
+                        println!("Listing transaction id: {reveal_id}");
+                        println!("Listing PSKT: {pskt}");
+                        if let Some(indexer) = &indexer {
+                            track_op(indexer, &reveal_id.to_string()).await;
+                        }
+                    }
+                    Err(e) => eprintln!("Error: {e}"),
+                }
+            }
+            Err(e) => eprintln!("Error: {e}"),
+        }
+    }
+
+    if !args.cancel_krc20_listing.is_empty() {
+        let file_path: String = args.cancel_krc20_listing;
+
+        match read_file_to_vec(&file_path) {
+            Ok(lines) => {
+                println!("Cancelling KRC20 Listing...");
+                match market::cancel_krc20_listing(&client, network_type, lines).await {
+                    Ok(cancel_id) => {
+                        println!("Cancel transaction id: {cancel_id}");
+                        if let Some(indexer) = &indexer {
+                            track_op(indexer, &cancel_id.to_string()).await;
+                        }
+                    }
+                    Err(e) => eprintln!("Error: {e}"),
+                }
+            }
+            Err(e) => eprintln!("Error: {e}"),
+        }
+    }
+
+    if !args.buy_krc20_listing.is_empty() {
+        let file_path: String = args.buy_krc20_listing;
+
+        match read_file_to_vec(&file_path) {
+            Ok(lines) => {
+                println!("Buying KRC20 Listing...");
+                match market::buy_krc20_listing(&client, preflight_indexer, network_type, lines)
+                    .await
+                {
+                    Ok(buy_id) => {
+                        println!("Buy transaction id: {buy_id}");
+                        if let Some(indexer) = &indexer {
+                            track_op(indexer, &buy_id.to_string()).await;
+                        }
+                    }
+                    Err(e) => eprintln!("Error: {e}"),
+                }
+            }
+            Err(e) => eprintln!("Error: {e}"),
+        }
+    }
+
+    if !args.decode_transactions.is_empty() {
+        let file_path: String = args.decode_transactions;
+
+        match read_file_to_vec(&file_path) {
+            Ok(lines) => {
+                println!("Decoding Inscriptions...");
+                if let Err(e) = decoder::decode_transactions(&client, prefix, lines).await {
+                    eprintln!("Error: {e}");
+                }
+            }
+            Err(e) => eprintln!("Error: {e}"),
+        }
+    }
+
+    if !args.local_index.is_empty() {
+        let file_path: String = args.local_index;
+
+        match read_file_to_vec(&file_path) {
+            Ok(lines) => {
+                println!("Running Local KRC20 Indexer...");
+                if let Err(e) = local_indexer::follow_chain(&client, network_type, lines).await {
+                    eprintln!("Error: {e}");
+                }
+            }
+            Err(e) => eprintln!("Error: {e}"),
+        }
+    }
+
+    if !args.get_krc20_balances.is_empty() {
+        let file_path: String = args.get_krc20_balances;
+
+        match (read_file_to_vec(&file_path), &indexer) {
+            (Ok(lines), Some(indexer)) => {
+                for line in lines {
+                    println!("Getting KRC20 Balances...{line}");
+                    match indexer.get_all_balances(&line).await {
+                        Ok(balances) => {
+                            for balance in balances {
+                                let parsed = parse_decimals(&balance.dec).and_then(|dec| {
+                                    Ok((
+                                        dec,
+                                        parse_number(&balance.balance, "balance")?,
+                                        parse_number(&balance.locked, "locked")?,
+                                    ))
+                                });
+                                let (dec, amount, locked) = match parsed {
+                                    Ok(parsed) => parsed,
+                                    Err(e) => {
+                                        eprintln!("Error: {}: {e}", balance.tick);
+                                        continue;
+                                    }
+                                };
+                                println!(
+                                    "{}: {} (locked: {})",
+                                    balance.tick,
+                                    format_amount(&amount, dec),
+                                    format_amount(&locked, dec)
+                                );
+                            }
+                        }
+                        Err(e) => eprintln!("Error: {e}"),
+                    }
+                }
+            }
+            (Err(e), _) => eprintln!("Error: {e}"),
+            (_, None) => eprintln!("Error: no KRC-20 indexer for {}", args.network),
+        }
+    }
+
+    if !args.get_krc20_token_info.is_empty() {
+        let file_path: String = args.get_krc20_token_info;
+
+        match (read_file_to_vec(&file_path), &indexer) {
+            (Ok(lines), Some(indexer)) => {
+                for line in lines {
+                    println!("Getting KRC20 Token Info...{line}");
+                    match indexer.get_token_info(&line).await {
+                        Ok(Some(info)) => {
+                            let parsed = parse_decimals(&info.dec).and_then(|dec| {
+                                Ok((
+                                    dec,
+                                    parse_number(&info.max, "max")?,
+                                    parse_number(&info.lim, "lim")?,
+                                    parse_number(&info.minted, "minted")?,
+                                ))
+                            });
+                            let (dec, max, lim, minted) = match parsed {
+                                Ok(parsed) => parsed,
+                                Err(e) => {
+                                    eprintln!("Error: {}: {e}", info.tick);
+                                    continue;
+                                }
+                            };
+                            println!("Token: {} ({})", info.tick, info.state);
+                            println!("Max supply: {}", format_amount(&max, dec));
+                            println!("Mint limit: {}", format_amount(&lim, dec));
+                            println!("Minted: {}", format_amount(&minted, dec));
+                            println!("Holders: {}", info.holder_total);
+                        }
+                        Ok(None) => println!("Token {line} not found"),
+                        Err(e) => eprintln!("Error: {e}"),
+                    }
+                }
+            }
+            (Err(e), _) => eprintln!("Error: {e}"),
+            (_, None) => eprintln!("Error: no KRC-20 indexer for {}", args.network),
+        }
+    }
+
+    if !args.op_history.is_empty() {
+        let file_path: String = args.op_history;
+        let output = if args.op_history_output.is_empty() {
+            history::HISTORY_FILE.to_string()
+        } else {
+            args.op_history_output
+        };
+
+        match (read_file_to_vec(&file_path), &indexer) {
+            (Ok(lines), Some(indexer)) => {
+                println!("Exporting KRC20 Operation History...");
+                match history::export_op_history(indexer, lines, &output).await {
+                    Ok(entries) => println!("Exported {} operations to {output}", entries.len()),
+                    Err(e) => eprintln!("Error: {e}"),
+                }
+            }
+            (Err(e), _) => eprintln!("Error: {e}"),
+            (_, None) => eprintln!("Error: no KRC-20 indexer for {}", args.network),
+        }
+    }
+
+    if !args.holder_snapshot.is_empty() {
+        let file_path: String = args.holder_snapshot;
+
+        match read_file_to_vec(&file_path) {
+            Ok(lines) => {
+                println!("Exporting KRC20 Holder Snapshot...");
+                match snapshot::export_holder_snapshot(&client, indexer.as_ref(), lines).await {
+                    Ok(snapshot) => {
+                        println!(
+                            "Exported {} holders of {} at DAA score {} (source: {})",
+                            snapshot.holders.len(),
+                            snapshot.tick,
+                            snapshot.daa_score,
+                            snapshot.source
+                        );
+                        println!("Minted supply: {}", snapshot.supply);
+                    }
+                    Err(e) => eprintln!("Error: {e}"),
+                }
             }
             Err(e) => eprintln!("Error: {e}"),
         }
@@ -0,0 +1,769 @@
+use kaspa_addresses::{Address, Prefix, Version};
+use kaspa_consensus_core::hashing::sighash::{calc_schnorr_signature_hash, SigHashReusedValues};
+use kaspa_consensus_core::hashing::sighash_type::{
+    SigHashType, SIG_HASH_ALL, SIG_HASH_ANY_ONE_CAN_PAY, SIG_HASH_SINGLE,
+};
+use kaspa_consensus_core::subnets::SUBNETWORK_ID_NATIVE;
+use kaspa_consensus_core::tx::{
+    SignableTransaction, Transaction, TransactionId, TransactionInput, TransactionOutpoint,
+    TransactionOutput, UtxoEntry,
+};
+use kaspa_rpc_core::{api::rpc::RpcApi, RpcTransaction};
+use kaspa_txscript::{
+    pay_to_address_script, pay_to_script_hash_script, pay_to_script_hash_signature_script,
+};
+use kaspa_wallet_core::utils::sompi_to_kaspa_string;
+use kaspa_wallet_pskt::prelude::{Bundle, Creator, InputBuilder, OutputBuilder, PSKT};
+use kaspa_wrpc_client::{error::Error, prelude::NetworkType, result::Result, KaspaRpcClient};
+use secp256k1::{schnorr, Keypair, Message, Secp256k1, XOnlyPublicKey};
+
+use crate::client::{
+    build_redeem_script, build_reveal_transaction, fetch_utxos, inscribe, keypair_from_hex,
+    p2sh_address, parse_kaspa, preflight, schnorr_signature, token_decimals, Fees, Utxo,
+};
+use crate::decoder::{parse_envelope, parse_ops};
+use crate::envelope::Content;
+use crate::fees::{compute_mass, fee_and_change, reveal_mass, FeeSchedule};
+use crate::indexer::IndexerClient;
+use crate::krc20::{Krc20Op, List, Send};
+
+use std::fs;
+
+/// Value in sompi of the UTXO that holds a listing. It goes to the buyer with
+/// the tokens, or back to the seller when the listing is cancelled.
+pub const LISTING_AMOUNT: u64 = 20_000_000;
+
+/// SIGHASH_SINGLE|ANYONECANPAY: the seller commits only to the listing input
+/// and the payment output at the same index, so a buyer can add inputs and
+/// outputs without invalidating the signature.
+pub fn listing_sighash_type() -> Result<SigHashType> {
+    Ok(SigHashType::from_u8(
+        SIG_HASH_SINGLE.to_u8() | SIG_HASH_ANY_ONE_CAN_PAY.to_u8(),
+    )?)
+}
+
+/// Builds the redeem script of the P2SH address a listing of `tick` by the
+/// owner of `keypair` is locked in. The script carries a `send` op, which is
+/// revealed by whichever transaction spends the listing.
+pub fn send_script(keypair: &Keypair, tick: &str) -> Result<Vec<u8>> {
+    let (x_only_public_key, _) = keypair.x_only_public_key();
+    let data = Krc20Op::Send(Send::new(tick)?).to_json()?;
+    build_redeem_script(&x_only_public_key.serialize(), data.as_bytes())
+}
+
+/// Builds a PSKT spending `listing_utxo` to a single output paying `price`
+/// to `seller`. Input 0 is signed with SIGHASH_SINGLE|ANYONECANPAY and
+/// finalized, so a buyer only adds and signs their own inputs and outputs.
+/// Returns the serialized bundle.
+pub fn build_listing_pskt(
+    keypair: &Keypair,
+    listing_utxo: Utxo,
+    send_script: &[u8],
+    price: u64,
+    seller: &Address,
+) -> Result<String> {
+    let sighash_type = listing_sighash_type()?;
+    let (outpoint, entry) = listing_utxo;
+    let payment = TransactionOutput::new(price, pay_to_address_script(seller));
+
+    let tx = Transaction::new(
+        0,
+        vec![TransactionInput::new(outpoint, vec![], 0, 1)],
+        vec![payment.clone()],
+        0,
+        SUBNETWORK_ID_NATIVE,
+        0,
+        vec![],
+    );
+    let signable = SignableTransaction::with_entries(tx, vec![entry.clone()]);
+    let signature = schnorr_signature(
+        &signable,
+        0,
+        keypair,
+        sighash_type,
+        &mut SigHashReusedValues::new(),
+    )?;
+    let signature_script = pay_to_script_hash_signature_script(send_script.to_vec(), signature)
+        .map_err(|e| Error::Custom(format!("Failed to build signature script: {}", e)))?;
+
+    let mut input = InputBuilder::default()
+        .utxo_entry(entry)
+        .previous_outpoint(outpoint)
+        .sequence(0)
+        .sig_op_count(1)
+        .sighash_type(sighash_type)
+        .redeem_script(send_script.to_vec())
+        .build()
+        .map_err(|e| Error::Custom(format!("Failed to build PSKT input: {}", e)))?;
+    input.final_script_sig = Some(signature_script);
+    let output = OutputBuilder::default()
+        .amount(payment.value)
+        .script_public_key(payment.script_public_key)
+        .build()
+        .map_err(|e| Error::Custom(format!("Failed to build PSKT output: {}", e)))?;
+
+    let pskt = PSKT::<Creator>::default()
+        .inputs_modifiable()
+        .outputs_modifiable()
+        .constructor()
+        .input(input)
+        .output(output);
+    Bundle::from(pskt)
+        .serialize()
+        .map_err(|e| Error::Custom(format!("Failed to serialize PSKT: {}", e)))
+}
+
+/// Lists KRC-20 tokens for sale. A `list` op is inscribed whose reveal locks
+/// the tokens in a UTXO at the P2SH address of the seller's `send` script,
+/// then a PSKT selling that UTXO for `price` is written to
+/// `listing-<reveal id>.pskt` for buyers. Returns the reveal id and the PSKT.
+///
+/// List file layout, one value per line:
+/// private key, ticker, amount, price (KAS), priority fee (KAS), optional
+/// token decimals (read from the indexer, required without one).
+pub async fn list_krc20_tokens(
+    client: &KaspaRpcClient,
+    indexer: Option<&IndexerClient>,
+    network: NetworkType,
+    list_args: Vec<String>,
+) -> Result<(TransactionId, String)> {
+    if list_args.len() < 5 {
+        return Err(Error::Custom(format!(
+            "Expected at least 5 lines of list arguments, found {}",
+            list_args.len()
+        )));
+    }
+
+    let keypair = keypair_from_hex(&list_args[0])?;
+    let ticker = list_args[1].clone();
+    let dec = token_decimals(
+        indexer,
+        &ticker,
+        list_args.get(5).map(String::as_str).unwrap_or(""),
+    )
+    .await?;
+    let op = Krc20Op::List(List::new(&ticker, &list_args[2], dec)?);
+    let price = parse_kaspa(&list_args[3], "price")?;
+    if price == 0 {
+        return Err(Error::Custom("Listing price must be positive".to_string()));
+    }
+    let fee = parse_kaspa(&list_args[4], "priority fee")?;
+
+    let prefix = Prefix::from(network);
+    let (x_only_public_key, _) = keypair.x_only_public_key();
+    let address = Address::new(prefix, Version::PubKey, &x_only_public_key.serialize());
+    println!("Determining address: {address}");
+
+    if let Some(indexer) = indexer {
+        preflight(indexer, &op, &address).await?;
+    }
+    let content = Content::krc20(&op)?;
+    println!("Inscribing: {content}");
+
+    let send_script = send_script(&keypair, &ticker)?;
+    let listing_output =
+        TransactionOutput::new(LISTING_AMOUNT, pay_to_script_hash_script(&send_script));
+    println!("Listing address: {}", p2sh_address(&send_script, prefix)?);
+
+    let utxos = fetch_utxos(client, &address).await?;
+    if utxos.is_empty() {
+        return Err(Error::Custom(format!(
+            "No UTXOs available for address: {address}"
+        )));
+    }
+    let fees = Fees {
+        priority_fee: fee,
+        protocol_fee: FeeSchedule::load(network)?.fee(&op),
+    };
+    let inscribed = inscribe(
+        client,
+        &keypair,
+        prefix,
+        utxos,
+        &content,
+        vec![listing_output.clone()],
+        fees,
+    )
+    .await?;
+
+    let listing_utxo = (
+        TransactionOutpoint::new(inscribed.reveal_id, 0),
+        UtxoEntry::new(
+            listing_output.value,
+            listing_output.script_public_key,
+            0,
+            false,
+        ),
+    );
+    let pskt = build_listing_pskt(&keypair, listing_utxo, &send_script, price, &address)?;
+    let path = format!("listing-{}.pskt", inscribed.reveal_id);
+    fs::write(&path, &pskt)
+        .map_err(|e| Error::Custom(format!("Failed to write {}: {}", path, e)))?;
+    println!("Listing PSKT written to {path}");
+
+    Ok((inscribed.reveal_id, pskt))
+}
+
+/// Builds the transaction cancelling a listing: it spends `listing_utxo`
+/// into a single output back to `seller`, revealing the `send` op.
+pub fn build_cancel_transaction(
+    keypair: &Keypair,
+    listing_utxo: Utxo,
+    send_script: &[u8],
+    seller: &Address,
+    priority_fee: u64,
+) -> Result<Transaction> {
+    let (cancel_fee, _) = fee_and_change(
+        &[listing_utxo.1.amount],
+        &[],
+        0,
+        reveal_mass(send_script, 0, &[], &pay_to_address_script(seller)),
+        priority_fee,
+    );
+    build_reveal_transaction(
+        keypair,
+        listing_utxo,
+        vec![],
+        send_script,
+        vec![],
+        seller,
+        cancel_fee,
+        0,
+    )
+}
+
+/// Cancels a listing by spending its UTXO back to the seller, which reveals
+/// the `send` op with the seller as recipient and unlocks the tokens.
+///
+/// Cancel file layout, one value per line:
+/// private key, ticker, listing (list reveal) transaction id, optional
+/// priority fee (KAS).
+pub async fn cancel_krc20_listing(
+    client: &KaspaRpcClient,
+    network: NetworkType,
+    cancel_args: Vec<String>,
+) -> Result<TransactionId> {
+    if cancel_args.len() < 3 {
+        return Err(Error::Custom(format!(
+            "Expected at least 3 lines of cancel arguments, found {}",
+            cancel_args.len()
+        )));
+    }
+
+    let keypair = keypair_from_hex(&cancel_args[0])?;
+    let send_script = send_script(&keypair, &cancel_args[1])?;
+    let listing_id: TransactionId = cancel_args[2]
+        .trim()
+        .parse()
+        .map_err(|e| Error::Custom(format!("Invalid listing transaction id: {}", e)))?;
+    let fee = parse_kaspa(
+        cancel_args.get(3).map(String::as_str).unwrap_or(""),
+        "priority fee",
+    )?;
+
+    let prefix = Prefix::from(network);
+    let (x_only_public_key, _) = keypair.x_only_public_key();
+    let address = Address::new(prefix, Version::PubKey, &x_only_public_key.serialize());
+    let listing_address = p2sh_address(&send_script, prefix)?;
+
+    let listing_outpoint = TransactionOutpoint::new(listing_id, 0);
+    let listing_utxo = fetch_utxos(client, &listing_address)
+        .await?
+        .into_iter()
+        .find(|(outpoint, _)| *outpoint == listing_outpoint)
+        .ok_or_else(|| {
+            Error::Custom(format!(
+                "No listing UTXO {} at {} (already sold or cancelled?)",
+                listing_outpoint, listing_address
+            ))
+        })?;
+
+    let cancel_tx = build_cancel_transaction(&keypair, listing_utxo, &send_script, &address, fee)?;
+    let cancel_id = client
+        .submit_transaction(RpcTransaction::from(&cancel_tx), false)
+        .await?;
+    Ok(cancel_id)
+}
+
+/// A seller's listing decoded from a PSKT: the finalized listing input and
+/// the payment output it was signed with.
+#[derive(Debug, Clone)]
+pub struct Listing {
+    pub utxo: Utxo,
+    pub sequence: u64,
+    pub signature_script: Vec<u8>,
+    pub redeem_script: Vec<u8>,
+    pub payment: TransactionOutput,
+}
+
+/// Decodes a serialized listing PSKT as produced by [`build_listing_pskt`] and
+/// checks its shape: one finalized input signed SIGHASH_SINGLE|ANYONECANPAY
+/// spending a P2SH UTXO, and one payment output. The seller's signature must
+/// verify against that output, so a PSKT whose price or payment address was
+/// changed after signing is rejected.
+pub fn decode_listing_pskt(pskt: &str) -> Result<Listing> {
+    let bundle = Bundle::deserialize(pskt.trim())
+        .map_err(|e| Error::Custom(format!("Failed to parse PSKT: {}", e)))?;
+    let inner = match bundle.0.as_slice() {
+        [inner] => inner.clone(),
+        _ => {
+            return Err(Error::Custom(
+                "Listing PSKT must contain exactly one transaction".to_string(),
+            ))
+        }
+    };
+    let (input, output) = match (inner.inputs.as_slice(), inner.outputs.as_slice()) {
+        ([input], [output]) => (input.clone(), output.clone()),
+        _ => {
+            return Err(Error::Custom(
+                "Listing PSKT must have exactly one input and one output".to_string(),
+            ))
+        }
+    };
+    if input.sighash_type.to_u8() != listing_sighash_type()?.to_u8() {
+        return Err(Error::Custom(
+            "Listing input is not signed with SIGHASH_SINGLE|ANYONECANPAY".to_string(),
+        ));
+    }
+    let entry = input
+        .utxo_entry
+        .ok_or_else(|| Error::Custom("Listing input has no UTXO entry".to_string()))?;
+    let signature_script = input
+        .final_script_sig
+        .ok_or_else(|| Error::Custom("Listing input is not signed".to_string()))?;
+    let redeem_script = input
+        .redeem_script
+        .ok_or_else(|| Error::Custom("Listing input has no redeem script".to_string()))?;
+    if pay_to_script_hash_script(&redeem_script) != entry.script_public_key {
+        return Err(Error::Custom(
+            "Listing redeem script does not match the listed UTXO".to_string(),
+        ));
+    }
+
+    let listing = Listing {
+        utxo: (input.previous_outpoint, entry),
+        sequence: input.sequence.unwrap_or_default(),
+        signature_script,
+        redeem_script,
+        payment: TransactionOutput::new(output.amount, output.script_public_key),
+    };
+    check_listing_signature(&listing)?;
+    Ok(listing)
+}
+
+/// Verifies the seller's Schnorr signature on the listing input, made with
+/// the key of the listing's redeem script over the listing input and the
+/// payment output under SIGHASH_SINGLE|ANYONECANPAY.
+pub fn check_listing_signature(listing: &Listing) -> Result<()> {
+    let sighash_type = listing_sighash_type()?;
+    let signature = match parse_ops(&listing.signature_script)?.as_slice() {
+        [(_, signature), (_, redeem_script)]
+            if signature.len() == 65 && *redeem_script == listing.redeem_script =>
+        {
+            signature.clone()
+        }
+        _ => {
+            return Err(Error::Custom(
+                "Listing input must push a signature and the redeem script".to_string(),
+            ))
+        }
+    };
+    if signature[64] != sighash_type.to_u8() {
+        return Err(Error::Custom(
+            "Listing input is not signed with SIGHASH_SINGLE|ANYONECANPAY".to_string(),
+        ));
+    }
+    let public_key = parse_envelope(&listing.redeem_script)
+        .and_then(|envelope| XOnlyPublicKey::from_slice(&envelope.public_key).ok())
+        .ok_or_else(|| {
+            Error::Custom("Listing redeem script has no Schnorr public key".to_string())
+        })?;
+
+    let (outpoint, entry) = listing.utxo.clone();
+    let tx = Transaction::new(
+        0,
+        vec![TransactionInput::new(outpoint, vec![], listing.sequence, 1)],
+        vec![listing.payment.clone()],
+        0,
+        SUBNETWORK_ID_NATIVE,
+        0,
+        vec![],
+    );
+    let signable = SignableTransaction::with_entries(tx, vec![entry]);
+    let sig_hash = calc_schnorr_signature_hash(
+        &signable.as_verifiable(),
+        0,
+        sighash_type,
+        &mut SigHashReusedValues::new(),
+    );
+    let msg = Message::from_digest_slice(sig_hash.as_bytes().as_slice())
+        .map_err(|e| Error::Custom(format!("Failed to create sighash message: {}", e)))?;
+    let signature = schnorr::Signature::from_slice(&signature[..64])
+        .map_err(|e| Error::Custom(format!("Invalid listing signature: {}", e)))?;
+    Secp256k1::verification_only()
+        .verify_schnorr(&signature, &msg, &public_key)
+        .map_err(|_| {
+            Error::Custom(
+                "Listing signature does not match its input and payment output".to_string(),
+            )
+        })
+}
+
+/// Checks that `listing` locks a `send` of `tick`, i.e. that its redeem script
+/// is the seller's send script for that ticker.
+fn check_listing_ticker(listing: &Listing, tick: &str) -> Result<()> {
+    // OP_DATA_32 <x-only public key> starts every envelope we build
+    let x_only_public_key = match listing.redeem_script.first() {
+        Some(0x20) if listing.redeem_script.len() > 33 => &listing.redeem_script[1..33],
+        _ => {
+            return Err(Error::Custom(
+                "Listing redeem script is not a KRC-20 envelope".to_string(),
+            ))
+        }
+    };
+    let data = Krc20Op::Send(Send::new(tick)?).to_json()?;
+    if build_redeem_script(x_only_public_key, data.as_bytes())? != listing.redeem_script {
+        return Err(Error::Custom(format!("Listing is not a send of {}", tick)));
+    }
+    Ok(())
+}
+
+/// Builds the unsigned buy transaction for `listing` spending `utxos`, with
+/// placeholder buyer signatures so its mass matches the signed transaction.
+fn unsigned_buy_transaction(listing: &Listing, utxos: &[Utxo], buyer: &Address) -> Transaction {
+    let (listing_outpoint, _) = listing.utxo;
+    let inputs = std::iter::once(TransactionInput::new(
+        listing_outpoint,
+        listing.signature_script.clone(),
+        listing.sequence,
+        1,
+    ))
+    .chain(utxos.iter().enumerate().map(|(sequence, (outpoint, _))| {
+        TransactionInput::new(*outpoint, vec![0; 66], sequence as u64 + 1, 1)
+    }))
+    .collect();
+    let outputs = vec![
+        listing.payment.clone(),
+        TransactionOutput::new(0, pay_to_address_script(buyer)),
+    ];
+    Transaction::new(0, inputs, outputs, 0, SUBNETWORK_ID_NATIVE, 0, vec![])
+}
+
+/// Builds the buy transaction for `listing`: input 0 and output 0 are the
+/// seller's, followed by as few of the buyer's `utxos` as cover the price and
+/// fee, and a single output returning the listing UTXO value plus change to
+/// `buyer`. Only the buyer inputs are signed.
+pub fn build_buy_transaction(
+    keypair: &Keypair,
+    listing: &Listing,
+    utxos: Vec<Utxo>,
+    buyer: &Address,
+    priority_fee: u64,
+) -> Result<Transaction> {
+    let listing_amount = listing.utxo.1.amount;
+    let mut selected: Vec<Utxo> = Vec::new();
+    let mut required = listing.payment.value;
+    for utxo in utxos {
+        selected.push(utxo);
+        let mut tx = unsigned_buy_transaction(listing, &selected, buyer);
+        let input_values: Vec<u64> = std::iter::once(listing_amount)
+            .chain(selected.iter().map(|(_, entry)| entry.amount))
+            .collect();
+        let (fee, change) = fee_and_change(
+            &input_values,
+            &[listing.payment.value],
+            0,
+            compute_mass(&tx),
+            priority_fee,
+        );
+        required = listing.payment.value + fee;
+        if change == 0 {
+            continue;
+        }
+
+        tx.outputs[1].value = change;
+        tx.finalize();
+        let entries = std::iter::once(listing.utxo.1.clone())
+            .chain(selected.into_iter().map(|(_, entry)| entry))
+            .collect();
+        let mut signable = SignableTransaction::with_entries(tx, entries);
+        let mut reused_values = SigHashReusedValues::new();
+        for index in 1..signable.tx.inputs.len() {
+            signable.tx.inputs[index].signature_script =
+                schnorr_signature(&signable, index, keypair, SIG_HASH_ALL, &mut reused_values)?;
+        }
+        return Ok(signable.tx);
+    }
+
+    Err(Error::Custom(format!(
+        "Insufficient funds to buy listing: need {} KAS",
+        sompi_to_kaspa_string(required.saturating_sub(listing_amount))
+    )))
+}
+
+/// Buys a listing from a seller's PSKT. Before anything is signed the
+/// listing is checked: the seller's signature must cover its payment output,
+/// it must lock a `send` of the expected ticker, its
+/// UTXO must still be unspent, its price must match the advertised price and,
+/// when an indexer is available, its `list` op must have been accepted.
+///
+/// Buy file layout, one value per line:
+/// private key, path to the listing PSKT file, ticker, advertised price (KAS),
+/// optional priority fee (KAS).
+pub async fn buy_krc20_listing(
+    client: &KaspaRpcClient,
+    indexer: Option<&IndexerClient>,
+    network: NetworkType,
+    buy_args: Vec<String>,
+) -> Result<TransactionId> {
+    if buy_args.len() < 4 {
+        return Err(Error::Custom(format!(
+            "Expected at least 4 lines of buy arguments, found {}",
+            buy_args.len()
+        )));
+    }
+
+    let keypair = keypair_from_hex(&buy_args[0])?;
+    let pskt_path = buy_args[1].trim();
+    let pskt = fs::read_to_string(pskt_path)
+        .map_err(|e| Error::Custom(format!("Failed to read {}: {}", pskt_path, e)))?;
+    let tick = buy_args[2].trim().to_lowercase();
+    let price = parse_kaspa(&buy_args[3], "price")?;
+    let fee = parse_kaspa(
+        buy_args.get(4).map(String::as_str).unwrap_or(""),
+        "priority fee",
+    )?;
+
+    let prefix = Prefix::from(network);
+    let (x_only_public_key, _) = keypair.x_only_public_key();
+    let address = Address::new(prefix, Version::PubKey, &x_only_public_key.serialize());
+    println!("Determining address: {address}");
+
+    let listing = decode_listing_pskt(&pskt)?;
+    check_listing_ticker(&listing, &tick)?;
+    if listing.payment.value != price {
+        return Err(Error::Custom(format!(
+            "Listing price is {} KAS, expected {} KAS",
+            sompi_to_kaspa_string(listing.payment.value),
+            sompi_to_kaspa_string(price)
+        )));
+    }
+
+    let (listing_outpoint, listing_entry) = &listing.utxo;
+    let listing_address = p2sh_address(&listing.redeem_script, prefix)?;
+    let unspent = fetch_utxos(client, &listing_address)
+        .await?
+        .into_iter()
+        .any(|(outpoint, entry)| {
+            outpoint == *listing_outpoint
+                && entry.amount == listing_entry.amount
+                && entry.script_public_key == listing_entry.script_public_key
+        });
+    if !unspent {
+        return Err(Error::Custom(format!(
+            "Listing UTXO {} is spent or unknown (already sold or cancelled?)",
+            listing_outpoint
+        )));
+    }
+
+    if let Some(indexer) = indexer {
+        let op = indexer
+            .get_op(&listing_outpoint.transaction_id.to_string())
+            .await?;
+        match op {
+            Some(op)
+                if op.op == "list"
+                    && op.op_accept == "1"
+                    && op.tick.eq_ignore_ascii_case(&tick) =>
+            {
+                println!("Listing of {} {} accepted by the indexer", op.amt, op.tick);
+            }
+            _ => {
+                return Err(Error::Custom(format!(
+                    "Listing {} is not an accepted list of {}",
+                    listing_outpoint.transaction_id, tick
+                )))
+            }
+        }
+    }
+
+    let mut utxos = fetch_utxos(client, &address).await?;
+    if utxos.is_empty() {
+        return Err(Error::Custom(format!(
+            "No UTXOs available for address: {address}"
+        )));
+    }
+    utxos.sort_by_key(|(_, entry)| std::cmp::Reverse(entry.amount));
+
+    let buy_tx = build_buy_transaction(&keypair, &listing, utxos, &address, fee)?;
+    println!(
+        "Buying listing for {} KAS",
+        sompi_to_kaspa_string(listing.payment.value)
+    );
+    let buy_id = client
+        .submit_transaction(RpcTransaction::from(&buy_tx), false)
+        .await?;
+    Ok(buy_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decoder::decode_inscription;
+    use kaspa_consensus_core::tx::ScriptPublicKey;
+    use secp256k1::Secp256k1;
+
+    const KAS: u64 = 100_000_000;
+
+    fn keypair(seed: u8) -> Keypair {
+        Keypair::from_seckey_slice(&Secp256k1::new(), &[seed; 32]).unwrap()
+    }
+
+    fn address(keypair: &Keypair) -> Address {
+        let (x_only_public_key, _) = keypair.x_only_public_key();
+        Address::new(
+            Prefix::Mainnet,
+            Version::PubKey,
+            &x_only_public_key.serialize(),
+        )
+    }
+
+    fn utxo(id: u64, value: u64, script_public_key: ScriptPublicKey) -> Utxo {
+        (
+            TransactionOutpoint::new(TransactionId::from_u64_word(id), 0),
+            UtxoEntry::new(value, script_public_key, 0, false),
+        )
+    }
+
+    /// A listing of `test` by `seller` for `price` and its decoded PSKT.
+    fn listing(seller: &Keypair, price: u64) -> (String, Listing) {
+        let send_script = send_script(seller, "test").unwrap();
+        let listing_utxo = utxo(1, LISTING_AMOUNT, pay_to_script_hash_script(&send_script));
+        let pskt = build_listing_pskt(seller, listing_utxo, &send_script, price, &address(seller))
+            .unwrap();
+        let listing = decode_listing_pskt(&pskt).unwrap();
+        (pskt, listing)
+    }
+
+    #[test]
+    fn round_trips_listing_pskts() {
+        let seller = keypair(1);
+        let (_, listing) = listing(&seller, 5 * KAS);
+        let send_script = send_script(&seller, "test").unwrap();
+        let (outpoint, entry) = &listing.utxo;
+        assert_eq!(
+            *outpoint,
+            TransactionOutpoint::new(TransactionId::from_u64_word(1), 0)
+        );
+        assert_eq!(entry.amount, LISTING_AMOUNT);
+        assert_eq!(
+            entry.script_public_key,
+            pay_to_script_hash_script(&send_script)
+        );
+        assert_eq!(listing.redeem_script, send_script);
+        assert_eq!(
+            listing.payment,
+            TransactionOutput::new(5 * KAS, pay_to_address_script(&address(&seller)))
+        );
+
+        // The signature push ends with the SIGHASH_SINGLE|ANYONECANPAY byte
+        let sighash_type = listing_sighash_type().unwrap().to_u8();
+        assert_eq!(sighash_type, 0x84);
+        assert_eq!(listing.signature_script[0], 65);
+        assert_eq!(listing.signature_script[65], sighash_type);
+
+        assert!(check_listing_ticker(&listing, "test").is_ok());
+        assert!(check_listing_ticker(&listing, "other").is_err());
+        assert!(decode_listing_pskt("not a pskt").is_err());
+    }
+
+    /// Re-serializes `pskt` with its payment output changed by `tamper`.
+    fn tampered(pskt: &str, tamper: impl Fn(&mut TransactionOutput)) -> String {
+        let mut bundle = Bundle::deserialize(pskt).unwrap();
+        let output = &mut bundle.0[0].outputs[0];
+        let mut payment = TransactionOutput::new(output.amount, output.script_public_key.clone());
+        tamper(&mut payment);
+        output.amount = payment.value;
+        output.script_public_key = payment.script_public_key;
+        bundle.serialize().unwrap()
+    }
+
+    #[test]
+    fn rejects_listings_changed_after_signing() {
+        let seller = keypair(1);
+        let (_, mut forged) = listing(&keypair(2), 5 * KAS);
+        let (pskt, listing) = listing(&seller, 5 * KAS);
+        assert!(check_listing_signature(&listing).is_ok());
+
+        let cheaper = tampered(&pskt, |payment| payment.value = KAS);
+        let error = decode_listing_pskt(&cheaper).unwrap_err().to_string();
+        assert!(error.contains("signature does not match"), "{error}");
+
+        let thief = pay_to_address_script(&address(&keypair(3)));
+        let redirected = tampered(&pskt, |payment| payment.script_public_key = thief.clone());
+        assert!(decode_listing_pskt(&redirected).is_err());
+
+        // A signature by another key over the same listing is refused too
+        forged.signature_script = pay_to_script_hash_signature_script(
+            listing.redeem_script.clone(),
+            forged.signature_script[..66].to_vec(),
+        )
+        .unwrap();
+        forged.redeem_script = listing.redeem_script.clone();
+        forged.utxo = listing.utxo.clone();
+        let error = check_listing_signature(&forged).unwrap_err().to_string();
+        assert!(error.contains("signature does not match"), "{error}");
+    }
+
+    #[test]
+    fn buy_keeps_the_seller_input_and_payment_first() {
+        let seller = keypair(1);
+        let buyer = keypair(2);
+        let (_, listing) = listing(&seller, 5 * KAS);
+        let buyer_script = pay_to_address_script(&address(&buyer));
+
+        let tx = build_buy_transaction(
+            &buyer,
+            &listing,
+            vec![utxo(2, 10 * KAS, buyer_script.clone())],
+            &address(&buyer),
+            0,
+        )
+        .unwrap();
+        assert_eq!(tx.inputs[0].previous_outpoint, listing.utxo.0);
+        assert_eq!(tx.inputs[0].signature_script, listing.signature_script);
+        assert_eq!(tx.outputs[0], listing.payment);
+        assert_eq!(tx.outputs[1].script_public_key, buyer_script);
+        let fee = LISTING_AMOUNT + 5 * KAS - tx.outputs[1].value;
+        assert!(fee > 0 && fee < KAS / 100, "{fee}");
+
+        let error = build_buy_transaction(
+            &buyer,
+            &listing,
+            vec![utxo(2, 4 * KAS, buyer_script)],
+            &address(&buyer),
+            0,
+        )
+        .unwrap_err()
+        .to_string();
+        assert!(error.contains("Insufficient funds"), "{error}");
+    }
+
+    #[test]
+    fn cancel_reveals_the_send_back_to_the_seller() {
+        let seller = keypair(1);
+        let send_script = send_script(&seller, "test").unwrap();
+        let listing_utxo = utxo(1, LISTING_AMOUNT, pay_to_script_hash_script(&send_script));
+        let tx =
+            build_cancel_transaction(&seller, listing_utxo, &send_script, &address(&seller), 0)
+                .unwrap();
+        assert_eq!(tx.outputs.len(), 1);
+        assert_eq!(
+            tx.outputs[0].script_public_key,
+            pay_to_address_script(&address(&seller))
+        );
+        let inscription = decode_inscription(&tx).unwrap();
+        assert_eq!(inscription.op, Krc20Op::Send(Send::new("test").unwrap()));
+        assert_eq!(inscription.signer(Prefix::Mainnet), address(&seller));
+    }
+}
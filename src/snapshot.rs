@@ -0,0 +1,263 @@
+use kaspa_rpc_core::{api::rpc::RpcApi, RpcHash};
+use kaspa_wrpc_client::{error::Error, result::Result, KaspaRpcClient};
+use num_bigint::BigInt;
+use serde::Serialize;
+
+use crate::history::csv_field;
+use crate::indexer::{parse_number, IndexerClient};
+use crate::krc20::{format_amount, parse_decimals, validate_ticker};
+use crate::local_indexer::LocalIndex;
+
+use std::fs;
+
+/// Decimal places of a holder's share of supply, in percent.
+const SHARE_DECIMALS: u8 = 6;
+
+/// A holder of a token at the snapshot.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SnapshotHolder {
+    pub address: String,
+    /// Balance in token units, including amounts locked in listings.
+    pub balance: String,
+    /// Share of the minted supply, in percent.
+    pub share_percent: String,
+}
+
+/// Holder list of a token at a DAA score.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Snapshot {
+    pub tick: String,
+    pub daa_score: u64,
+    /// `indexer` or `local`.
+    pub source: String,
+    /// Minted supply in token units.
+    pub supply: String,
+    pub holders: Vec<SnapshotHolder>,
+}
+
+impl Snapshot {
+    /// Builds a snapshot from atomic `(address, balance)` pairs, largest
+    /// holders first.
+    fn new(
+        tick: &str,
+        daa_score: u64,
+        source: &str,
+        supply: &BigInt,
+        dec: u8,
+        mut holders: Vec<(String, BigInt)>,
+    ) -> Snapshot {
+        holders.retain(|(_, balance)| *balance > BigInt::from(0));
+        holders.sort_by(|(a_address, a), (b_address, b)| b.cmp(a).then(a_address.cmp(b_address)));
+        let scale = BigInt::from(100) * BigInt::from(10u64.pow(SHARE_DECIMALS as u32));
+        let holders = holders
+            .into_iter()
+            .map(|(address, balance)| {
+                let share = if *supply > BigInt::from(0) {
+                    &balance * &scale / supply
+                } else {
+                    BigInt::from(0)
+                };
+                SnapshotHolder {
+                    address,
+                    balance: format_amount(&balance, dec),
+                    share_percent: format_amount(&share, SHARE_DECIMALS),
+                }
+            })
+            .collect();
+        Snapshot {
+            tick: tick.to_string(),
+            daa_score,
+            source: source.to_string(),
+            supply: format_amount(supply, dec),
+            holders,
+        }
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        serde_json::to_string_pretty(self)
+            .map_err(|e| Error::Custom(format!("Failed to serialize snapshot: {}", e)))
+    }
+
+    /// Returns one `address,balance,share_percent` row per holder after a
+    /// header row.
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from("address,balance,share_percent\n");
+        for holder in &self.holders {
+            let row: Vec<String> = [&holder.address, &holder.balance, &holder.share_percent]
+                .iter()
+                .map(|field| csv_field(field))
+                .collect();
+            csv.push_str(&row.join(","));
+            csv.push('\n');
+        }
+        csv
+    }
+}
+
+/// Builds the snapshot from the indexer at the node's current virtual DAA
+/// score. The indexer only lists the largest holders, so this fails unless
+/// the list holds every holder.
+async fn indexer_snapshot(
+    client: &KaspaRpcClient,
+    indexer: &IndexerClient,
+    tick: &str,
+) -> Result<Snapshot> {
+    let daa_score = client.get_block_dag_info().await?.virtual_daa_score;
+    let info = indexer
+        .get_token_holders(tick)
+        .await?
+        .ok_or_else(|| Error::Custom(format!("Token {} not found", tick)))?;
+    let dec = parse_decimals(&info.dec)?;
+    let supply = parse_number(&info.minted, "minted")?;
+    let holder_total: usize = info
+        .holder_total
+        .trim()
+        .parse()
+        .map_err(|e| Error::Custom(format!("Invalid holder total: {}", e)))?;
+    if info.holder.len() < holder_total {
+        return Err(Error::Custom(format!(
+            "The indexer lists {} of {} holders of {}. Give a local index store path for the full list",
+            info.holder.len(),
+            holder_total,
+            tick
+        )));
+    }
+    let holders = info
+        .holder
+        .into_iter()
+        .map(|holder| Ok((holder.address, parse_number(&holder.amount, "amount")?)))
+        .collect::<Result<Vec<_>>>()?;
+    Ok(Snapshot::new(
+        tick, daa_score, "indexer", &supply, dec, holders,
+    ))
+}
+
+/// Builds the snapshot from the local index store at `path`, at the DAA score
+/// of its last applied chain block.
+async fn local_snapshot(client: &KaspaRpcClient, path: &str, tick: &str) -> Result<Snapshot> {
+    let index = LocalIndex::open(path)?;
+    let sink: RpcHash = index
+        .sink()
+        .ok_or_else(|| Error::Custom(format!("Local index {} is empty", path)))?
+        .parse()
+        .map_err(|e| Error::Custom(format!("Invalid stored sink: {}", e)))?;
+    let daa_score = client.get_block(sink, false).await?.header.daa_score;
+    let token = index
+        .tokens
+        .get(tick)
+        .ok_or_else(|| {
+            Error::Custom(format!(
+                "Token {} not found in local index. It only tracks tokens deployed after its start block",
+                tick
+            ))
+        })?;
+    let holders = index
+        .holders(tick)?
+        .into_iter()
+        .map(|(address, holding)| (address, holding.balance + holding.locked))
+        .collect();
+    Ok(Snapshot::new(
+        tick,
+        daa_score,
+        "local",
+        &token.minted,
+        token.dec,
+        holders,
+    ))
+}
+
+/// Exports every holder of a KRC-20 token with their balance and share of the
+/// minted supply. The holder list comes from the local index if a store path
+/// is given, and from the indexer otherwise, which only works for tokens with
+/// few enough holders for the indexer to list them all. The output is JSON if
+/// its path ends in `.json`, and CSV otherwise.
+///
+/// Snapshot file layout, one value per line:
+/// ticker, output path, optional local index store path.
+pub async fn export_holder_snapshot(
+    client: &KaspaRpcClient,
+    indexer: Option<&IndexerClient>,
+    snapshot_args: Vec<String>,
+) -> Result<Snapshot> {
+    if snapshot_args.len() < 2 {
+        return Err(Error::Custom(format!(
+            "Expected at least 2 lines of snapshot arguments, found {}",
+            snapshot_args.len()
+        )));
+    }
+
+    let tick = validate_ticker(&snapshot_args[0])?;
+    let output = snapshot_args[1].trim();
+    if output.is_empty() {
+        return Err(Error::Custom("Snapshot output path is empty".to_string()));
+    }
+    let local_path = snapshot_args
+        .get(2)
+        .map(|path| path.trim())
+        .filter(|path| !path.is_empty());
+
+    let snapshot = match (local_path, indexer) {
+        (Some(path), _) => local_snapshot(client, path, &tick).await?,
+        (None, Some(indexer)) => indexer_snapshot(client, indexer, &tick).await?,
+        (None, None) => {
+            return Err(Error::Custom(
+                "No KRC-20 indexer available. Use --indexer or give a local index store path"
+                    .to_string(),
+            ))
+        }
+    };
+
+    let contents = if output.to_lowercase().ends_with(".json") {
+        snapshot.to_json()?
+    } else {
+        snapshot.to_csv()
+    };
+    fs::write(output, contents)
+        .map_err(|e| Error::Custom(format!("Failed to write {}: {}", output, e)))?;
+    Ok(snapshot)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot(holders: Vec<(&str, u64)>) -> Snapshot {
+        let holders = holders
+            .into_iter()
+            .map(|(address, balance)| (address.to_string(), BigInt::from(balance)))
+            .collect();
+        Snapshot::new("test", 7, "local", &BigInt::from(400u64), 2, holders)
+    }
+
+    #[test]
+    fn orders_holders_and_computes_shares() {
+        let snapshot = snapshot(vec![("b", 100), ("c", 0), ("a", 100), ("d", 200)]);
+        let holders: Vec<(&str, &str, &str)> = snapshot
+            .holders
+            .iter()
+            .map(|holder| {
+                (
+                    holder.address.as_str(),
+                    holder.balance.as_str(),
+                    holder.share_percent.as_str(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            holders,
+            [("d", "2", "50"), ("a", "1", "25"), ("b", "1", "25")]
+        );
+        assert_eq!(snapshot.supply, "4");
+    }
+
+    #[test]
+    fn quotes_csv_fields() {
+        let csv = snapshot(vec![("kaspa:qz", 150), ("odd,\"address\"", 50)]).to_csv();
+        assert_eq!(
+            csv,
+            "address,balance,share_percent\nkaspa:qz,1.5,37.5\n\"odd,\"\"address\"\"\",0.5,12.5\n"
+        );
+    }
+}
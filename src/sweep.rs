@@ -0,0 +1,451 @@
+use kaspa_addresses::{Address, Prefix, Version};
+use kaspa_consensus_core::tx::TransactionId;
+use kaspa_wallet_core::utils::sompi_to_kaspa_string;
+use kaspa_wrpc_client::{error::Error, prelude::NetworkType, result::Result, KaspaRpcClient};
+use num_bigint::BigInt;
+use secp256k1::Keypair;
+use serde::{Deserialize, Serialize};
+
+use crate::client::{
+    fetch_utxos, inscribe, keypair_from_hex, parse_kaspa, pay_in_chunks, Fees, Utxo,
+};
+use crate::envelope::Content;
+use crate::fees::{CostBreakdown, FeeSchedule};
+use crate::indexer::{parse_number, IndexerClient, OpInfo};
+use crate::krc20::{
+    as_string, format_amount, parse_decimals, validate_address, validate_ticker, Krc20Op, Transfer,
+};
+use crate::wallet::build_from_imported_mnemonic;
+
+use std::collections::HashMap;
+use std::fs::{self, OpenOptions};
+use std::io::prelude::*;
+use std::path::{Path, PathBuf};
+
+/// Default location of the sweep progress log.
+pub const SWEEP_FILE: &str = "krc20-sweep.jsonl";
+
+/// A child whose balance was swept.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SweepEntry {
+    pub tick: String,
+    /// Derivation index of the child.
+    pub index: u32,
+    pub address: String,
+    /// Swept amount in atomic units.
+    #[serde(with = "as_string")]
+    pub amount: BigInt,
+    pub target: String,
+    pub reveal_id: String,
+}
+
+/// A JSON lines file with one entry per swept child, so an interrupted sweep
+/// can be run again without sweeping a child twice.
+pub struct SweepLog {
+    path: PathBuf,
+}
+
+impl SweepLog {
+    pub fn new<P: AsRef<Path>>(path: P) -> SweepLog {
+        SweepLog {
+            path: path.as_ref().to_path_buf(),
+        }
+    }
+
+    pub fn entries(&self) -> Result<Vec<SweepEntry>> {
+        let contents = match fs::read_to_string(&self.path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(e) => return Err(Error::Custom(format!("Failed to read sweep log: {}", e))),
+        };
+        contents
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| {
+                serde_json::from_str(line)
+                    .map_err(|e| Error::Custom(format!("Failed to parse sweep entry: {}", e)))
+            })
+            .collect()
+    }
+
+    /// The latest entry of each child swept of `tick`, by address.
+    pub fn swept(&self, tick: &str) -> Result<HashMap<String, SweepEntry>> {
+        Ok(self
+            .entries()?
+            .into_iter()
+            .filter(|entry| entry.tick == tick)
+            .map(|entry| (entry.address.clone(), entry))
+            .collect())
+    }
+
+    /// Appends an entry and flushes it to disk.
+    pub fn append(&self, entry: &SweepEntry) -> Result<()> {
+        let line = serde_json::to_string(entry)
+            .map_err(|e| Error::Custom(format!("Failed to serialize sweep entry: {}", e)))?;
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .map_err(|e| Error::Custom(format!("Failed to open sweep log: {}", e)))?;
+        writeln!(file, "{}", line)
+            .and_then(|_| file.sync_all())
+            .map_err(|e| Error::Custom(format!("Failed to write sweep log: {}", e)))
+    }
+}
+
+/// Outcome of the sweep of one child.
+#[derive(Debug, Clone)]
+pub struct SweptChild {
+    pub index: u32,
+    pub address: Address,
+    /// Balance in token units.
+    pub amount: String,
+    /// The transfer's reveal id, or why it failed.
+    pub result: std::result::Result<TransactionId, String>,
+}
+
+/// Result of a sweep run.
+#[derive(Debug, Clone)]
+pub struct SweepResult {
+    /// Transactions topping up children short of KAS, if any were.
+    pub top_up_ids: Vec<TransactionId>,
+    pub children: Vec<SweptChild>,
+    /// Children skipped because their transfer from an earlier run is not
+    /// settled by the indexer yet.
+    pub in_flight: usize,
+}
+
+/// What to do with a child whose sweep is in the log.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Resume {
+    /// The logged transfer was accepted, so the balance is what was received
+    /// since and can be swept.
+    Sweep,
+    /// The logged transfer was rejected, so the balance is still there.
+    Retry,
+    /// The indexer has not settled the logged transfer yet.
+    Wait,
+}
+
+impl Resume {
+    /// Decides from the indexer's view of the logged transfer, `None` if it
+    /// is not indexed yet or could not be fetched.
+    fn from_op(op: Option<&OpInfo>) -> Resume {
+        match op.map(|info| info.op_accept.as_str()) {
+            Some("1") => Resume::Sweep,
+            Some("-1") => Resume::Retry,
+            _ => Resume::Wait,
+        }
+    }
+}
+
+/// A child holding tokens to sweep.
+struct Holder {
+    index: u32,
+    keypair: Keypair,
+    address: Address,
+    balance: BigInt,
+    utxos: Vec<Utxo>,
+    /// KAS the child is short of to pay for its transfer.
+    top_up: u64,
+}
+
+/// Transfers the whole balance of a KRC-20 token held by the first `n` child
+/// wallets of a mnemonic to one target address. Children short of KAS for the
+/// transfer fees are topped up by the master wallet in chained transactions
+/// of at most `PAYMENT_CHUNK_SIZE` outputs.
+/// Every sweep is appended to the sweep log. Until the indexer settles a
+/// logged transfer its child is skipped, since its balance still shows the
+/// swept tokens, so an interrupted sweep can simply be run again.
+///
+/// Balances are read from the indexer, so an indexer is required.
+///
+/// Sweep file layout, one value per line:
+/// master private key, mnemonic phrase, child count, ticker, target address
+/// (may be empty, defaults to the master address), priority fee (KAS).
+pub async fn sweep_krc20_tokens(
+    client: &KaspaRpcClient,
+    indexer: &IndexerClient,
+    network: NetworkType,
+    sweep_args: Vec<String>,
+) -> Result<SweepResult> {
+    if sweep_args.len() < 6 {
+        return Err(Error::Custom(format!(
+            "Expected at least 6 lines of sweep arguments, found {}",
+            sweep_args.len()
+        )));
+    }
+
+    let master = keypair_from_hex(&sweep_args[0])?;
+    let phrase = sweep_args[1].trim().to_string();
+    let child_count: u32 = sweep_args[2]
+        .trim()
+        .parse()
+        .map_err(|e| Error::Custom(format!("Invalid child count: {}", e)))?;
+    let tick = validate_ticker(&sweep_args[3])?;
+    let fee = parse_kaspa(&sweep_args[5], "priority fee")?;
+
+    let prefix = Prefix::from(network);
+    let (x_only_public_key, _) = master.x_only_public_key();
+    let master_address = Address::new(prefix, Version::PubKey, &x_only_public_key.serialize());
+    println!("Determining address: {master_address}");
+    let target = match sweep_args[4].trim() {
+        "" => master_address.to_string(),
+        target => validate_address(target, "target")?,
+    };
+
+    let info = indexer
+        .get_token_info(&tick)
+        .await?
+        .ok_or_else(|| Error::Custom(format!("Ticker {} is not deployed", tick)))?;
+    let dec = parse_decimals(&info.dec)?;
+    let schedule = FeeSchedule::load(network)?;
+
+    let log = SweepLog::new(SWEEP_FILE);
+    let swept = log.swept(&tick)?;
+
+    // Find the children holding the token
+    let mut holders = Vec::new();
+    let mut in_flight = 0;
+    for index in 0..child_count {
+        let private_key = build_from_imported_mnemonic(phrase.clone(), Some(index))?;
+        let keypair = keypair_from_hex(&private_key.to_hex())?;
+        let (x_only_public_key, _) = keypair.x_only_public_key();
+        let address = Address::new(prefix, Version::PubKey, &x_only_public_key.serialize());
+        if address.to_string() == target {
+            continue;
+        }
+        if let Some(entry) = swept.get(&address.to_string()) {
+            let op = indexer.get_op(&entry.reveal_id).await.ok().flatten();
+            match Resume::from_op(op.as_ref()) {
+                Resume::Sweep => {}
+                Resume::Retry => println!(
+                    "[child {index}] earlier transfer {} was rejected, sweeping again",
+                    entry.reveal_id
+                ),
+                Resume::Wait => {
+                    in_flight += 1;
+                    continue;
+                }
+            }
+        }
+        let balance = match indexer.get_balance(&address.to_string(), &tick).await? {
+            Some(balance) => parse_number(&balance.balance, "balance")?,
+            None => BigInt::from(0),
+        };
+        if balance <= BigInt::from(0) {
+            continue;
+        }
+        println!(
+            "[child {index}] {address} holds {} {}",
+            format_amount(&balance, dec),
+            tick.to_uppercase()
+        );
+        let utxos = fetch_utxos(client, &address).await?;
+        holders.push(Holder {
+            index,
+            keypair,
+            address,
+            balance,
+            utxos,
+            top_up: 0,
+        });
+    }
+    println!(
+        "{} children to sweep, {in_flight} still settling",
+        holders.len()
+    );
+    if holders.is_empty() {
+        return Ok(SweepResult {
+            top_up_ids: Vec::new(),
+            children: Vec::new(),
+            in_flight,
+        });
+    }
+
+    // Work out what each child is short of. What a transfer pays depends on
+    // the child's inputs, so children are topped up for the most expensive
+    // transfer.
+    let mut short = 0;
+    for holder in holders.iter_mut() {
+        let op = Krc20Op::Transfer(Transfer {
+            tick: Some(tick.clone()),
+            ca: None,
+            amt: holder.balance.clone(),
+            to: target.clone(),
+        });
+        let have: u64 = holder.utxos.iter().map(|(_, entry)| entry.amount).sum();
+        let cost = CostBreakdown::upper_bound(&[], fee, schedule.fee(&op)).required_balance();
+        if have < cost {
+            holder.top_up = cost - have;
+            short += 1;
+        }
+    }
+
+    let top_up_ids = if short > 0 {
+        let utxos = fetch_utxos(client, &master_address).await?;
+        if utxos.is_empty() {
+            return Err(Error::Custom(format!(
+                "No UTXOs available for address: {master_address}"
+            )));
+        }
+        let payments: Vec<(Address, u64)> = holders
+            .iter()
+            .filter(|holder| holder.top_up > 0)
+            .map(|holder| (holder.address.clone(), holder.top_up))
+            .collect();
+        println!("Topping up {short} children");
+        let paid = pay_in_chunks(client, &master, utxos, &payments, &master_address, fee).await?;
+        let total: u64 = paid.utxos.iter().map(|(_, entry)| entry.amount).sum();
+        println!("Top-ups:       {} KAS", sompi_to_kaspa_string(total));
+        println!("Top-up fee:    {} KAS", sompi_to_kaspa_string(paid.fee));
+
+        // Children spend their top-ups straight away, without waiting for
+        // the top-ups to be accepted.
+        for (holder, utxo) in holders
+            .iter_mut()
+            .filter(|holder| holder.top_up > 0)
+            .zip(paid.utxos)
+        {
+            holder.utxos.push(utxo);
+        }
+        paid.ids
+    } else {
+        Vec::new()
+    };
+
+    let mut children = Vec::new();
+    for holder in holders {
+        let op = Krc20Op::Transfer(Transfer {
+            tick: Some(tick.clone()),
+            ca: None,
+            amt: holder.balance.clone(),
+            to: target.clone(),
+        });
+        let fees = Fees {
+            priority_fee: fee,
+            protocol_fee: schedule.fee(&op),
+        };
+        let result = match inscribe(
+            client,
+            &holder.keypair,
+            prefix,
+            holder.utxos,
+            &Content::krc20(&op)?,
+            vec![],
+            fees,
+        )
+        .await
+        {
+            Ok(inscribed) => {
+                println!(
+                    "[child {}] swept to {target}: {}",
+                    holder.index, inscribed.reveal_id
+                );
+                // The transfer is submitted either way, so a log failure
+                // must not stop the other children.
+                if let Err(e) = log.append(&SweepEntry {
+                    tick: tick.clone(),
+                    index: holder.index,
+                    address: holder.address.to_string(),
+                    amount: holder.balance.clone(),
+                    target: target.clone(),
+                    reveal_id: inscribed.reveal_id.to_string(),
+                }) {
+                    eprintln!(
+                        "[child {}] failed to log sweep {}, a rerun may sweep it again: {e}",
+                        holder.index, inscribed.reveal_id
+                    );
+                }
+                Ok(inscribed.reveal_id)
+            }
+            Err(e) => {
+                println!("[child {}] sweep failed: {e}", holder.index);
+                Err(e.to_string())
+            }
+        };
+        children.push(SweptChild {
+            index: holder.index,
+            address: holder.address,
+            amount: format_amount(&holder.balance, dec),
+            result,
+        });
+    }
+
+    Ok(SweepResult {
+        top_up_ids,
+        children,
+        in_flight,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(tick: &str, index: u32, reveal_id: &str) -> SweepEntry {
+        SweepEntry {
+            tick: tick.to_string(),
+            index,
+            address: format!("kaspa:child{index}"),
+            amount: BigInt::from(1_000u64) * BigInt::from(10u64).pow(18),
+            target: "kaspa:target".to_string(),
+            reveal_id: reveal_id.to_string(),
+        }
+    }
+
+    fn op(op_accept: &str) -> OpInfo {
+        OpInfo {
+            op_accept: op_accept.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn resumes_from_the_logged_transfer_status() {
+        assert_eq!(Resume::from_op(Some(&op("1"))), Resume::Sweep);
+        assert_eq!(Resume::from_op(Some(&op("-1"))), Resume::Retry);
+        assert_eq!(Resume::from_op(Some(&op("0"))), Resume::Wait);
+        assert_eq!(Resume::from_op(Some(&op(""))), Resume::Wait);
+        assert_eq!(Resume::from_op(None), Resume::Wait);
+    }
+
+    #[test]
+    fn keeps_the_latest_sweep_of_each_child() {
+        let path = std::env::temp_dir().join(format!("sweep-{}.jsonl", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let log = SweepLog::new(&path);
+        assert!(log.entries().unwrap().is_empty());
+
+        let first = entry("test", 0, "aa");
+        let retry = entry("test", 0, "bb");
+        let other = entry("test", 1, "cc");
+        let other_tick = entry("other", 2, "dd");
+        let appended = [&first, &other, &other_tick, &retry]
+            .into_iter()
+            .try_for_each(|entry| log.append(entry));
+        let entries = log.entries();
+        let swept = log.swept("test");
+        let _ = fs::remove_file(&path);
+        appended.unwrap();
+
+        let entries = entries.unwrap();
+        assert_eq!(entries.len(), 4);
+        assert_eq!(entries[0].amount, first.amount);
+        let swept = swept.unwrap();
+        assert_eq!(swept.len(), 2);
+        assert_eq!(swept["kaspa:child0"].reveal_id, "bb");
+        assert_eq!(swept["kaspa:child1"].reveal_id, "cc");
+        assert!(!swept.contains_key("kaspa:child2"));
+    }
+
+    #[test]
+    fn rejects_corrupt_sweep_logs() {
+        let path = std::env::temp_dir().join(format!("sweep-corrupt-{}.jsonl", std::process::id()));
+        fs::write(&path, "{\"tick\":\"test\"}\n").unwrap();
+        let entries = SweepLog::new(&path).entries();
+        let _ = fs::remove_file(&path);
+        let error = entries.unwrap_err().to_string();
+        assert!(error.contains("Failed to parse sweep entry"), "{error}");
+    }
+}